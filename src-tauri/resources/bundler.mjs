@@ -1,19 +1,133 @@
 #!/usr/bin/env node
 
-import { execFileSync } from 'child_process';
+import { execFile } from 'child_process';
 import { createRequire } from 'module';
+import { createInterface } from 'readline';
 import * as fs from 'fs';
 import * as path from 'path';
 import * as os from 'os';
 
 const CACHE_DIR = process.env.TERRARIUM_CACHE_DIR || path.join(os.homedir(), '.terrarium');
 const NODE_MODULES = path.join(CACHE_DIR, 'node_modules');
-const NETWORK_MARKERS = ['ENOTFOUND', 'ENETUNREACH', 'ETIMEDOUT', 'EAI_AGAIN', 'ECONNREFUSED'];
+const NETWORK_MARKERS = [
+  'ENOTFOUND', 'ENETUNREACH', 'ETIMEDOUT', 'EAI_AGAIN', 'ECONNREFUSED',
+  // pnpm and bun word some connection failures differently
+  'ERR_PNPM_META_FETCH_FAIL', 'ConnectionRefused', 'FailedToOpenSocket',
+];
+// Records which package manager built the cache's node_modules.
+const LAYOUT_MARKER = path.join(CACHE_DIR, '.package-manager');
+// Registry config for installs into the cache. npm, pnpm, yarn 1 and bun all
+// read a .npmrc in the directory they install into.
+const NPMRC = path.join(CACHE_DIR, '.npmrc');
+const NPMRC_HEADER = '# Managed by Terrarium';
+// Written into an isolated environment once its packages are installed;
+// holds the package manager that installed them.
+const ENV_MARKER = '.terrarium-env';
+// The exact version of every package installed into the cache on purpose.
+// Unlike the package manager's own lockfile it survives switching managers
+// and resets, so the cache comes back with the same versions.
+const LOCKFILE = path.join(CACHE_DIR, 'terrarium-lock.json');
+// Keeps npm and pnpm from installing peer dependencies into an isolated
+// environment: peers like react have to resolve from the shared cache, or
+// the bundle ends up with two copies of them. Bun only reads this from the
+// bunfig.toml in the directory it installs into.
+const NO_PEERS_ENV = { npm_config_legacy_peer_deps: 'true', npm_config_auto_install_peers: 'false' };
+const NO_PEERS_BUNFIG = '[install]\npeer = false\n';
+const PUBLIC_REGISTRY_HOST = 'registry.npmjs.org';
+
+// How each supported package manager installs into a directory (the shared
+// cache or an isolated environment). All of them leave a node_modules tree (pnpm via symlinks into its store) that
+// esbuild, isInstalled and the Rust-side cache key read the same way.
+// `failure` matches their own install-error output, which is reported like
+// npm's. `noScripts` is how each one is told to skip install scripts.
+const IGNORE_SCRIPTS_FLAG = { args: ['--ignore-scripts'], env: {} };
+const PACKAGE_MANAGERS = {
+  npm: {
+    args: (packages, dir) => ['install', '--prefix', dir, ...packages],
+    removeArgs: packages => ['uninstall', '--prefix', CACHE_DIR, ...packages],
+    lockfiles: ['package-lock.json'],
+    failure: /npm (ERR!|error)/,
+    noScripts: IGNORE_SCRIPTS_FLAG,
+  },
+  pnpm: {
+    args: packages => ['add', ...packages],
+    removeArgs: packages => ['remove', ...packages],
+    lockfiles: ['pnpm-lock.yaml'],
+    failure: /ERR_PNPM_/,
+    noScripts: IGNORE_SCRIPTS_FLAG,
+  },
+  yarn: {
+    args: packages => ['add', ...packages],
+    removeArgs: packages => ['remove', ...packages],
+    lockfiles: ['yarn.lock'],
+    failure: /^error |YN\d{4}/m,
+    // Yarn 2+ rejects --ignore-scripts on `add`; both versions read these.
+    noScripts: { args: [], env: { YARN_ENABLE_SCRIPTS: 'false', npm_config_ignore_scripts: 'true' } },
+  },
+  bun: {
+    args: packages => ['add', ...packages],
+    removeArgs: packages => ['remove', ...packages],
+    lockfiles: ['bun.lockb', 'bun.lock'],
+    failure: /^error: /m,
+    noScripts: IGNORE_SCRIPTS_FLAG,
+  },
+};
+const ALL_LOCKFILES = Object.values(PACKAGE_MANAGERS).flatMap(pm => pm.lockfiles);
+// Installed up front and kept by --prune; --reseed starts over with just these.
+const BASE_PACKAGES = [
+  'react', 'react-dom', 'esbuild', 'postcss', 'tailwindcss@3',
+  // Common dependencies used in Claude-generated artifacts
+  'lucide-react',
+  'recharts',
+  'date-fns',
+  'framer-motion',
+  'clsx',
+  'tailwind-merge',
+  'class-variance-authority',
+];
+// Packages whose install scripts always run: esbuild's postinstall sets up
+// its native binary. Everything else installs with --ignore-scripts unless
+// settings.json lists it in `trusted_packages`.
+const ALWAYS_TRUSTED = ['esbuild'];
+// Held (as a directory, which is created atomically) by whichever process is
+// installing into the shared cache. Concurrent installs corrupt node_modules.
+const INSTALL_LOCK = path.join(CACHE_DIR, '.install.lock');
+const LOCK_POLL_MS = 200;
+// The holder rewrites the lock's pid file this often, so a lock nobody has
+// touched for LOCK_STALE_MS is left over from a crash.
+const LOCK_TOUCH_MS = 60 * 1000;
+const LOCK_STALE_MS = 10 * 60 * 1000;
 
 function progress(phase, message) {
   console.error(JSON.stringify({ progress: true, phase, message }));
 }
 
+// Loaded once per process so the daemon only pays the esbuild import cost on
+// its first request.
+let esbuildModule = null;
+let basePackagesReady = false;
+
+async function loadEsbuild() {
+  if (!esbuildModule) {
+    esbuildModule = await import(path.join(NODE_MODULES, 'esbuild', 'lib', 'main.js'));
+  }
+  return esbuildModule;
+}
+
+function runFile(command, args, options) {
+  return new Promise((resolve, reject) => {
+    execFile(command, args, options, (err, stdout, stderr) => {
+      if (err) {
+        err.stdout = stdout;
+        err.stderr = stderr;
+        reject(err);
+      } else {
+        resolve(stdout);
+      }
+    });
+  });
+}
+
 export function ensureCacheDir() {
   fs.mkdirSync(CACHE_DIR, { recursive: true });
   const pkgPath = path.join(CACHE_DIR, 'package.json');
@@ -22,34 +136,451 @@ export function ensureCacheDir() {
   }
 }
 
-export function installPackages(packages) {
+export function packageManager(name) {
+  const manager = PACKAGE_MANAGERS[name || 'npm'];
+  if (!manager) throw new Error(`Unsupported package manager: ${name}`);
+  return manager;
+}
+
+// node_modules trees from different package managers don't mix (pnpm refuses
+// to touch an npm-built one without a TTY), so switching managers starts the
+// cache's node_modules over.
+export function prepareLayout(name) {
+  let previous = null;
+  try {
+    previous = fs.readFileSync(LAYOUT_MARKER, 'utf-8').trim();
+  } catch {
+    // Caches from before the marker existed were always built by npm.
+    if (fs.existsSync(NODE_MODULES)) previous = 'npm';
+  }
+  if (previous === name && fs.existsSync(LAYOUT_MARKER)) return;
+  if (previous !== null && previous !== name) {
+    console.error(`[terrarium] Switching package manager to ${name}, reinstalling packages`);
+    fs.rmSync(NODE_MODULES, { recursive: true, force: true });
+    for (const lockfile of ALL_LOCKFILES) {
+      fs.rmSync(path.join(CACHE_DIR, lockfile), { force: true });
+    }
+    esbuildModule = null;
+    basePackagesReady = false;
+  }
+  if (name === 'yarn') {
+    // Yarn 2+ defaults to Plug'n'Play, which has no node_modules to resolve from.
+    fs.writeFileSync(path.join(CACHE_DIR, '.yarnrc.yml'), 'nodeLinker: node-modules\n');
+  }
+  fs.writeFileSync(LAYOUT_MARKER, name);
+}
+
+// Writes the .npmrc the app renders from the `registries` in settings.json.
+// A file the user wrote by hand (without NPMRC_HEADER) is left alone: an
+// empty config keeps it, and any other config is not written over it.
+export function writeNpmrc(contents) {
+  let current = null;
+  try {
+    current = fs.readFileSync(NPMRC, 'utf-8');
+  } catch {
+    // no .npmrc yet
+  }
+  const managed = current === null || current.startsWith(NPMRC_HEADER);
+  if (!contents) {
+    if (current !== null && managed) fs.rmSync(NPMRC, { force: true });
+    return;
+  }
+  if (!managed) {
+    console.error(`[terrarium] Not overwriting ${NPMRC}, which Terrarium doesn't manage; `
+      + 'remove it to use the registries in settings.json');
+    return;
+  }
+  if (current !== contents) fs.writeFileSync(NPMRC, contents, { mode: 0o600 });
+  // It can hold auth tokens, and `mode` only applies to a new file.
+  fs.chmodSync(NPMRC, 0o600);
+}
+
+// Hosts of the non-public registries the cache's .npmrc points at.
+export function privateRegistryHosts() {
+  let contents = '';
+  try {
+    contents = fs.readFileSync(NPMRC, 'utf-8');
+  } catch {
+    return [];
+  }
+  const hosts = [];
+  for (const line of contents.split(/\r?\n/)) {
+    const match = line.match(/^\s*(?:@[^:\s]+:)?registry\s*=\s*(\S+)/);
+    if (!match) continue;
+    try {
+      const { host } = new URL(match[1]);
+      if (host !== PUBLIC_REGISTRY_HOST && !hosts.includes(host)) hosts.push(host);
+    } catch {
+      // not a URL; the package manager will complain about it
+    }
+  }
+  return hosts;
+}
+
+// Whether `name` matches an entry of a settings package list: an exact name
+// or a whole scope such as `@acme/*`.
+export function matchesPackage(name, patterns) {
+  return patterns.some(pattern =>
+    pattern === name || (pattern.endsWith('/*') && name.startsWith(pattern.slice(0, -1))));
+}
+
+function sleep(ms) {
+  return new Promise(resolve => setTimeout(resolve, ms));
+}
+
+// Whether the install lock was left behind by a process that is gone. A
+// live holder keeps touching the lock, so one that hasn't been touched in
+// LOCK_STALE_MS belongs to a crashed process whose pid was reused.
+function lockIsStale() {
+  const pidFile = path.join(INSTALL_LOCK, 'pid');
+  try {
+    const pid = Number(fs.readFileSync(pidFile, 'utf-8'));
+    if (pid && pid !== process.pid) process.kill(pid, 0);
+  } catch (err) {
+    if (err.code === 'ESRCH') return true;
+  }
+  // Before the holder writes its pid, the directory's own time stands in.
+  for (const file of [pidFile, INSTALL_LOCK]) {
+    try {
+      return Date.now() - fs.statSync(file).mtimeMs > LOCK_STALE_MS;
+    } catch {
+      // not there
+    }
+  }
+  return false;
+}
+
+// Runs `fn` while holding the cross-process install lock on the cache.
+export async function withInstallLock(fn, { signal } = {}) {
+  for (;;) {
+    try {
+      fs.mkdirSync(INSTALL_LOCK);
+      break;
+    } catch (err) {
+      if (err.code !== 'EEXIST') throw err;
+    }
+    if (lockIsStale()) {
+      console.error('[terrarium] Removing stale install lock');
+      fs.rmSync(INSTALL_LOCK, { recursive: true, force: true });
+      continue;
+    }
+    signal?.throwIfAborted();
+    await sleep(LOCK_POLL_MS);
+  }
+  const touch = () => fs.writeFileSync(path.join(INSTALL_LOCK, 'pid'), String(process.pid));
+  const touching = setInterval(() => {
+    try {
+      touch();
+    } catch {
+      // taken over meanwhile; nothing to keep fresh
+    }
+  }, LOCK_TOUCH_MS);
+  touching.unref();
+  try {
+    touch();
+    return await fn();
+  } finally {
+    clearInterval(touching);
+    fs.rmSync(INSTALL_LOCK, { recursive: true, force: true });
+  }
+}
+
+// The install waiting for the lock. Packages requested meanwhile (by other
+// bundles in this daemon) join it, so they are installed in one go.
+let nextInstall = null;
+
+// Queues `packages` for installation into the shared cache and resolves
+// once they are installed. Aborting `signal` stops waiting; the install
+// itself is only killed once every bundle waiting on it has been aborted.
+export function queueInstall(packages, { signal, manager = 'npm', trusted = [] } = {}) {
+  if (packages.length === 0) return Promise.resolve();
+  const joining = nextInstall?.manager === manager;
+  const batch = joining ? nextInstall : {
+    manager,
+    packages: new Set(),
+    trusted: new Set(),
+    waiters: 0,
+    controller: new AbortController(),
+  };
+  packages.forEach(p => batch.packages.add(p));
+  trusted.forEach(p => batch.trusted.add(p));
+  batch.waiters += 1;
+  if (!joining) {
+    nextInstall = batch;
+    batch.done = withInstallLock(() => {
+      if (nextInstall === batch) nextInstall = null;
+      const toInstall = [...batch.packages].filter(needsInstall).map(lockedSpec);
+      return installPackages(toInstall, {
+        signal: batch.controller.signal, manager, trusted: [...batch.trusted],
+      }).then(recordLock);
+    }, { signal: batch.controller.signal }).finally(() => {
+      if (nextInstall === batch) nextInstall = null;
+    });
+  }
+
+  return new Promise((resolve, reject) => {
+    const onAbort = () => {
+      batch.waiters -= 1;
+      if (batch.waiters === 0) batch.controller.abort();
+      reject(signal.reason);
+    };
+    signal?.addEventListener('abort', onAbort, { once: true });
+    batch.done.then(resolve, reject).finally(() => signal?.removeEventListener('abort', onAbort));
+  });
+}
+
+// Installs `packages` into `dir` (the shared cache unless given), running
+// install scripts only for trusted ones. `peers: false` leaves out peer
+// dependencies.
+export async function installPackages(packages, {
+  signal, manager = 'npm', trusted = [], dir = CACHE_DIR, peers = true,
+} = {}) {
+  const allowScripts = [...ALWAYS_TRUSTED, ...trusted];
+  const withScripts = packages.filter(p => matchesPackage(packageName(p), allowScripts));
+  const withoutScripts = packages.filter(p => !withScripts.includes(p));
+  await runInstall(withScripts, { signal, manager, dir, peers, ignoreScripts: false });
+  await runInstall(withoutScripts, { signal, manager, dir, peers, ignoreScripts: true });
+}
+
+async function runInstall(packages, { signal, manager, dir, peers, ignoreScripts }) {
   if (packages.length === 0) return;
-  console.error(`[terrarium] Installing: ${packages.join(' ')}`);
-  const run = () => execFileSync('npm', ['install', '--prefix', CACHE_DIR, ...packages], {
-    stdio: ['pipe', 'pipe', 'pipe'],
+  const pm = packageManager(manager);
+  console.error(`[terrarium] Installing with ${manager}${ignoreScripts ? ' (scripts disabled)' : ''}: ${packages.join(' ')}`);
+  const args = [...pm.args(packages, dir), ...(ignoreScripts ? pm.noScripts.args : [])];
+  const run = () => runFile(manager, args, {
+    cwd: dir,
+    env: {
+      ...process.env,
+      ...(ignoreScripts ? pm.noScripts.env : {}),
+      ...(peers ? {} : NO_PEERS_ENV),
+    },
     encoding: 'utf-8',
-    timeout: 120000
+    timeout: 120000,
+    maxBuffer: 10 * 1024 * 1024,
+    signal,
+    // npm, pnpm and yarn are .cmd shims on Windows
+    shell: process.platform === 'win32',
   });
   try {
-    run();
+    await run();
   } catch (err) {
+    if (err.name === 'AbortError') throw err;
     const msg = String(err.stderr || err.stdout || err.message || '');
+    // Failures talking to a private registry are usually a VPN or token
+    // problem rather than being offline, so they get their own type.
+    const registry = privateRegistryHosts().find(host => msg.includes(host));
+    if (registry) {
+      const registryErr = new Error(`Registry error: could not install ${packages.join(', ')} from ${registry}. Check that the registry is reachable and that its token in settings.json is valid.`);
+      registryErr.type = 'registry';
+      throw registryErr;
+    }
     if (isNetworkMessage(msg)) {
       const networkErr = new Error(`Network error: could not install ${packages.join(', ')}. Check your internet connection.`);
       networkErr.type = 'network';
       throw networkErr;
     }
-    // ENOTEMPTY is a known npm bug with stale node_modules directories — retry once
+    // ENOTEMPTY is a known npm bug with stale node_modules directories (from
+    // installs interrupted before the lock existed) — retry once
     if (msg.includes('ENOTEMPTY')) {
-      console.error('[terrarium] Got ENOTEMPTY, retrying npm install...');
-      fs.rmSync(NODE_MODULES, { recursive: true, force: true });
-      run();
+      console.error(`[terrarium] Got ENOTEMPTY, retrying ${manager} install...`);
+      fs.rmSync(path.join(dir, 'node_modules'), { recursive: true, force: true });
+      if (dir === CACHE_DIR) {
+        esbuildModule = null;
+        basePackagesReady = false;
+      }
+      await run();
       return;
     }
+    if (pm.failure.test(msg)) err.type = 'network';
     throw err;
   }
 }
 
+export function readLock() {
+  try {
+    return JSON.parse(fs.readFileSync(LOCKFILE, 'utf-8')).packages || {};
+  } catch {
+    return {};
+  }
+}
+
+function installedVersion(name, dir = NODE_MODULES) {
+  try {
+    return JSON.parse(fs.readFileSync(path.join(dir, name, 'package.json'), 'utf-8')).version || null;
+  } catch {
+    return null;
+  }
+}
+
+// Rewrites the lockfile from what is installed now. Called with the install
+// lock held, after anything changed the cache's packages.
+export function recordLock() {
+  const packages = {};
+  for (const name of directDependencies().sort()) {
+    const version = installedVersion(name);
+    if (version) packages[name] = version;
+  }
+  fs.writeFileSync(LOCKFILE, JSON.stringify({ packages }, null, 2) + '\n');
+}
+
+// Installs a plain name (or a base package's range) at its locked version,
+// if there is one. Pinned versions are installed as asked.
+function lockedSpec(spec) {
+  const name = packageName(spec);
+  const locked = readLock()[name];
+  return locked && (spec === name || BASE_PACKAGES.includes(spec)) ? `${name}@${locked}` : spec;
+}
+
+// Packages installed on purpose (the cache's package.json dependencies), as
+// opposed to whatever they pulled in.
+export function directDependencies() {
+  try {
+    const manifest = JSON.parse(fs.readFileSync(path.join(CACHE_DIR, 'package.json'), 'utf-8'));
+    return Object.keys(manifest.dependencies || {});
+  } catch {
+    return [];
+  }
+}
+
+function isBasePackage(name) {
+  return BASE_PACKAGES.some(p => packageName(p) === name);
+}
+
+// Uninstalls `packages` from the shared cache and returns the ones that
+// were installed. Base packages only go away with a reseed.
+export async function removePackages(packages, { manager = 'npm', report = progress } = {}) {
+  const base = packages.filter(isBasePackage);
+  if (base.length > 0) {
+    throw new Error(`${base.join(', ')} ${base.length === 1 ? 'is' : 'are'} part of the base package set. Reset the package cache instead.`);
+  }
+  const installed = directDependencies();
+  const toRemove = packages.filter(p => installed.includes(p));
+  if (toRemove.length === 0) return [];
+  const pm = packageManager(manager);
+  report('removing', `Removing ${toRemove.join(', ')}...`);
+  await withInstallLock(async () => {
+    await runFile(manager, pm.removeArgs(toRemove), {
+      cwd: CACHE_DIR,
+      encoding: 'utf-8',
+      timeout: 120000,
+      maxBuffer: 10 * 1024 * 1024,
+      shell: process.platform === 'win32',
+    });
+    recordLock();
+  });
+  return toRemove;
+}
+
+// Removes every installed package that is neither a base package nor in
+// `keep` (what recent artifacts import).
+export async function prunePackages(keep, options = {}) {
+  const unused = directDependencies().filter(name => !isBasePackage(name) && !keep.includes(name));
+  return removePackages(unused, options);
+}
+
+// Wipes the cache's packages and installs the base set from scratch, at
+// the versions in the lockfile.
+export async function reseed({ manager = 'npm', report = progress } = {}) {
+  await withInstallLock(async () => {
+    report('wiping', 'Removing installed packages...');
+    fs.rmSync(NODE_MODULES, { recursive: true, force: true });
+    for (const lockfile of ALL_LOCKFILES) {
+      fs.rmSync(path.join(CACHE_DIR, lockfile), { force: true });
+    }
+    fs.rmSync(path.join(CACHE_DIR, 'package.json'), { force: true });
+    ensureCacheDir();
+    esbuildModule = null;
+    basePackagesReady = false;
+  });
+  prepareLayout(manager);
+  report('installing', `Installing ${BASE_PACKAGES.join(', ')}...`);
+  await queueInstall(BASE_PACKAGES, { manager });
+  basePackagesReady = true;
+}
+
+// Installs an isolated environment (`{dir, packages}`, sent by the app when
+// pinned versions conflict with the shared cache) unless that was done
+// before. Everything else the artifact needs still comes from the cache.
+export async function ensureEnv(env, { signal, manager = 'npm', trusted = [], report = progress } = {}) {
+  const marker = path.join(env.dir, ENV_MARKER);
+  try {
+    if (fs.readFileSync(marker, 'utf-8').trim() === manager) return;
+  } catch {
+    // not installed yet
+  }
+  report('installing', `Installing ${env.packages.join(', ')} separately...`);
+  await withInstallLock(async () => {
+    fs.rmSync(path.join(env.dir, 'node_modules'), { recursive: true, force: true });
+    fs.mkdirSync(env.dir, { recursive: true });
+    fs.writeFileSync(path.join(env.dir, 'package.json'),
+      JSON.stringify({ name: 'terrarium-env', private: true }, null, 2));
+    if (fs.existsSync(NPMRC)) {
+      fs.copyFileSync(NPMRC, path.join(env.dir, '.npmrc'));
+      fs.chmodSync(path.join(env.dir, '.npmrc'), 0o600);
+    }
+    if (manager === 'yarn') {
+      fs.writeFileSync(path.join(env.dir, '.yarnrc.yml'), 'nodeLinker: node-modules\n');
+    }
+    if (manager === 'bun') {
+      fs.writeFileSync(path.join(env.dir, 'bunfig.toml'), NO_PEERS_BUNFIG);
+    }
+    await installPackages(env.packages, { signal, manager, trusted, dir: env.dir, peers: false });
+    fs.writeFileSync(marker, manager);
+  }, { signal });
+}
+
+// Newest version of `spec` (a name, or a base package's range) in the
+// registry the cache installs from.
+async function latestVersion(spec) {
+  const stdout = await runFile('npm', ['view', spec.includes('@', 1) ? spec : `${spec}@latest`, 'version', '--json'], {
+    cwd: CACHE_DIR,
+    encoding: 'utf-8',
+    timeout: 30000,
+    shell: process.platform === 'win32',
+  });
+  const versions = [].concat(JSON.parse(stdout || 'null') || []);
+  return versions[versions.length - 1] || null;
+}
+
+// What upgrading the cache would change: `{name, from, to}` for every
+// locked package with a newer version. Base packages stay within their
+// range (tailwindcss@3 doesn't jump to 4).
+export async function outdatedPackages({ report = progress } = {}) {
+  const locked = readLock();
+  const names = Object.keys(locked);
+  report('checking', `Checking ${names.length} packages for upgrades...`);
+  const changes = [];
+  await Promise.all(names.map(async name => {
+    const spec = BASE_PACKAGES.find(p => packageName(p) === name) || name;
+    let latest = null;
+    try {
+      latest = await latestVersion(spec);
+    } catch (err) {
+      const msg = String(err.stderr || err.message || '');
+      console.error(`[terrarium] Could not check ${name}: ${msg.trim()}`);
+      if (isNetworkMessage(msg)) {
+        const networkErr = new Error('Network error: could not check for upgrades. Check your internet connection.');
+        networkErr.type = 'network';
+        throw networkErr;
+      }
+    }
+    if (latest && latest !== locked[name]) changes.push({ name, from: locked[name], to: latest });
+  }));
+  return changes.sort((a, b) => a.name.localeCompare(b.name));
+}
+
+// Installs the exact `name@version` specs an upgrade was previewed with and
+// locks them.
+export async function upgradePackages(specs, { manager = 'npm', trusted = [], report = progress } = {}) {
+  if (specs.length === 0) return [];
+  report('installing', `Upgrading ${specs.join(', ')}...`);
+  await withInstallLock(async () => {
+    await installPackages(specs, { manager, trusted });
+    recordLock();
+  });
+  return specs;
+}
+
 function isNetworkMessage(message) {
   return NETWORK_MARKERS.some(marker => message.includes(marker));
 }
@@ -61,8 +592,22 @@ function packageName(specifier) {
   return specifier.split('/')[0].replace(/@.*$/, '');
 }
 
-export function isInstalled(pkg) {
-  return fs.existsSync(path.join(NODE_MODULES, packageName(pkg)));
+// Where packages resolve from: the artifact's project first (if it lives in
+// one), then its isolated environment (if it has one), then the shared cache.
+function resolutionDirs(root, envDir = null) {
+  return [root, envDir].filter(Boolean).map(dir => path.join(dir, 'node_modules')).concat(NODE_MODULES);
+}
+
+export function isInstalled(pkg, root = null, envDir = null) {
+  const name = packageName(pkg);
+  return resolutionDirs(root, envDir).some(dir => fs.existsSync(path.join(dir, name)));
+}
+
+// Whether installing `spec` into the shared cache would change anything.
+// Plain names are skipped once installed; a pinned `name@version` is always
+// (re)installed, since the app only sends pins the cache doesn't match.
+function needsInstall(spec) {
+  return packageName(spec) !== spec || !isInstalled(spec);
 }
 
 async function generateTailwindCss(contentToScan) {
@@ -82,8 +627,19 @@ async function generateTailwindCss(contentToScan) {
   return result.css;
 }
 
-export async function bundle(inputFile) {
+// `approve` is asked before any package the artifact imports is installed
+// and resolves to whether the install may go ahead. Base packages are
+// installed without asking. `pins` are `name@version` specs from the file's
+// header that the shared cache has to install at that version; `env` is an
+// isolated environment for pins that conflict with it.
+export async function bundle(inputFile, {
+  report = progress, signal, root = null, manager = 'npm', npmrc, trusted = [], pins = [], env = null,
+  approve = async () => true,
+} = {}) {
   ensureCacheDir();
+  if (npmrc !== undefined) writeNpmrc(npmrc);
+  packageManager(manager);
+  prepareLayout(manager);
 
   const resolvedInput = path.resolve(inputFile);
 
@@ -91,25 +647,22 @@ export async function bundle(inputFile) {
     throw new Error(`File not found: ${resolvedInput}`);
   }
 
-  const basePackages = [
-    'react', 'react-dom', 'esbuild', 'postcss', 'tailwindcss@3',
-    // Common dependencies used in Claude-generated artifacts
-    'lucide-react',
-    'recharts',
-    'date-fns',
-    'framer-motion',
-    'clsx',
-    'tailwind-merge',
-    'class-variance-authority',
-  ];
-  const toInstall = basePackages.filter(p => !isInstalled(p));
-  if (toInstall.length > 0) {
-    progress('installing', `Installing ${toInstall.join(', ')}...`);
+  if (!basePackagesReady || !fs.existsSync(NODE_MODULES)) {
+    const toInstall = BASE_PACKAGES.filter(p => !isInstalled(p));
+    if (toInstall.length > 0) {
+      report('installing', `Installing ${toInstall.join(', ')}...`);
+    }
+    await queueInstall(toInstall, { signal, manager, trusted });
+    basePackagesReady = true;
   }
-  installPackages(toInstall);
+  signal?.throwIfAborted();
+  if (env) {
+    await ensureEnv(env, { signal, manager, trusted, report });
+    signal?.throwIfAborted();
+  }
+  const envDir = env?.dir ?? null;
 
-  const esbuildPath = path.join(NODE_MODULES, 'esbuild', 'lib', 'main.js');
-  const esbuild = await import(esbuildPath);
+  const esbuild = await loadEsbuild();
 
   const missing = new Set();
 
@@ -118,7 +671,7 @@ export async function bundle(inputFile) {
     setup(build) {
       build.onResolve({ filter: /^[^./]/ }, (args) => {
         const pkg = packageName(args.path);
-        if (!isInstalled(pkg)) {
+        if (!isInstalled(pkg, root, envDir)) {
           missing.add(pkg);
           return { path: args.path, external: true };
         }
@@ -127,7 +680,7 @@ export async function bundle(inputFile) {
     }
   };
 
-  progress('detecting', 'Scanning imports...');
+  report('detecting', 'Scanning imports...');
 
   await esbuild.build({
     entryPoints: [resolvedInput],
@@ -138,14 +691,30 @@ export async function bundle(inputFile) {
     write: false,
     outfile: 'out.js',
     plugins: [detectPlugin],
-    nodePaths: [NODE_MODULES],
+    nodePaths: resolutionDirs(root, envDir),
     logLevel: 'silent'
   });
 
+  // A pinned package is installed at its pinned version instead.
+  for (const pin of pins) {
+    if (!isInstalled(pin)) missing.add(packageName(pin));
+  }
   if (missing.size > 0) {
-    progress('installing', `Installing ${[...missing].join(', ')}...`);
+    report('approving', `Waiting for approval to install ${[...missing].join(', ')}...`);
+    if (!(await approve([...missing]))) {
+      const deniedErr = new Error(`Installing ${[...missing].join(', ')} was not approved.`);
+      deniedErr.type = 'not-approved';
+      throw deniedErr;
+    }
+    signal?.throwIfAborted();
+  }
+  const pinned = pins.map(packageName);
+  const toInstall = [...[...missing].filter(name => !pinned.includes(name)), ...pins];
+  if (toInstall.length > 0) {
+    report('installing', `Installing ${toInstall.join(', ')}...`);
   }
-  installPackages([...missing]);
+  await queueInstall(toInstall, { signal, manager, trusted });
+  signal?.throwIfAborted();
 
   const entryCode = `
     import * as _Module from ${JSON.stringify(resolvedInput)};
@@ -167,7 +736,7 @@ export async function bundle(inputFile) {
     }
   `;
 
-  progress('bundling', 'Bundling component...');
+  report('bundling', 'Bundling component...');
 
   const result = await esbuild.build({
     stdin: {
@@ -182,14 +751,17 @@ export async function bundle(inputFile) {
     jsxImportSource: 'react',
     write: false,
     outfile: 'out.js',
-    nodePaths: [NODE_MODULES],
+    nodePaths: resolutionDirs(root, envDir),
     minify: false,
     sourcemap: false,
+    metafile: true,
   });
 
   const bundledJs = result.outputFiles[0].text;
+  const { inputs, packages, packageDirs } = describeInputs(result.metafile);
+  signal?.throwIfAborted();
 
-  progress('styling', 'Generating styles...');
+  report('styling', 'Generating styles...');
 
   let css = '';
   try {
@@ -198,38 +770,195 @@ export async function bundle(inputFile) {
     console.error('[terrarium] Tailwind CSS generation failed, continuing without styles:', err.message);
   }
 
+  let code = bundledJs;
   if (css) {
     const cssInjection = `(function(){var s=document.getElementById("terrarium-tw");if(s)s.remove();s=document.createElement("style");s.id="terrarium-tw";s.textContent=${JSON.stringify(css)};document.head.appendChild(s)})();\n`;
-    return cssInjection + bundledJs;
+    code = cssInjection + bundledJs;
   }
 
-  return bundledJs;
+  return { code, inputs, packages, packageDirs };
 }
 
-const inputFile = process.argv[2];
-if (!inputFile) {
-  console.error('Usage: bundler.mjs <file.tsx|file.jsx>');
-  process.exit(1);
+// Splits esbuild's metafile inputs into local source files (absolute paths)
+// and the installed packages they pulled in, with their versions and the
+// directories they resolved to (pnpm keeps indirect dependencies under
+// `node_modules/.pnpm`, not at the top level).
+export function describeInputs(metafile) {
+  const inputs = [];
+  const packages = {};
+  const packageDirs = {};
+  const segment = `${path.sep}node_modules${path.sep}`;
+  for (const key of Object.keys(metafile?.inputs || {})) {
+    if (key.startsWith('<')) continue;
+    const file = path.resolve(key);
+    const marker = file.lastIndexOf(segment);
+    if (marker === -1) {
+      inputs.push(file);
+      continue;
+    }
+    const root = file.slice(0, marker + segment.length);
+    const rest = file.slice(root.length).split(path.sep);
+    const name = rest[0].startsWith('@') ? rest.slice(0, 2).join('/') : rest[0];
+    if (name in packages) continue;
+    packageDirs[name] = path.join(root, name);
+    try {
+      const manifest = JSON.parse(fs.readFileSync(path.join(packageDirs[name], 'package.json'), 'utf-8'));
+      packages[name] = manifest.version || '';
+    } catch {
+      packages[name] = '';
+    }
+  }
+  return { inputs, packages, packageDirs };
 }
 
-bundle(inputFile)
-  .then(output => {
-    process.stdout.write(output);
-  })
-  .catch(err => {
-    const type = classifyErrorType(err);
-    const errorPayload = JSON.stringify({
-      error: true,
-      type,
-      message: err.message,
-      errors: err.errors || []
+function errorPayload(err) {
+  return {
+    error: true,
+    type: classifyErrorType(err),
+    message: err.message,
+    errors: err.errors || []
+  };
+}
+
+// Daemon mode: one long-lived process serving line-delimited JSON requests
+// on stdin. Each request is `{"id": n, "file": "/abs/path.tsx", "root":
+// "/abs/project" | null, "packageManager": "npm", "npmrc": "..."}`, where
+// `root` is a project whose node_modules are searched before the shared
+// cache, `packageManager` (npm, pnpm, yarn or bun) installs anything missing
+// into the cache and `npmrc` is the registry config to install with;
+// `trusted` lists packages allowed to run install scripts, `pins` the
+// `name@version` specs to install at exactly that version and `env` (`{dir,
+// packages}`) an isolated environment for pins the cache can't take. Every line
+// written to stdout carries the request id and is either a progress update,
+// a request for install approval (`approve: [packages]`), a successful
+// result (`ok: true, code, inputs, packages, packageDirs`) or a failure (`ok: false,
+// error`). `{"id": n, "approved": true|false}` answers an approval request;
+// `{"id": n, "cancel": true}` aborts request n, killing the npm install it
+// is waiting on unless other requests are waiting on it too.
+function send(message) {
+  process.stdout.write(JSON.stringify(message) + '\n');
+}
+
+// Approval requests waiting on the app, by request id.
+const approvals = new Map();
+
+function requestApproval(id, packages, signal) {
+  return new Promise((resolve, reject) => {
+    const onAbort = () => {
+      approvals.delete(id);
+      reject(signal.reason);
+    };
+    signal?.addEventListener('abort', onAbort, { once: true });
+    approvals.set(id, approved => {
+      signal?.removeEventListener('abort', onAbort);
+      approvals.delete(id);
+      resolve(approved);
+    });
+    send({ id, approve: packages });
+  });
+}
+
+async function handleRequest(request, signal) {
+  const { id, file, root, packageManager: manager, npmrc, trusted, pins, env } = request;
+  const report = (phase, message) => send({ id, progress: true, phase, message });
+  const approve = packages => requestApproval(id, packages, signal);
+  try {
+    const { code, inputs, packages, packageDirs } = await bundle(file, { report, signal, root, manager, npmrc, trusted, pins, env, approve });
+    send({ id, ok: true, code, inputs, packages, packageDirs });
+  } catch (err) {
+    send({ id, ok: false, error: errorPayload(err) });
+  }
+}
+
+function runDaemon() {
+  const inFlight = new Set();
+  const controllers = new Map();
+  const lines = createInterface({ input: process.stdin, crlfDelay: Infinity });
+  lines.on('line', line => {
+    if (!line.trim()) return;
+    let request;
+    try {
+      request = JSON.parse(line);
+    } catch {
+      console.error(`[terrarium] Ignoring malformed request: ${line}`);
+      return;
+    }
+    if (request.cancel) {
+      controllers.get(request.id)?.abort();
+      return;
+    }
+    if ('approved' in request) {
+      approvals.get(request.id)?.(request.approved === true);
+      return;
+    }
+    const controller = new AbortController();
+    controllers.set(request.id, controller);
+    const task = handleRequest(request, controller.signal).finally(() => {
+      inFlight.delete(task);
+      controllers.delete(request.id);
     });
-    process.stdout.write(errorPayload);
-    process.exit(1);
+    inFlight.add(task);
+  });
+  // The Rust side closes stdin on shutdown; deny pending approvals (nobody is
+  // left to answer them), finish what's in flight, then exit.
+  lines.on('close', () => {
+    for (const answer of [...approvals.values()]) answer(false);
+    Promise.allSettled([...inFlight]).then(() => process.exit(0));
   });
+}
+
+// One-shot maintenance of the shared cache, behind the app's dependency
+// commands. Progress goes to stderr like a CLI bundle's; stdout gets
+// `{"ok": true, ...result}` or an error payload.
+const MAINTENANCE = {
+  '--uninstall': (packages, options) => removePackages(packages, options).then(removed => ({ removed })),
+  '--prune': (keep, options) => prunePackages(keep, options).then(removed => ({ removed })),
+  '--reseed': (_, options) => reseed(options).then(() => ({ removed: [] })),
+  '--outdated': (_, options) => outdatedPackages(options).then(changes => ({ changes })),
+  '--upgrade': (specs, options) => upgradePackages(specs, options).then(upgraded => ({ upgraded })),
+};
+
+function runMaintenance(command, args) {
+  ensureCacheDir();
+  const options = { manager: process.env.TERRARIUM_PACKAGE_MANAGER || 'npm' };
+  MAINTENANCE[command](args, options)
+    .then(result => {
+      process.stdout.write(JSON.stringify({ ok: true, ...result }));
+    })
+    .catch(err => {
+      process.stdout.write(JSON.stringify(errorPayload(err)));
+      process.exit(1);
+    });
+}
+
+const inputFile = process.argv[2];
+if (inputFile === '--daemon') {
+  runDaemon();
+} else if (Object.hasOwn(MAINTENANCE, inputFile ?? '')) {
+  runMaintenance(inputFile, process.argv.slice(3));
+} else if (!inputFile) {
+  console.error('Usage: bundler.mjs <file.tsx|file.jsx> [project-root]');
+  console.error('       bundler.mjs --daemon');
+  console.error('       bundler.mjs --uninstall <package...> | --prune [keep...] | --reseed');
+  console.error('       bundler.mjs --outdated | --upgrade <package@version...>');
+  process.exit(1);
+} else {
+  bundle(inputFile, {
+    root: process.argv[3] || null,
+    manager: process.env.TERRARIUM_PACKAGE_MANAGER || 'npm',
+  })
+    .then(output => {
+      process.stdout.write(output.code);
+    })
+    .catch(err => {
+      process.stdout.write(JSON.stringify(errorPayload(err)));
+      process.exit(1);
+    });
+}
 
 function classifyErrorType(err) {
   if (err.type) return err.type;
+  if (err.name === 'AbortError') return 'cancelled';
   const message = err.message || '';
   if (err.errors?.some(e => e.text?.includes('Expected'))) return 'syntax';
   if (message.includes('Could not resolve') || err.errors?.some(e => e.text?.includes('Could not resolve'))) {
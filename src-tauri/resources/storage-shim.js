@@ -1,25 +1,66 @@
 // Polyfill for Claude's window.storage API.
-// Routes getItem/setItem/removeItem through Tauri IPC,
-// scoped to the file path of the component loaded in this window.
+// Routes get/set/delete/list through Tauri IPC, scoped to the file open in
+// this window, or to the namespace shared by all artifacts when `shared` is
+// true. The app works out the file from the window itself; the path sent
+// along only has to agree with it. Results are shaped like Claude's:
+// `{key, value, shared}`, `{key, deleted, shared}` and
+// `{keys, prefix, shared}`; `get` resolves to null for a missing key.
+// Failures reject with an Error; going over the artifact's storage quota
+// rejects with one named QuotaExceededError, like localStorage's, and
+// reaching for another file's storage with a SecurityError.
+// getItem/setItem/removeItem are kept for artifacts written against the
+// earlier localStorage-style API.
 (function() {
   var invoke = window.__TAURI__.core.invoke;
-  window.storage = {
-    getItem: function(key) {
-      var filePath = window.__TERRARIUM_FILE_PATH__ || '';
-      if (!filePath) return Promise.resolve(null);
-      return invoke('storage_get', { filePath: filePath, key: key }).then(function(r) {
+
+  var ERROR_NAMES = { quota: 'QuotaExceededError', forbidden: 'SecurityError' };
+
+  // Turns a command's error (a string, or `{type, message}`) into an Error.
+  function toError(err) {
+    var error = new Error(err && err.message ? err.message : String(err));
+    error.name = (err && ERROR_NAMES[err.type]) || 'StorageError';
+    return error;
+  }
+
+  // Without a file there is no artifact namespace, but shared values still
+  // work.
+  function call(command, args, shared) {
+    var filePath = window.__TERRARIUM_FILE_PATH__ || '';
+    if (!filePath && !shared) return Promise.resolve(null);
+    args.filePath = filePath;
+    args.shared = !!shared;
+    return invoke(command, args).catch(function(err) {
+      throw toError(err);
+    });
+  }
+
+  var storage = {
+    get: function(key, shared) {
+      return call('storage_get', { key: key }, shared).then(function(r) {
         return r != null ? r : null;
       });
     },
+    set: function(key, value, shared) {
+      return call('storage_set', { key: key, value: String(value) }, shared);
+    },
+    delete: function(key, shared) {
+      return call('storage_remove', { key: key }, shared);
+    },
+    list: function(prefix, shared) {
+      var args = prefix ? { prefix: prefix } : {};
+      return call('storage_list', args, shared);
+    },
+    getItem: function(key) {
+      return storage.get(key).then(function(r) {
+        return r ? r.value : null;
+      });
+    },
     setItem: function(key, value) {
-      var filePath = window.__TERRARIUM_FILE_PATH__ || '';
-      if (!filePath) return Promise.resolve();
-      return invoke('storage_set', { filePath: filePath, key: key, value: String(value) });
+      return storage.set(key, value).then(function() {});
     },
     removeItem: function(key) {
-      var filePath = window.__TERRARIUM_FILE_PATH__ || '';
-      if (!filePath) return Promise.resolve();
-      return invoke('storage_remove', { filePath: filePath, key: key });
+      return storage.delete(key).then(function() {});
     }
   };
+  window.storage = storage;
 })();
@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use tokio::sync::oneshot;
+
+use crate::bundler::cache_dir;
+
+/// Returns the path to the approved-packages JSON file:
+/// `~/.terrarium/approved-packages.json`.
+fn approved_path() -> PathBuf {
+    cache_dir().join("approved-packages.json")
+}
+
+/// Reads the packages the user has approved installing. Returns an empty
+/// vec if the file is missing, unreadable, or contains invalid JSON.
+pub fn read_approved() -> Vec<String> {
+    let Ok(data) = std::fs::read_to_string(approved_path()) else {
+        return Vec::new();
+    };
+    serde_json::from_str(&data).unwrap_or_default()
+}
+
+/// Adds `packages` to the approved list on disk (best-effort).
+pub fn remember_approved(packages: &[String]) {
+    let mut approved = read_approved();
+    for package in packages {
+        if !approved.contains(package) {
+            approved.push(package.clone());
+        }
+    }
+    approved.sort();
+    let path = approved_path();
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    if let Ok(json) = serde_json::to_string_pretty(&approved) {
+        let _ = std::fs::write(&path, json);
+    }
+}
+
+/// Whether `name` matches an entry of a settings package list: an exact name
+/// or a whole scope such as `@acme/*`. Mirrors `matchesPackage` in
+/// `bundler.mjs`.
+pub fn matches_package(name: &str, patterns: &[String]) -> bool {
+    patterns.iter().any(|pattern| {
+        pattern == name
+            || pattern
+                .strip_suffix('*')
+                .is_some_and(|scope| scope.ends_with('/') && name.starts_with(scope))
+    })
+}
+
+/// The packages in `packages` that are neither allowlisted in settings nor
+/// approved before, i.e. the ones the user has to be asked about.
+pub fn needs_approval(packages: &[String], allowed: &[String], approved: &[String]) -> Vec<String> {
+    packages
+        .iter()
+        .filter(|p| !matches_package(p, allowed) && !approved.contains(p))
+        .cloned()
+        .collect()
+}
+
+/// Install approvals waiting on an answer from a window, keyed by the
+/// bundle request that asked, along with the label of the window asked.
+#[derive(Default)]
+pub struct PendingApprovals {
+    waiting: Mutex<HashMap<u64, (String, oneshot::Sender<bool>)>>,
+}
+
+impl PendingApprovals {
+    /// Registers a question for `request`, put to window `label`. The
+    /// receiver yields the answer, or an error if the question is dropped
+    /// unanswered.
+    pub fn ask(&self, request: u64, label: &str) -> oneshot::Receiver<bool> {
+        let (tx, rx) = oneshot::channel();
+        if let Ok(mut waiting) = self.waiting.lock() {
+            waiting.insert(request, (label.to_string(), tx));
+        }
+        rx
+    }
+
+    /// Delivers window `label`'s answer for `request`. Returns `false` if
+    /// nothing was waiting, e.g. because the bundle was superseded
+    /// meanwhile, or if the question was put to another window, which is
+    /// then still waiting.
+    pub fn answer(&self, request: u64, label: &str, approved: bool) -> bool {
+        let Some((_, tx)) =
+            self.waiting
+                .lock()
+                .ok()
+                .and_then(|mut waiting| match waiting.get(&request) {
+                    Some((asked, _)) if asked == label => waiting.remove(&request),
+                    _ => None,
+                })
+        else {
+            return false;
+        };
+        tx.send(approved).is_ok()
+    }
+
+    /// Drops the question for `request` without an answer. Returns whether
+    /// it was still waiting.
+    pub fn forget(&self, request: u64) -> bool {
+        self.waiting
+            .lock()
+            .is_ok_and(|mut waiting| waiting.remove(&request).is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn matches_exact_names_and_scopes() {
+        let patterns = names(&["lodash", "@acme/*"]);
+        assert!(matches_package("lodash", &patterns));
+        assert!(matches_package("@acme/ui", &patterns));
+        assert!(!matches_package("lodash-es", &patterns));
+        assert!(!matches_package("@acme-evil/ui", &patterns));
+    }
+
+    #[test]
+    fn only_unknown_packages_need_approval() {
+        let packages = names(&["lodash", "@acme/ui", "left-pad", "reqeusts"]);
+        let allowed = names(&["@acme/*"]);
+        let approved = names(&["lodash"]);
+        assert_eq!(
+            needs_approval(&packages, &allowed, &approved),
+            names(&["left-pad", "reqeusts"])
+        );
+    }
+
+    #[test]
+    fn answers_reach_the_waiting_request() {
+        let pending = PendingApprovals::default();
+        let mut rx = pending.ask(3, "main");
+        assert!(!pending.answer(4, "main", true));
+        assert!(pending.answer(3, "main", true));
+        assert_eq!(rx.try_recv(), Ok(true));
+    }
+
+    #[test]
+    fn only_the_asked_window_can_answer() {
+        let pending = PendingApprovals::default();
+        let mut rx = pending.ask(3, "window-2");
+        assert!(!pending.answer(3, "main", true));
+        assert!(rx.try_recv().is_err());
+        assert!(pending.answer(3, "window-2", false));
+        assert_eq!(rx.try_recv(), Ok(false));
+    }
+
+    #[test]
+    fn forgotten_questions_are_denied() {
+        let pending = PendingApprovals::default();
+        let mut rx = pending.ask(3, "main");
+        assert!(pending.forget(3));
+        assert!(!pending.forget(3));
+        assert!(rx.try_recv().is_err());
+        assert!(!pending.answer(3, "main", true));
+    }
+}
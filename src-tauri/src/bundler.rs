@@ -1,101 +1,153 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
-pub fn cache_dir() -> PathBuf {
-    dirs::home_dir()
-        .unwrap_or_else(|| PathBuf::from("/tmp"))
-        .join(".terrarium")
+use tokio::sync::{oneshot, watch};
+
+use crate::approvals::{self, PendingApprovals};
+use crate::daemon::{BundleRequest, BundlerDaemon};
+
+/// A successful bundle plus what it was built from.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BundleOutput {
+    pub code: String,
+    /// Local source files esbuild read, as absolute paths.
+    pub inputs: Vec<PathBuf>,
+    /// Installed packages the bundle pulled in, by name, with their versions.
+    pub packages: BTreeMap<String, String>,
+    /// The directory each of `packages` resolved to. With pnpm that's under
+    /// `node_modules/.pnpm` for anything the file didn't import directly.
+    pub package_dirs: BTreeMap<String, PathBuf>,
+    /// Packages pinned in the file's header that were bundled at another
+    /// version.
+    pub mismatches: Vec<crate::pins::PinMismatch>,
+    /// The project whose `node_modules` packages resolved from first, if the
+    /// file lives inside one.
+    pub project: Option<PathBuf>,
 }
 
-pub fn find_node() -> Result<PathBuf, String> {
-    let home = dirs::home_dir().unwrap_or_default();
+/// Source location attached to an esbuild message.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorLocation {
+    #[serde(default)]
+    pub file: String,
+    #[serde(default)]
+    pub line: u32,
+    #[serde(default)]
+    pub column: u32,
+    #[serde(default)]
+    pub line_text: String,
+}
 
-    // Direct paths: Homebrew (Apple Silicon + Intel), official installer
-    let direct = [
-        PathBuf::from("/opt/homebrew/bin/node"),
-        PathBuf::from("/usr/local/bin/node"),
-        home.join(".volta/bin/node"),
-    ];
-    for p in &direct {
-        if p.exists() {
-            return Ok(p.clone());
+/// A single esbuild diagnostic, as reported in `err.errors`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ErrorMessage {
+    #[serde(default)]
+    pub text: String,
+    #[serde(default)]
+    pub location: Option<ErrorLocation>,
+}
+
+/// Human-readable message plus the esbuild diagnostics behind it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ErrorDetails {
+    #[serde(default)]
+    pub message: String,
+    #[serde(default)]
+    pub errors: Vec<ErrorMessage>,
+}
+
+impl ErrorDetails {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            errors: Vec::new(),
         }
     }
+}
 
-    // Version managers: pick the latest installed version
-    let version_dirs = [
-        home.join(".nvm/versions/node"),
-        home.join("Library/Application Support/fnm/node-versions"),
-        home.join(".asdf/installs/nodejs"),
-    ];
-    for dir in &version_dirs {
-        if let Some(node) = find_latest_node_in(dir) {
-            return Ok(node);
+/// Why a bundle failed. Variants mirror `classifyErrorType` in
+/// `bundler.mjs`, plus the failures raised on the Rust side before or while
+/// talking to Node. Serialized as `{ "type": "...", "message", "errors" }`
+/// for the `bundle-error` event and command results.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum BundleError {
+    Syntax(ErrorDetails),
+    Resolve(ErrorDetails),
+    Network(ErrorDetails),
+    /// Installing from a private registry in the cache's `.npmrc` failed.
+    Registry(ErrorDetails),
+    Build(ErrorDetails),
+    /// The user declined installing a package the artifact imports.
+    NotApproved(ErrorDetails),
+    Timeout(ErrorDetails),
+    NodeMissing(ErrorDetails),
+    SpawnFailed(ErrorDetails),
+    /// A newer bundle for the same window replaced this one. Never emitted.
+    Cancelled(ErrorDetails),
+    Unknown(ErrorDetails),
+}
+
+impl BundleError {
+    pub fn details(&self) -> &ErrorDetails {
+        match self {
+            Self::Syntax(d)
+            | Self::Resolve(d)
+            | Self::Network(d)
+            | Self::Registry(d)
+            | Self::Build(d)
+            | Self::NotApproved(d)
+            | Self::Timeout(d)
+            | Self::NodeMissing(d)
+            | Self::SpawnFailed(d)
+            | Self::Cancelled(d)
+            | Self::Unknown(d) => d,
         }
     }
 
-    // Fallback: try user's login shell to resolve PATH (covers custom setups)
-    if let Some(node) = find_node_via_shell() {
-        return Ok(node);
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self, Self::Cancelled(_))
     }
 
-    Err("Node.js not found. Install it from https://nodejs.org".to_string())
-}
+    pub fn message(&self) -> &str {
+        &self.details().message
+    }
 
-fn find_latest_node_in(versions_dir: &Path) -> Option<PathBuf> {
-    let entries = std::fs::read_dir(versions_dir).ok()?;
-    let mut versions: Vec<PathBuf> = entries
-        .filter_map(|e| e.ok())
-        .map(|e| e.path())
-        .filter(|p| p.join("bin/node").exists())
-        .collect();
-    // Sort by version number, not lexicographically (so v18 > v9)
-    versions.sort_by(|a, b| {
-        let parse = |p: &Path| -> Vec<u64> {
-            p.file_name()
-                .unwrap_or_default()
-                .to_string_lossy()
-                .trim_start_matches('v')
-                .split('.')
-                .filter_map(|s| s.parse().ok())
-                .collect()
-        };
-        parse(a).cmp(&parse(b))
-    });
-    versions.last().map(|p| p.join("bin/node"))
+    /// Parses the error payload written by `bundler.mjs`, falling back to
+    /// `Unknown` for payloads that don't match a known shape.
+    pub fn from_payload(payload: serde_json::Value) -> Self {
+        serde_json::from_value(payload.clone()).unwrap_or_else(|_| {
+            let message = payload
+                .get("message")
+                .and_then(|m| m.as_str())
+                .map(str::to_string)
+                .unwrap_or_else(|| payload.to_string());
+            Self::Unknown(ErrorDetails::new(message))
+        })
+    }
 }
 
-fn find_node_via_shell() -> Option<PathBuf> {
-    // Launch the user's login shell to get their full PATH,
-    // then use it to find node. This handles custom setups.
-    let shell = std::env::var("SHELL").unwrap_or_else(|_| "/bin/zsh".to_string());
-    let output = std::process::Command::new(&shell)
-        .args(["-l", "-c", "which node"])
-        .output()
-        .ok()?;
-    output
-        .status
-        .success()
-        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
-        .filter(|p| !p.is_empty())
-        .map(PathBuf::from)
+impl std::fmt::Display for BundleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.message())
+    }
 }
 
-pub fn check_node_availability() -> Result<(String, String), String> {
-    let node = find_node()?;
-    let version = node_version(&node)?;
-    Ok((node.to_string_lossy().to_string(), version))
-}
+impl std::error::Error for BundleError {}
 
-fn node_version(node_path: &Path) -> Result<String, String> {
-    let output = std::process::Command::new(node_path)
-        .arg("--version")
-        .output()
-        .map_err(|e| format!("Failed to run node: {e}"))?;
-    if !output.status.success() {
-        return Err("Could not determine Node.js version".to_string());
+impl From<String> for BundleError {
+    fn from(message: String) -> Self {
+        Self::Unknown(ErrorDetails::new(message))
     }
-    let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
-    Ok(version)
+}
+
+pub fn cache_dir() -> PathBuf {
+    dirs::home_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join(".terrarium")
 }
 
 pub fn bundler_script_path<R: tauri::Runtime>(
@@ -114,81 +166,673 @@ pub fn bundler_script_path<R: tauri::Runtime>(
         .map_err(|e| format!("Failed to locate bundler: {e}"))
 }
 
-pub async fn bundle_tsx<R: tauri::Runtime>(
-    app_handle: &tauri::AppHandle<R>,
-    tsx_path: &Path,
-) -> Result<String, String> {
-    use tauri::Emitter;
-    use tokio::io::{AsyncBufReadExt, BufReader};
+/// Returns app state of type `T`, registering a default instance on first use
+/// so callers (and tests using a mock app) don't have to set it up ahead of
+/// time.
+pub(crate) fn managed<T, R>(app_handle: &tauri::AppHandle<R>) -> tauri::State<'_, T>
+where
+    T: Default + Send + Sync + 'static,
+    R: tauri::Runtime,
+{
+    use tauri::Manager;
+    if app_handle.try_state::<T>().is_none() {
+        app_handle.manage(T::default());
+    }
+    app_handle.state::<T>()
+}
 
-    let _ = app_handle.emit("bundle-started", ());
+#[derive(Debug, Default, Clone, Copy)]
+struct InFlight {
+    generation: u64,
+    request: Option<u64>,
+}
 
-    let bundler = bundler_script_path(app_handle)?;
-    let node = find_node()?;
+/// Tracks the newest bundle per window. Each bundle started for a window
+/// gets a new generation; only the latest generation's result may reach the
+/// window, so a slow older bundle can't overwrite a newer preview.
+#[derive(Default)]
+pub struct BundleGenerations {
+    windows: Mutex<HashMap<String, InFlight>>,
+}
 
-    let node_dir = node.parent().unwrap_or(Path::new(""));
-    let path_env = match std::env::var("PATH") {
-        Ok(existing) => format!("{}:{existing}", node_dir.display()),
-        Err(_) => format!("{}:/usr/bin:/bin", node_dir.display()),
-    };
+impl BundleGenerations {
+    /// Starts a new generation for `label`. Returns it along with the daemon
+    /// request of the generation it supersedes, if that is still running.
+    pub fn begin(&self, label: &str) -> (u64, Option<u64>) {
+        let Ok(mut windows) = self.windows.lock() else {
+            return (0, None);
+        };
+        let entry = windows.entry(label.to_string()).or_default();
+        let superseded = entry.request.take();
+        entry.generation += 1;
+        (entry.generation, superseded)
+    }
 
-    let mut child = tokio::process::Command::new(&node)
-        .arg(&bundler)
-        .arg(tsx_path)
-        .env("PATH", &path_env)
-        .stdout(std::process::Stdio::piped())
-        .stderr(std::process::Stdio::piped())
-        .spawn()
-        .map_err(|e| format!("Failed to run bundler: {e}"))?;
-
-    // Stream stderr for progress events
-    let stderr = child.stderr.take();
-    let progress_handle = app_handle.clone();
-    let stderr_task = tokio::spawn(async move {
-        if let Some(stderr) = stderr {
-            let reader = BufReader::new(stderr);
-            let mut lines = reader.lines();
-            while let Ok(Some(line)) = lines.next_line().await {
-                if let Ok(val) = serde_json::from_str::<serde_json::Value>(&line) {
-                    if val.get("progress").and_then(|v| v.as_bool()) == Some(true) {
-                        if let Some(msg) = val.get("message").and_then(|v| v.as_str()) {
-                            let _ = progress_handle.emit("bundle-progress", msg);
-                        }
-                    }
+    /// Records the daemon request serving `generation`, unless a newer
+    /// generation has already started.
+    pub fn attach(&self, label: &str, generation: u64, request: u64) {
+        if let Ok(mut windows) = self.windows.lock() {
+            if let Some(entry) = windows.get_mut(label) {
+                if entry.generation == generation {
+                    entry.request = Some(request);
                 }
             }
         }
-    });
+    }
+
+    /// Whether `generation` is still the latest one for `label`; a bundle
+    /// that isn't has nothing left to show and can stop.
+    pub fn is_current(&self, label: &str, generation: u64) -> bool {
+        self.windows.lock().is_ok_and(|windows| {
+            windows
+                .get(label)
+                .is_some_and(|e| e.generation == generation)
+        })
+    }
+
+    /// Marks `generation` as finished. Returns `true` if it is still the
+    /// latest one for `label`, i.e. its result may be shown.
+    pub fn finish(&self, label: &str, generation: u64) -> bool {
+        let Ok(mut windows) = self.windows.lock() else {
+            return false;
+        };
+        match windows.get_mut(label) {
+            Some(entry) if entry.generation == generation => {
+                entry.request = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Drops all tracking for a closed window, returning its in-flight
+    /// daemon request, if any.
+    pub fn forget(&self, label: &str) -> Option<u64> {
+        self.windows.lock().ok()?.remove(label)?.request
+    }
+}
+
+type BundleResult = Result<BundleOutput, BundleError>;
+
+/// How [`BundleScheduler::admit`] let a bundle in.
+pub enum Admission {
+    /// A slot was free: run now as job `job`.
+    Run(u64),
+    /// Every slot is busy. Run as job `job` once `start` fires; `position`
+    /// is the 1-based place in the queue.
+    Queued {
+        job: u64,
+        start: oneshot::Receiver<()>,
+        position: watch::Receiver<usize>,
+    },
+    /// A queued bundle of the same file will answer this request too.
+    Joined {
+        result: oneshot::Receiver<BundleResult>,
+        position: watch::Receiver<usize>,
+    },
+}
+
+struct QueuedJob {
+    id: u64,
+    file: PathBuf,
+    /// Windows waiting on the job, for focus priority.
+    labels: Vec<String>,
+    start: oneshot::Sender<()>,
+    position: watch::Sender<usize>,
+    followers: Vec<oneshot::Sender<BundleResult>>,
+    /// False for a suspended bundle waiting to resume: it may have read the
+    /// file already, so requests for it don't join.
+    joinable: bool,
+}
+
+#[derive(Default)]
+struct SchedulerState {
+    limit: usize,
+    focused: Option<String>,
+    next_job: u64,
+    /// Waiting jobs, in the order they will start.
+    queue: Vec<QueuedJob>,
+    /// Running jobs and the merged requests waiting on their result.
+    running: HashMap<u64, Vec<oneshot::Sender<BundleResult>>>,
+    /// Jobs that gave up their slot while waiting on the user.
+    suspended: HashMap<u64, Vec<oneshot::Sender<BundleResult>>>,
+}
+
+impl SchedulerState {
+    /// Puts the focused window's jobs first (otherwise first come, first
+    /// served), starts as many as there are free slots and tells the rest
+    /// where they stand.
+    fn reschedule(&mut self) {
+        let focused = self.focused.clone();
+        self.queue.sort_by_key(|job| {
+            let is_focused = focused.as_ref().is_some_and(|f| job.labels.contains(f));
+            (!is_focused, job.id)
+        });
+        while self.running.len() < self.limit && !self.queue.is_empty() {
+            let job = self.queue.remove(0);
+            if job.start.send(()).is_ok() {
+                self.running.insert(job.id, job.followers);
+            } else {
+                for follower in job.followers {
+                    let _ = follower.send(Err(BundleError::Cancelled(ErrorDetails::new(
+                        "Queued bundle was abandoned",
+                    ))));
+                }
+            }
+        }
+        for (index, job) in self.queue.iter().enumerate() {
+            job.position.send_replace(index + 1);
+        }
+    }
+}
+
+/// Caps how many bundles run at once across all windows. Bundles over the
+/// limit wait in a queue that serves the focused window first, and a
+/// request for a file that is already queued joins that bundle rather than
+/// queueing another one.
+#[derive(Default)]
+pub struct BundleScheduler {
+    state: Mutex<SchedulerState>,
+}
+
+impl BundleScheduler {
+    /// Admits a bundle of `file` for window `label`, allowing `limit`
+    /// bundles to run at once.
+    pub fn admit(&self, file: &Path, label: Option<&str>, limit: usize) -> Admission {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.limit = limit.max(1);
+
+        if let Some(job) = state
+            .queue
+            .iter_mut()
+            .find(|job| job.joinable && job.file == file)
+        {
+            let (tx, rx) = oneshot::channel();
+            job.followers.push(tx);
+            if let Some(label) = label.filter(|l| !job.labels.iter().any(|j| j == l)) {
+                job.labels.push(label.to_string());
+            }
+            let position = job.position.subscribe();
+            state.reschedule();
+            return Admission::Joined {
+                result: rx,
+                position,
+            };
+        }
+
+        state.next_job += 1;
+        let id = state.next_job;
+        if state.queue.is_empty() && state.running.len() < state.limit {
+            state.running.insert(id, Vec::new());
+            return Admission::Run(id);
+        }
+        let (start, start_rx) = oneshot::channel();
+        let (position, position_rx) = watch::channel(0);
+        state.queue.push(QueuedJob {
+            id,
+            file: file.to_path_buf(),
+            labels: label.map(str::to_string).into_iter().collect(),
+            start,
+            position,
+            followers: Vec::new(),
+            joinable: true,
+        });
+        state.reschedule();
+        Admission::Queued {
+            job: id,
+            start: start_rx,
+            position: position_rx,
+        }
+    }
+
+    /// Frees `job`'s slot, hands `result` to the requests merged into it
+    /// and starts the next queued bundle.
+    pub fn finish(&self, job: u64, result: &BundleResult) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let followers = match state.running.remove(&job) {
+            Some(followers) => followers,
+            None => match state.suspended.remove(&job) {
+                Some(followers) => followers,
+                None => match state.queue.iter().position(|queued| queued.id == job) {
+                    Some(index) => state.queue.remove(index).followers,
+                    None => Vec::new(),
+                },
+            },
+        };
+        for follower in followers {
+            let _ = follower.send(result.clone());
+        }
+        state.reschedule();
+    }
+
+    /// Frees running `job`'s slot while it waits on the user (an install
+    /// approval), so an unanswered prompt doesn't hold up other bundles.
+    pub fn suspend(&self, job: u64) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(followers) = state.running.remove(&job) {
+            state.suspended.insert(job, followers);
+            state.reschedule();
+        }
+    }
+
+    /// Takes a slot for suspended `job` again. Returns `None` if it got one
+    /// right away; otherwise it is queued like a new bundle, ahead of the
+    /// ones admitted after it, and may run once `start` fires.
+    pub fn resume(
+        &self,
+        job: u64,
+        file: &Path,
+        label: Option<&str>,
+    ) -> Option<(oneshot::Receiver<()>, watch::Receiver<usize>)> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let followers = state.suspended.remove(&job)?;
+        if state.queue.is_empty() && state.running.len() < state.limit {
+            state.running.insert(job, followers);
+            return None;
+        }
+        let (start, start_rx) = oneshot::channel();
+        let (position, position_rx) = watch::channel(0);
+        state.queue.push(QueuedJob {
+            id: job,
+            file: file.to_path_buf(),
+            labels: label.map(str::to_string).into_iter().collect(),
+            start,
+            position,
+            followers,
+            joinable: false,
+        });
+        state.reschedule();
+        Some((start_rx, position_rx))
+    }
+
+    /// Gives window `label`'s queued bundles priority.
+    pub fn set_focused(&self, label: &str) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.focused = Some(label.to_string());
+        state.reschedule();
+    }
+}
+
+/// A running job's slot. Freed when dropped, so a bundle whose future is
+/// dropped midway doesn't hold its slot forever.
+struct Slot<'a> {
+    scheduler: &'a BundleScheduler,
+    job: u64,
+    finished: bool,
+}
+
+impl Slot<'_> {
+    fn finish(mut self, result: &BundleResult) {
+        self.scheduler.finish(self.job, result);
+        self.finished = true;
+    }
+
+    /// Gives up the slot until [`Slot::resume`].
+    fn suspend(&self) {
+        self.scheduler.suspend(self.job);
+    }
+
+    /// Waits for a slot again, reporting the queue position to `label`.
+    async fn resume<R: tauri::Runtime>(
+        &self,
+        app_handle: &tauri::AppHandle<R>,
+        label: Option<&str>,
+        file: &Path,
+    ) {
+        if let Some((start, position)) = self.scheduler.resume(self.job, file, label) {
+            let _ = wait_in_queue(app_handle, label, position, start).await;
+        }
+    }
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.scheduler.finish(
+                self.job,
+                &Err(BundleError::Cancelled(ErrorDetails::new(
+                    "Bundle was abandoned",
+                ))),
+            );
+        }
+    }
+}
+
+/// Waits for `until` while reporting the queue `position` to window `label`
+/// (or every window) as bundle progress.
+async fn wait_in_queue<R: tauri::Runtime, T>(
+    app_handle: &tauri::AppHandle<R>,
+    label: Option<&str>,
+    mut position: watch::Receiver<usize>,
+    until: impl std::future::Future<Output = T>,
+) -> T {
+    use tauri::Emitter;
+
+    tokio::pin!(until);
+    loop {
+        let message = format!(
+            "Waiting to bundle (#{} in queue)...",
+            *position.borrow_and_update()
+        );
+        let _ = match label {
+            Some(label) => app_handle.emit_to(label, "bundle-progress", message),
+            None => app_handle.emit("bundle-progress", message),
+        };
+        tokio::select! {
+            output = &mut until => return output,
+            changed = position.changed() => {
+                if changed.is_err() {
+                    return until.await;
+                }
+            }
+        }
+    }
+}
+
+/// Gives window `label`'s queued bundles priority, e.g. when it gains focus.
+pub fn focus_window<R: tauri::Runtime>(app_handle: &tauri::AppHandle<R>, label: &str) {
+    managed::<BundleScheduler, R>(app_handle).set_focused(label);
+}
+
+/// Bundles `tsx_path` without tracking which window it is for.
+pub async fn bundle_tsx<R: tauri::Runtime>(
+    app_handle: &tauri::AppHandle<R>,
+    tsx_path: &Path,
+) -> Result<String, BundleError> {
+    Ok(run_bundle(app_handle, tsx_path, None, || true, |_| {})
+        .await?
+        .code)
+}
 
-    let result = tokio::time::timeout(
-        std::time::Duration::from_secs(120),
-        child.wait_with_output(),
+/// Bundles `tsx_path` for window `label`, cancelling whatever bundle that
+/// window still has in flight. Returns [`BundleError::Cancelled`] if a newer
+/// bundle for the same window starts before this one finishes; callers must
+/// not show that result. Pinned dependencies the bundle missed are sent to
+/// the window as `dependency-mismatches` (empty when everything matched),
+/// and the project it resolved from as `bundle-project` (`null` for a
+/// standalone file), which is also kept in the window's state.
+pub async fn bundle_for_window<R: tauri::Runtime>(
+    app_handle: &tauri::AppHandle<R>,
+    tsx_path: &Path,
+    label: &str,
+) -> Result<BundleOutput, BundleError> {
+    let generations = managed::<BundleGenerations, R>(app_handle);
+    let (generation, superseded) = generations.begin(label);
+    if let Some(request) = superseded {
+        cancel_request(app_handle, request).await;
+    }
+
+    let result = run_bundle(
+        app_handle,
+        tsx_path,
+        Some(label),
+        || generations.is_current(label, generation),
+        |request| generations.attach(label, generation, request),
     )
     .await;
 
-    // Ensure stderr task completes
-    let _ = stderr_task.await;
+    if generations.finish(label, generation) {
+        if let Ok(output) = &result {
+            use tauri::{Emitter, Manager};
+            let _ = app_handle.emit_to(label, "dependency-mismatches", &output.mismatches);
+            if let Some(state) = app_handle.try_state::<crate::AppState>() {
+                if let Ok(mut windows) = state.windows.lock() {
+                    if let Some(ws) = windows.get_mut(label) {
+                        ws.project.clone_from(&output.project);
+                    }
+                }
+            }
+            let _ = app_handle.emit_to(label, "bundle-project", &output.project);
+        }
+        result
+    } else {
+        Err(BundleError::Cancelled(ErrorDetails::new(
+            "Superseded by a newer bundle",
+        )))
+    }
+}
 
-    let _ = app_handle.emit("bundle-finished", ());
+/// Stops tracking a closed window and cancels its in-flight bundle.
+pub async fn forget_window<R: tauri::Runtime>(app_handle: &tauri::AppHandle<R>, label: &str) {
+    let request = managed::<BundleGenerations, R>(app_handle).forget(label);
+    if let Some(request) = request {
+        cancel_request(app_handle, request).await;
+    }
+}
+
+async fn cancel_request<R: tauri::Runtime>(app_handle: &tauri::AppHandle<R>, request: u64) {
+    managed::<BundlerDaemon, R>(app_handle)
+        .cancel(request)
+        .await;
+}
 
-    let output = result
-        .map_err(|_| "Bundler timed out after 120 seconds".to_string())?
-        .map_err(|e| format!("Failed to run bundler: {e}"))?;
+/// Delivers window `label`'s answer to the install approval `request` asked
+/// for. Answers from any window other than the one asked are ignored.
+pub fn answer_install_approval<R: tauri::Runtime>(
+    app_handle: &tauri::AppHandle<R>,
+    label: &str,
+    request: u64,
+    approved: bool,
+) -> bool {
+    managed::<PendingApprovals, R>(app_handle).answer(request, label, approved)
+}
 
-    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+/// Decides whether bundle `request` may install `packages`. Packages
+/// allowlisted in settings or approved before go ahead; anything else is put
+/// to window `label` as an `install-approval` event and remembered once
+/// approved. Without a window to ask, unknown packages are refused. The
+/// bundle's `slot` is given up while the window decides.
+async fn approve_install<R: tauri::Runtime>(
+    app_handle: &tauri::AppHandle<R>,
+    label: Option<&str>,
+    request: u64,
+    packages: Vec<String>,
+    allowed: &[String],
+    slot: &Slot<'_>,
+    file: &Path,
+) -> bool {
+    use tauri::Emitter;
 
-    if stdout.starts_with("{\"error\":true")
-        || (!output.status.success() && stdout.starts_with('{'))
+    let unknown = approvals::needs_approval(&packages, allowed, &approvals::read_approved());
+    if unknown.is_empty() {
+        return true;
+    }
+    let Some(label) = label else {
+        log::warn!(
+            "Refusing to install unapproved packages: {}",
+            unknown.join(", ")
+        );
+        return false;
+    };
+    let pending = managed::<PendingApprovals, R>(app_handle);
+    let answer = pending.ask(request, label);
+    let payload = serde_json::json!({ "request": request, "packages": unknown });
+    if app_handle
+        .emit_to(label, "install-approval", payload)
+        .is_err()
     {
-        return Err(stdout);
+        let _ = pending.forget(request);
+        return false;
+    }
+    slot.suspend();
+    let approved = answer.await.unwrap_or(false);
+    slot.resume(app_handle, Some(label), file).await;
+    if approved {
+        approvals::remember_approved(&unknown);
     }
+    approved
+}
 
-    if output.status.success() {
-        Ok(stdout)
-    } else {
-        let stderr_str = String::from_utf8_lossy(&output.stderr).to_string();
-        Err(format!("Bundler failed:\n{stderr_str}"))
+async fn run_bundle<R: tauri::Runtime>(
+    app_handle: &tauri::AppHandle<R>,
+    tsx_path: &Path,
+    label: Option<&str>,
+    is_current: impl Fn() -> bool,
+    on_request: impl FnOnce(u64),
+) -> Result<BundleOutput, BundleError> {
+    use tauri::Emitter;
+
+    let bundler = bundler_script_path(app_handle)
+        .map_err(|e| BundleError::SpawnFailed(ErrorDetails::new(e)))?;
+
+    // Detected once per requirement, so picking the file's Node is cheap
+    // even when the bundle comes from the cache below.
+    let node = managed::<crate::node::NodeCache, R>(app_handle)
+        .get(tsx_path.parent())
+        .map_err(|e| BundleError::NodeMissing(ErrorDetails::new(e)))?;
+
+    // An unchanged file with unchanged imports and packages, bundled by the
+    // same Node, is served from the on-disk cache without starting it.
+    let project = crate::project::resolution_root(tsx_path);
+    let version = crate::cache::bundler_version(&bundler).unwrap_or_default();
+    let cache_root = crate::cache::bundle_cache_dir();
+    let pins = crate::pins::read(tsx_path);
+    let plan = crate::envs::plan(
+        &pins,
+        project.as_deref(),
+        &cache_dir().join("node_modules"),
+        &crate::envs::envs_dir(),
+    );
+    let env_dir = plan.env.as_ref().map(|env| env.dir.as_path());
+    if let Some(mut output) = crate::cache::lookup(
+        &cache_root,
+        tsx_path,
+        project.as_deref(),
+        env_dir,
+        &version,
+        &node.version,
+    ) {
+        crate::envs::record_use(tsx_path, plan.env.as_ref());
+        output.mismatches = crate::pins::mismatches(&pins, &output.packages);
+        output.project = project;
+        return Ok(output);
     }
+
+    let node_version = node.version;
+    let node = node.path;
+    let path_env = crate::node::path_env(&node);
+    let settings = crate::settings::read_settings();
+    let package_manager = crate::package_manager::choose(
+        settings.package_manager,
+        &crate::package_manager::available(&path_env),
+    );
+
+    let _ = app_handle.emit("bundle-started", ());
+
+    // A bundle superseded while it waits for a slot (its window opened
+    // another file) gives its place up rather than running for nothing.
+    let superseded = || {
+        let _ = app_handle.emit("bundle-finished", ());
+        Err(BundleError::Cancelled(ErrorDetails::new(
+            "Superseded by a newer bundle",
+        )))
+    };
+    let scheduler = managed::<BundleScheduler, R>(app_handle);
+    let slot = loop {
+        if !is_current() {
+            return superseded();
+        }
+        match scheduler.admit(tsx_path, label, settings.max_concurrent_bundles) {
+            Admission::Run(job) => {
+                break Slot {
+                    scheduler: &scheduler,
+                    job,
+                    finished: false,
+                }
+            }
+            Admission::Queued {
+                job,
+                start,
+                position,
+            } => {
+                let slot = Slot {
+                    scheduler: &scheduler,
+                    job,
+                    finished: false,
+                };
+                // The scheduler only drops `start` unsent if it is torn down.
+                let _ = wait_in_queue(app_handle, label, position, start).await;
+                if !is_current() {
+                    let result = superseded();
+                    slot.finish(&result);
+                    return result;
+                }
+                break slot;
+            }
+            Admission::Joined { result, position } => {
+                match wait_in_queue(app_handle, label, position, result).await {
+                    // The bundle joined was cancelled for the sake of the
+                    // window that started it, not this one: queue again.
+                    Ok(Err(BundleError::Cancelled(_))) | Err(_) => continue,
+                    Ok(result) => {
+                        let _ = app_handle.emit("bundle-finished", ());
+                        return result;
+                    }
+                }
+            }
+        }
+    };
+
+    let daemon = managed::<BundlerDaemon, R>(app_handle);
+    let request = daemon.next_request_id();
+    on_request(request);
+    let result = daemon
+        .bundle(
+            BundleRequest {
+                id: request,
+                file: tsx_path,
+                root: project.as_deref(),
+                package_manager,
+                npmrc: crate::registry::npmrc(&settings.registries),
+                trusted: &settings.trusted_packages,
+                pins: plan.shared.clone(),
+                env: plan.env.as_ref(),
+            },
+            &node,
+            &bundler,
+            &path_env,
+            |msg| {
+                let _ = app_handle.emit("bundle-progress", msg);
+            },
+            |packages| {
+                approve_install(
+                    app_handle,
+                    label,
+                    request,
+                    packages,
+                    &settings.allowed_packages,
+                    &slot,
+                    tsx_path,
+                )
+            },
+        )
+        .await;
+
+    // A request that ended while the user was deciding (cancelled, or the
+    // daemon died) takes its question down with it.
+    if managed::<PendingApprovals, R>(app_handle).forget(request) {
+        if let Some(label) = label {
+            let _ = app_handle.emit_to(label, "install-approval-closed", request);
+        }
+    }
+    slot.finish(&result);
+    let _ = app_handle.emit("bundle-finished", ());
+
+    let mut output = result?;
+    crate::cache::store(
+        &cache_root,
+        tsx_path,
+        project.as_deref(),
+        env_dir,
+        &version,
+        &node_version,
+        &output,
+    );
+    crate::envs::record_use(tsx_path, plan.env.as_ref());
+    crate::recent::record_bundle_versions(&tsx_path.to_string_lossy(), &output.packages);
+    output.mismatches = crate::pins::mismatches(&pins, &output.packages);
+    output.project = project;
+    Ok(output)
 }
 
 #[cfg(test)]
@@ -203,14 +847,254 @@ mod tests {
     }
 
     #[test]
-    fn check_node_availability_returns_version() {
-        // This test requires Node.js to be installed on the machine.
-        if find_node().is_ok() {
-            let result = check_node_availability();
-            assert!(result.is_ok());
-            let (path, version) = result.unwrap();
-            assert!(!path.is_empty());
-            assert!(version.starts_with('v'));
+    fn bundle_error_parses_bundler_payload() {
+        let payload = serde_json::json!({
+            "error": true,
+            "type": "syntax",
+            "message": "Build failed with 1 error",
+            "errors": [{
+                "text": "Expected \";\" but found \"{\"",
+                "location": {
+                    "file": "App.tsx",
+                    "line": 3,
+                    "column": 12,
+                    "length": 1,
+                    "lineText": "  return {{{"
+                }
+            }]
+        });
+        let err = BundleError::from_payload(payload);
+        let BundleError::Syntax(details) = &err else {
+            panic!("expected a syntax error, got {err:?}");
+        };
+        assert_eq!(details.message, "Build failed with 1 error");
+        let location = details.errors[0].location.as_ref().unwrap();
+        assert_eq!(location.file, "App.tsx");
+        assert_eq!(location.line, 3);
+        assert_eq!(location.column, 12);
+        assert_eq!(location.line_text, "  return {{{");
+    }
+
+    #[test]
+    fn bundle_error_falls_back_to_unknown() {
+        let err = BundleError::from_payload(serde_json::json!({
+            "type": "mystery",
+            "message": "something odd"
+        }));
+        assert_eq!(
+            err,
+            BundleError::Unknown(ErrorDetails::new("something odd"))
+        );
+    }
+
+    #[test]
+    fn bundle_error_serializes_with_kebab_case_type() {
+        let err = BundleError::NodeMissing(ErrorDetails::new("Node.js not found"));
+        let json = serde_json::to_value(&err).unwrap();
+        assert_eq!(json["type"], "node-missing");
+        assert_eq!(json["message"], "Node.js not found");
+        assert_eq!(json["errors"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn generations_supersede_older_bundles() {
+        let generations = BundleGenerations::default();
+        let (first, superseded) = generations.begin("main");
+        assert_eq!(superseded, None);
+        generations.attach("main", first, 10);
+
+        let (second, superseded) = generations.begin("main");
+        assert_eq!(superseded, Some(10));
+        assert!(!generations.finish("main", first));
+        assert!(generations.finish("main", second));
+    }
+
+    #[test]
+    fn generations_are_tracked_per_window() {
+        let generations = BundleGenerations::default();
+        let (main, _) = generations.begin("main");
+        let (other, _) = generations.begin("window-2");
+        assert!(generations.finish("main", main));
+        assert!(generations.finish("window-2", other));
+    }
+
+    #[test]
+    fn generations_ignore_attach_from_stale_generation() {
+        let generations = BundleGenerations::default();
+        let (first, _) = generations.begin("main");
+        let (_second, _) = generations.begin("main");
+        generations.attach("main", first, 10);
+        assert_eq!(generations.forget("main"), None);
+    }
+
+    #[test]
+    fn generations_know_when_a_bundle_is_stale() {
+        let generations = BundleGenerations::default();
+        let (first, _) = generations.begin("main");
+        assert!(generations.is_current("main", first));
+        let (second, _) = generations.begin("main");
+        assert!(!generations.is_current("main", first));
+        assert!(generations.is_current("main", second));
+        generations.forget("main");
+        assert!(!generations.is_current("main", second));
+    }
+
+    #[test]
+    fn forget_returns_in_flight_request() {
+        let generations = BundleGenerations::default();
+        let (generation, _) = generations.begin("main");
+        generations.attach("main", generation, 3);
+        assert_eq!(generations.forget("main"), Some(3));
+        assert_eq!(generations.forget("main"), None);
+    }
+
+    fn run_job(admission: Admission) -> u64 {
+        match admission {
+            Admission::Run(job) => job,
+            _ => panic!("expected the bundle to run right away"),
+        }
+    }
+
+    fn queued(admission: Admission) -> (u64, oneshot::Receiver<()>, watch::Receiver<usize>) {
+        match admission {
+            Admission::Queued {
+                job,
+                start,
+                position,
+            } => (job, start, position),
+            _ => panic!("expected the bundle to be queued"),
         }
     }
+
+    fn output(code: &str) -> BundleResult {
+        Ok(BundleOutput {
+            code: code.to_string(),
+            ..BundleOutput::default()
+        })
+    }
+
+    #[test]
+    fn scheduler_queues_bundles_over_the_limit() {
+        let scheduler = BundleScheduler::default();
+        let first = run_job(scheduler.admit(Path::new("/a.tsx"), Some("main"), 2));
+        run_job(scheduler.admit(Path::new("/b.tsx"), Some("w2"), 2));
+        let (_, mut start, position) = queued(scheduler.admit(Path::new("/c.tsx"), Some("w3"), 2));
+        assert_eq!(*position.borrow(), 1);
+        assert!(start.try_recv().is_err());
+
+        scheduler.finish(first, &output("a"));
+        assert_eq!(start.try_recv(), Ok(()));
+    }
+
+    #[test]
+    fn scheduler_reports_queue_positions() {
+        let scheduler = BundleScheduler::default();
+        let running = run_job(scheduler.admit(Path::new("/a.tsx"), None, 1));
+        let (_, _b_start, b_position) = queued(scheduler.admit(Path::new("/b.tsx"), None, 1));
+        let (_, _c_start, c_position) = queued(scheduler.admit(Path::new("/c.tsx"), None, 1));
+        assert_eq!((*b_position.borrow(), *c_position.borrow()), (1, 2));
+
+        scheduler.finish(running, &output("a"));
+        assert_eq!(*c_position.borrow(), 1);
+    }
+
+    #[test]
+    fn scheduler_starts_the_focused_window_first() {
+        let scheduler = BundleScheduler::default();
+        let running = run_job(scheduler.admit(Path::new("/a.tsx"), Some("main"), 1));
+        let (_, mut background, _) = queued(scheduler.admit(Path::new("/b.tsx"), Some("w2"), 1));
+        let (_, mut focused, position) =
+            queued(scheduler.admit(Path::new("/c.tsx"), Some("w3"), 1));
+        assert_eq!(*position.borrow(), 2);
+
+        scheduler.set_focused("w3");
+        assert_eq!(*position.borrow(), 1);
+
+        scheduler.finish(running, &output("a"));
+        assert_eq!(focused.try_recv(), Ok(()));
+        assert!(background.try_recv().is_err());
+    }
+
+    #[test]
+    fn scheduler_merges_duplicate_requests_for_a_queued_file() {
+        let scheduler = BundleScheduler::default();
+        let running = run_job(scheduler.admit(Path::new("/a.tsx"), Some("main"), 1));
+        let (job, _start, _) = queued(scheduler.admit(Path::new("/b.tsx"), Some("w2"), 1));
+        let Admission::Joined { mut result, .. } =
+            scheduler.admit(Path::new("/b.tsx"), Some("w3"), 1)
+        else {
+            panic!("expected the duplicate to join the queued bundle");
+        };
+
+        scheduler.finish(running, &output("a"));
+        assert!(result.try_recv().is_err());
+        scheduler.finish(job, &output("b"));
+        assert_eq!(result.try_recv().unwrap().unwrap().code, "b");
+    }
+
+    #[test]
+    fn scheduler_can_readmit_followers_of_a_cancelled_bundle() {
+        let scheduler = BundleScheduler::default();
+        let running = run_job(scheduler.admit(Path::new("/a.tsx"), Some("main"), 1));
+        let (_, leader_start, _) = queued(scheduler.admit(Path::new("/b.tsx"), Some("w2"), 1));
+        let Admission::Joined { mut result, .. } =
+            scheduler.admit(Path::new("/b.tsx"), Some("w3"), 1)
+        else {
+            panic!("expected the duplicate to join the queued bundle");
+        };
+
+        // w2 moves on before its bundle starts.
+        drop(leader_start);
+        scheduler.finish(running, &output("a"));
+        assert!(matches!(
+            result.try_recv(),
+            Ok(Err(BundleError::Cancelled(_)))
+        ));
+        // The follower, queueing again, gets a bundle of its own.
+        run_job(scheduler.admit(Path::new("/b.tsx"), Some("w3"), 1));
+    }
+
+    #[test]
+    fn suspended_bundles_free_their_slot() {
+        let scheduler = BundleScheduler::default();
+        let asking = run_job(scheduler.admit(Path::new("/a.tsx"), Some("main"), 1));
+        let (other, mut start, _) = queued(scheduler.admit(Path::new("/b.tsx"), Some("w2"), 1));
+
+        scheduler.suspend(asking);
+        assert_eq!(start.try_recv(), Ok(()));
+
+        // Answered while /b.tsx runs: waits for it, without taking requests
+        // for its file along.
+        let (mut resumed, _) = scheduler
+            .resume(asking, Path::new("/a.tsx"), Some("main"))
+            .expect("expected the resumed bundle to queue");
+        queued(scheduler.admit(Path::new("/a.tsx"), Some("w3"), 1));
+        assert!(resumed.try_recv().is_err());
+        scheduler.finish(other, &output("b"));
+        assert_eq!(resumed.try_recv(), Ok(()));
+    }
+
+    #[test]
+    fn suspended_bundles_resume_at_once_with_a_free_slot() {
+        let scheduler = BundleScheduler::default();
+        let asking = run_job(scheduler.admit(Path::new("/a.tsx"), Some("main"), 1));
+        scheduler.suspend(asking);
+        assert!(scheduler
+            .resume(asking, Path::new("/a.tsx"), Some("main"))
+            .is_none());
+        // Holding the slot again.
+        queued(scheduler.admit(Path::new("/b.tsx"), Some("w2"), 1));
+        // Finishing while suspended frees nothing twice.
+        scheduler.suspend(asking);
+        scheduler.finish(asking, &output("a"));
+        run_job(scheduler.admit(Path::new("/c.tsx"), Some("w3"), 2));
+    }
+
+    #[test]
+    fn scheduler_does_not_merge_with_a_running_bundle() {
+        let scheduler = BundleScheduler::default();
+        run_job(scheduler.admit(Path::new("/a.tsx"), Some("main"), 1));
+        // The running bundle may have read the file before it changed.
+        queued(scheduler.admit(Path::new("/a.tsx"), Some("main"), 1));
+    }
 }
@@ -0,0 +1,567 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::bundler::{cache_dir, BundleOutput};
+
+/// Total size the bundle cache may grow to before the least recently used
+/// bundles are evicted.
+pub const MAX_CACHE_BYTES: u64 = 256 * 1024 * 1024;
+/// Bundles not read or written for this long are evicted.
+pub const MAX_CACHE_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// What a cached bundle was built from. Stored per entry file under
+/// `bundles/index/`; the bundle itself lives at `bundles/<key>.js`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+struct Manifest {
+    entry: PathBuf,
+    key: String,
+    /// The project whose `node_modules` packages were resolved from, if any.
+    #[serde(default)]
+    project: Option<PathBuf>,
+    /// The isolated environment packages were resolved from, if any.
+    #[serde(default)]
+    env: Option<PathBuf>,
+    inputs: Vec<PathBuf>,
+    packages: BTreeMap<String, String>,
+    /// Where each package resolved to when the bundle was built.
+    #[serde(default)]
+    package_dirs: BTreeMap<String, PathBuf>,
+}
+
+/// Returns the bundle cache directory: `~/.terrarium/bundles`.
+pub fn bundle_cache_dir() -> PathBuf {
+    cache_dir().join("bundles")
+}
+
+/// Returns the cached bundle for `entry` if none of its inputs, installed
+/// package versions, the bundler script or the Node version bundling it have
+/// changed since it was stored, and it was resolved against the same
+/// `project` and `env`.
+pub fn lookup(
+    root: &Path,
+    entry: &Path,
+    project: Option<&Path>,
+    env: Option<&Path>,
+    bundler_version: &str,
+    node_version: &str,
+) -> Option<BundleOutput> {
+    let data = std::fs::read_to_string(manifest_path(root, entry)).ok()?;
+    let manifest: Manifest = serde_json::from_str(&data).ok()?;
+    if manifest.project.as_deref() != project || manifest.env.as_deref() != env {
+        return None;
+    }
+    let key = cache_key(
+        bundler_version,
+        node_version,
+        project,
+        env,
+        &manifest.inputs,
+        &manifest.packages,
+        &manifest.package_dirs,
+    )?;
+    if key != manifest.key {
+        return None;
+    }
+    let bundle = root.join(format!("{key}.js"));
+    let code = std::fs::read_to_string(&bundle).ok()?;
+    touch(&bundle);
+    Some(BundleOutput {
+        code,
+        inputs: manifest.inputs,
+        packages: manifest.packages,
+        package_dirs: manifest.package_dirs,
+        ..BundleOutput::default()
+    })
+}
+
+/// Packages the cached bundle of `entry` was built with, whether or not it
+/// is still fresh.
+pub fn cached_packages(root: &Path, entry: &Path) -> Option<BTreeMap<String, String>> {
+    let data = std::fs::read_to_string(manifest_path(root, entry)).ok()?;
+    let manifest: Manifest = serde_json::from_str(&data).ok()?;
+    Some(manifest.packages)
+}
+
+/// Stores a freshly built bundle and evicts old entries (best-effort).
+pub fn store(
+    root: &Path,
+    entry: &Path,
+    project: Option<&Path>,
+    env: Option<&Path>,
+    bundler_version: &str,
+    node_version: &str,
+    output: &BundleOutput,
+) {
+    // The daemon reports what it read; the entry is always part of the key
+    // even if an older bundler didn't list it.
+    let mut inputs = output.inputs.clone();
+    if !inputs.iter().any(|p| p == entry) {
+        inputs.push(entry.to_path_buf());
+    }
+    inputs.sort();
+    inputs.dedup();
+
+    let Some(key) = cache_key(
+        bundler_version,
+        node_version,
+        project,
+        env,
+        &inputs,
+        &output.packages,
+        &output.package_dirs,
+    ) else {
+        return;
+    };
+    let manifest = Manifest {
+        entry: entry.to_path_buf(),
+        key: key.clone(),
+        project: project.map(Path::to_path_buf),
+        env: env.map(Path::to_path_buf),
+        inputs,
+        packages: output.packages.clone(),
+        package_dirs: output.package_dirs.clone(),
+    };
+    let manifest_file = manifest_path(root, entry);
+    if let Some(parent) = manifest_file.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    if std::fs::write(root.join(format!("{key}.js")), &output.code).is_err() {
+        return;
+    }
+    if let Ok(json) = serde_json::to_string_pretty(&manifest) {
+        let _ = std::fs::write(&manifest_file, json);
+    }
+    evict(root, MAX_CACHE_BYTES, MAX_CACHE_AGE);
+}
+
+/// Deletes bundles older than `max_age`, then the least recently used ones
+/// until the cache fits in `max_bytes`. Manifests pointing at evicted
+/// bundles simply miss on the next lookup.
+pub fn evict(root: &Path, max_bytes: u64, max_age: Duration) {
+    let Ok(entries) = std::fs::read_dir(root) else {
+        return;
+    };
+    let now = SystemTime::now();
+    let mut bundles: Vec<(SystemTime, u64, PathBuf)> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|ext| ext == "js"))
+        .filter_map(|p| {
+            let meta = std::fs::metadata(&p).ok()?;
+            Some((meta.modified().ok()?, meta.len(), p))
+        })
+        .collect();
+
+    bundles.retain(|(modified, _, path)| {
+        let expired = now.duration_since(*modified).unwrap_or_default() > max_age;
+        if expired {
+            let _ = std::fs::remove_file(path);
+        }
+        !expired
+    });
+
+    // Oldest first, so the least recently used go before anything fresh.
+    bundles.sort_by_key(|(modified, _, _)| *modified);
+    let mut total: u64 = bundles.iter().map(|(_, size, _)| size).sum();
+    for (_, size, path) in &bundles {
+        if total <= max_bytes {
+            break;
+        }
+        if std::fs::remove_file(path).is_ok() {
+            total -= size;
+        }
+    }
+
+    if let Ok(manifests) = std::fs::read_dir(root.join("index")) {
+        for manifest in manifests.filter_map(|e| e.ok()) {
+            let expired = manifest
+                .metadata()
+                .and_then(|m| m.modified())
+                .map(|m| now.duration_since(m).unwrap_or_default() > max_age)
+                .unwrap_or(false);
+            if expired {
+                let _ = std::fs::remove_file(manifest.path());
+            }
+        }
+    }
+}
+
+/// Hash of everything a bundle depends on: the bundler script version, the
+/// project and environment it was resolved in, the path and contents of
+/// every local input, and the currently installed version of every package
+/// it used (from the directory it resolved to, else the project first, then
+/// the environment, then the shared cache). `None` if an input is unreadable or a package is no longer
+/// installed.
+fn cache_key(
+    bundler_version: &str,
+    node_version: &str,
+    project: Option<&Path>,
+    env: Option<&Path>,
+    inputs: &[PathBuf],
+    packages: &BTreeMap<String, String>,
+    package_dirs: &BTreeMap<String, PathBuf>,
+) -> Option<String> {
+    let mut hasher = Sha256::new();
+    hasher.update(bundler_version.as_bytes());
+    hasher.update([0]);
+    // A bundle built by another Node (a file's `.nvmrc` may pick a different
+    // one) isn't reused.
+    hasher.update(node_version.as_bytes());
+    hasher.update([0]);
+    if let Some(project) = project {
+        hasher.update(project.to_string_lossy().as_bytes());
+    }
+    hasher.update([0]);
+    if let Some(env) = env {
+        hasher.update(env.to_string_lossy().as_bytes());
+        hasher.update([0]);
+    }
+    for input in inputs {
+        let contents = std::fs::read(input).ok()?;
+        hasher.update(input.to_string_lossy().as_bytes());
+        hasher.update([0]);
+        hasher.update(Sha256::digest(&contents));
+    }
+    let node_modules = crate::project::node_modules_dirs(project, env);
+    for name in packages.keys() {
+        let version = package_dirs
+            .get(name)
+            .and_then(|dir| package_version(dir))
+            .or_else(|| {
+                node_modules
+                    .iter()
+                    .find_map(|dir| installed_version(dir, name))
+            })?;
+        hasher.update(format!("{name}@{version}\0").as_bytes());
+    }
+    Some(format!("{:x}", hasher.finalize()))
+}
+
+/// Reads the `version` field of `node_modules/<name>/package.json`.
+pub fn installed_version(node_modules: &Path, name: &str) -> Option<String> {
+    package_version(&node_modules.join(name))
+}
+
+/// Reads the `version` field of `<dir>/package.json`.
+fn package_version(dir: &Path) -> Option<String> {
+    let data = std::fs::read_to_string(dir.join("package.json")).ok()?;
+    let manifest: serde_json::Value = serde_json::from_str(&data).ok()?;
+    Some(manifest.get("version")?.as_str()?.to_string())
+}
+
+/// Hash of the bundler script, so upgrading Terrarium invalidates bundles
+/// produced by an older bundler.
+pub fn bundler_version(script: &Path) -> Option<String> {
+    let contents = std::fs::read(script).ok()?;
+    Some(format!("{:x}", Sha256::digest(&contents)))
+}
+
+fn manifest_path(root: &Path, entry: &Path) -> PathBuf {
+    let digest = Sha256::digest(entry.to_string_lossy().as_bytes());
+    root.join("index").join(format!("{digest:x}.json"))
+}
+
+/// Marks a bundle as recently used so size-based eviction keeps it.
+fn touch(path: &Path) {
+    if let Ok(file) = std::fs::File::options().write(true).open(path) {
+        let _ = file.set_modified(SystemTime::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NODE: &str = "v20.11.0";
+
+    fn output(code: &str, inputs: &[&Path]) -> BundleOutput {
+        BundleOutput {
+            code: code.to_string(),
+            inputs: inputs.iter().map(|p| p.to_path_buf()).collect(),
+            ..BundleOutput::default()
+        }
+    }
+
+    #[test]
+    fn lookup_misses_when_nothing_is_stored() {
+        let root = tempfile::TempDir::new().unwrap();
+        assert_eq!(
+            lookup(
+                root.path(),
+                Path::new("/tmp/nope.tsx"),
+                None,
+                None,
+                "v1",
+                NODE
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn store_then_lookup_hits() {
+        let root = tempfile::TempDir::new().unwrap();
+        let src = tempfile::TempDir::new().unwrap();
+        let entry = src.path().join("App.tsx");
+        std::fs::write(&entry, "export default () => null").unwrap();
+
+        store(
+            root.path(),
+            &entry,
+            None,
+            None,
+            "v1",
+            NODE,
+            &output("bundle", &[&entry]),
+        );
+        assert_eq!(
+            lookup(root.path(), &entry, None, None, "v1", NODE),
+            Some(output("bundle", &[&entry]))
+        );
+    }
+
+    #[test]
+    fn lookup_misses_after_a_local_import_changes() {
+        let root = tempfile::TempDir::new().unwrap();
+        let src = tempfile::TempDir::new().unwrap();
+        let entry = src.path().join("App.tsx");
+        let data = src.path().join("data.ts");
+        std::fs::write(&entry, "import data from './data';").unwrap();
+        std::fs::write(&data, "export default [1, 2, 3];").unwrap();
+
+        store(
+            root.path(),
+            &entry,
+            None,
+            None,
+            "v1",
+            NODE,
+            &output("bundle", &[&entry, &data]),
+        );
+        std::fs::write(&data, "export default [4, 5, 6];").unwrap();
+        assert_eq!(lookup(root.path(), &entry, None, None, "v1", NODE), None);
+    }
+
+    #[test]
+    fn lookup_misses_when_bundler_version_changes() {
+        let root = tempfile::TempDir::new().unwrap();
+        let src = tempfile::TempDir::new().unwrap();
+        let entry = src.path().join("App.tsx");
+        std::fs::write(&entry, "export default () => null").unwrap();
+
+        store(
+            root.path(),
+            &entry,
+            None,
+            None,
+            "v1",
+            NODE,
+            &output("bundle", &[&entry]),
+        );
+        assert_eq!(lookup(root.path(), &entry, None, None, "v2", NODE), None);
+    }
+
+    #[test]
+    fn lookup_misses_when_node_version_changes() {
+        let root = tempfile::TempDir::new().unwrap();
+        let src = tempfile::TempDir::new().unwrap();
+        let entry = src.path().join("App.tsx");
+        std::fs::write(&entry, "export default () => null").unwrap();
+
+        store(
+            root.path(),
+            &entry,
+            None,
+            None,
+            "v1",
+            NODE,
+            &output("bundle", &[&entry]),
+        );
+        assert_eq!(
+            lookup(root.path(), &entry, None, None, "v1", "v22.1.0"),
+            None
+        );
+        assert!(lookup(root.path(), &entry, None, None, "v1", NODE).is_some());
+    }
+
+    #[test]
+    fn lookup_misses_when_a_package_is_missing() {
+        let root = tempfile::TempDir::new().unwrap();
+        let src = tempfile::TempDir::new().unwrap();
+        let entry = src.path().join("App.tsx");
+        std::fs::write(&entry, "export default () => null").unwrap();
+
+        let mut out = output("bundle", &[&entry]);
+        out.packages.insert(
+            "__terrarium_not_installed__".to_string(),
+            "1.0.0".to_string(),
+        );
+        store(root.path(), &entry, None, None, "v1", NODE, &out);
+        assert_eq!(lookup(root.path(), &entry, None, None, "v1", NODE), None);
+    }
+
+    #[test]
+    fn lookup_misses_when_the_project_changes() {
+        let root = tempfile::TempDir::new().unwrap();
+        let src = tempfile::TempDir::new().unwrap();
+        let entry = src.path().join("App.tsx");
+        std::fs::write(&entry, "export default () => null").unwrap();
+
+        store(
+            root.path(),
+            &entry,
+            None,
+            None,
+            "v1",
+            NODE,
+            &output("bundle", &[&entry]),
+        );
+        assert_eq!(
+            lookup(root.path(), &entry, Some(src.path()), None, "v1", NODE),
+            None
+        );
+    }
+
+    #[test]
+    fn lookup_misses_when_the_environment_changes() {
+        let root = tempfile::TempDir::new().unwrap();
+        let src = tempfile::TempDir::new().unwrap();
+        let entry = src.path().join("App.tsx");
+        std::fs::write(&entry, "export default () => null").unwrap();
+        let env = src.path().join("envs/abc");
+
+        store(
+            root.path(),
+            &entry,
+            None,
+            Some(&env),
+            "v1",
+            NODE,
+            &output("bundle", &[&entry]),
+        );
+        assert!(lookup(root.path(), &entry, None, Some(&env), "v1", NODE).is_some());
+        assert_eq!(lookup(root.path(), &entry, None, None, "v1", NODE), None);
+    }
+
+    #[test]
+    fn project_packages_are_versioned_from_the_project() {
+        let root = tempfile::TempDir::new().unwrap();
+        let project = tempfile::TempDir::new().unwrap();
+        let entry = project.path().join("App.tsx");
+        let pkg = project
+            .path()
+            .join("node_modules/__terrarium_project_pkg__");
+        std::fs::write(&entry, "export default () => null").unwrap();
+        std::fs::create_dir_all(&pkg).unwrap();
+        std::fs::write(pkg.join("package.json"), r#"{"version":"1.0.0"}"#).unwrap();
+
+        let mut out = output("bundle", &[&entry]);
+        out.packages
+            .insert("__terrarium_project_pkg__".to_string(), "1.0.0".to_string());
+        store(
+            root.path(),
+            &entry,
+            Some(project.path()),
+            None,
+            "v1",
+            NODE,
+            &out,
+        );
+        assert!(lookup(root.path(), &entry, Some(project.path()), None, "v1", NODE).is_some());
+
+        // Upgrading the project's copy invalidates the bundle.
+        std::fs::write(pkg.join("package.json"), r#"{"version":"2.0.0"}"#).unwrap();
+        assert_eq!(
+            lookup(root.path(), &entry, Some(project.path()), None, "v1", NODE),
+            None
+        );
+    }
+
+    #[test]
+    fn pnpm_packages_are_versioned_from_where_they_resolved() {
+        let root = tempfile::TempDir::new().unwrap();
+        let project = tempfile::TempDir::new().unwrap();
+        let entry = project.path().join("App.tsx");
+        let pkg = project.path().join(
+            "node_modules/.pnpm/__terrarium_pnpm_pkg__@1.0.0/node_modules/__terrarium_pnpm_pkg__",
+        );
+        std::fs::write(&entry, "export default () => null").unwrap();
+        std::fs::create_dir_all(&pkg).unwrap();
+        std::fs::write(pkg.join("package.json"), r#"{"version":"1.0.0"}"#).unwrap();
+
+        let mut out = output("bundle", &[&entry]);
+        out.packages
+            .insert("__terrarium_pnpm_pkg__".to_string(), "1.0.0".to_string());
+        out.package_dirs
+            .insert("__terrarium_pnpm_pkg__".to_string(), pkg.clone());
+        store(
+            root.path(),
+            &entry,
+            Some(project.path()),
+            None,
+            "v1",
+            NODE,
+            &out,
+        );
+        assert!(lookup(root.path(), &entry, Some(project.path()), None, "v1", NODE).is_some());
+
+        std::fs::write(pkg.join("package.json"), r#"{"version":"1.0.1"}"#).unwrap();
+        assert_eq!(
+            lookup(root.path(), &entry, Some(project.path()), None, "v1", NODE),
+            None
+        );
+    }
+
+    #[test]
+    fn evict_removes_least_recently_used_over_budget() {
+        let root = tempfile::TempDir::new().unwrap();
+        let old = root.path().join("old.js");
+        let new = root.path().join("new.js");
+        std::fs::write(&old, "x".repeat(100)).unwrap();
+        std::fs::write(&new, "y".repeat(100)).unwrap();
+        let past = SystemTime::now() - Duration::from_secs(60);
+        std::fs::File::options()
+            .write(true)
+            .open(&old)
+            .unwrap()
+            .set_modified(past)
+            .unwrap();
+
+        evict(root.path(), 150, MAX_CACHE_AGE);
+        assert!(!old.exists());
+        assert!(new.exists());
+    }
+
+    #[test]
+    fn evict_removes_expired_bundles() {
+        let root = tempfile::TempDir::new().unwrap();
+        let stale = root.path().join("stale.js");
+        std::fs::write(&stale, "x").unwrap();
+        let past = SystemTime::now() - Duration::from_secs(120);
+        std::fs::File::options()
+            .write(true)
+            .open(&stale)
+            .unwrap()
+            .set_modified(past)
+            .unwrap();
+
+        evict(root.path(), MAX_CACHE_BYTES, Duration::from_secs(60));
+        assert!(!stale.exists());
+    }
+
+    #[test]
+    fn installed_version_reads_package_json() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let pkg = dir.path().join("@scope/pkg");
+        std::fs::create_dir_all(&pkg).unwrap();
+        std::fs::write(pkg.join("package.json"), r#"{"version":"1.2.3"}"#).unwrap();
+        assert_eq!(
+            installed_version(dir.path(), "@scope/pkg"),
+            Some("1.2.3".to_string())
+        );
+        assert_eq!(installed_version(dir.path(), "missing"), None);
+    }
+}
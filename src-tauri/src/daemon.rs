@@ -0,0 +1,541 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, Command};
+use tokio::sync::mpsc;
+
+use crate::bundler::{BundleError, BundleOutput, ErrorDetails};
+use crate::package_manager::PackageManager;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(2);
+
+/// A line written by `bundler.mjs --daemon` on stdout.
+#[derive(Debug, Deserialize)]
+struct DaemonLine {
+    id: u64,
+    #[serde(default)]
+    progress: bool,
+    message: Option<String>,
+    ok: Option<bool>,
+    code: Option<String>,
+    #[serde(default)]
+    inputs: Vec<PathBuf>,
+    #[serde(default)]
+    packages: BTreeMap<String, String>,
+    #[serde(default, rename = "packageDirs")]
+    package_dirs: BTreeMap<String, PathBuf>,
+    error: Option<serde_json::Value>,
+    /// Packages the daemon wants to install, pending approval.
+    approve: Option<Vec<String>>,
+}
+
+/// A bundle request as written to the daemon's stdin.
+#[derive(Debug, Serialize)]
+pub struct BundleRequest<'a> {
+    pub id: u64,
+    pub file: &'a Path,
+    /// Project whose `node_modules` are searched before the shared cache.
+    pub root: Option<&'a Path>,
+    /// Installs missing packages into the shared cache.
+    #[serde(rename = "packageManager")]
+    pub package_manager: PackageManager,
+    /// Registry config written to the cache's `.npmrc` before installing.
+    pub npmrc: String,
+    /// Packages whose install scripts may run.
+    pub trusted: &'a [String],
+    /// Pinned versions to install into the shared cache, as `name@version`.
+    pub pins: Vec<String>,
+    /// Isolated environment for pins that conflict with the shared cache.
+    pub env: Option<&'a crate::envs::Environment>,
+}
+
+/// Events delivered to the caller waiting on a single request.
+enum DaemonEvent {
+    Progress(String),
+    /// The daemon asks whether it may install these packages.
+    Approval(Vec<String>),
+    Done(Result<BundleOutput, BundleError>),
+}
+
+type Pending = Arc<Mutex<HashMap<u64, mpsc::UnboundedSender<DaemonEvent>>>>;
+
+struct DaemonProcess {
+    child: Child,
+    stdin: ChildStdin,
+    pending: Pending,
+    script: PathBuf,
+}
+
+impl DaemonProcess {
+    fn is_running(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(None))
+    }
+}
+
+/// Long-lived `node bundler.mjs --daemon` workers shared by every window,
+/// one per Node binary (artifacts can pin different Node versions).
+///
+/// Requests and responses are line-delimited JSON over stdio. Each process is
+/// spawned lazily on the first request for its Node, respawned if it has
+/// exited, and stopped by [`BundlerDaemon::shutdown`] when the app exits.
+#[derive(Default)]
+pub struct BundlerDaemon {
+    processes: tokio::sync::Mutex<HashMap<PathBuf, DaemonProcess>>,
+    next_id: AtomicU64,
+}
+
+impl BundlerDaemon {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allocates the id for a request, so callers can cancel it while it runs.
+    pub fn next_request_id(&self) -> u64 {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Runs `request` on the daemon for `node`, calling `on_progress` for
+    /// each progress message and `on_approval` when the daemon wants to
+    /// install packages. Time spent waiting for approval doesn't count
+    /// towards the request timeout.
+    pub async fn bundle<F>(
+        &self,
+        request: BundleRequest<'_>,
+        node: &Path,
+        script: &Path,
+        path_env: &std::ffi::OsStr,
+        on_progress: impl Fn(&str),
+        on_approval: impl Fn(Vec<String>) -> F,
+    ) -> Result<BundleOutput, BundleError>
+    where
+        F: std::future::Future<Output = bool>,
+    {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let id = request.id;
+        let request = serde_json::to_value(&request)
+            .map_err(|e| BundleError::Unknown(ErrorDetails::new(e.to_string())))?;
+        let pending = self
+            .send(node, script, path_env, &request, tx)
+            .await
+            .map_err(|e| BundleError::SpawnFailed(ErrorDetails::new(e)))?;
+
+        let mut deadline = tokio::time::Instant::now() + REQUEST_TIMEOUT;
+        let result = loop {
+            let event = match tokio::time::timeout_at(deadline, rx.recv()).await {
+                Ok(Some(event)) => event,
+                Ok(None) => break Err(exited()),
+                Err(_) => {
+                    // Abandoned here, so stop the daemon working on it too:
+                    // it may hold the install lock or wait for an approval.
+                    self.abort(id).await;
+                    break Err(BundleError::Timeout(ErrorDetails::new(format!(
+                        "Bundler timed out after {} seconds",
+                        REQUEST_TIMEOUT.as_secs()
+                    ))));
+                }
+            };
+            match event {
+                DaemonEvent::Progress(msg) => on_progress(&msg),
+                DaemonEvent::Approval(packages) => {
+                    let asked = tokio::time::Instant::now();
+                    let approved =
+                        match await_approval(&mut rx, on_approval(packages), &on_progress).await {
+                            Ok(approved) => approved,
+                            Err(result) => break result,
+                        };
+                    deadline += asked.elapsed();
+                    self.reply(id, serde_json::json!({ "id": id, "approved": approved }))
+                        .await;
+                }
+                DaemonEvent::Done(result) => break result,
+            }
+        };
+        if let Ok(mut pending) = pending.lock() {
+            pending.remove(&id);
+        }
+        result
+    }
+
+    /// Writes `message` to the daemon serving request `id`.
+    async fn reply(&self, id: u64, message: serde_json::Value) {
+        let mut processes = self.processes.lock().await;
+        let Some(process) = processes.values_mut().find(|process| {
+            process
+                .pending
+                .lock()
+                .is_ok_and(|pending| pending.contains_key(&id))
+        }) else {
+            return;
+        };
+        let line = format!("{message}\n");
+        let _ = process.stdin.write_all(line.as_bytes()).await;
+    }
+
+    /// Cancels request `id`: its caller gets [`BundleError::Cancelled`] right
+    /// away, and the daemon aborts the work, killing any npm install it is
+    /// waiting on. No-op if the request already finished.
+    pub async fn cancel(&self, id: u64) {
+        if let Some(tx) = self.abort(id).await {
+            let _ = tx.send(DaemonEvent::Done(Err(BundleError::Cancelled(
+                ErrorDetails::new("Superseded by a newer bundle"),
+            ))));
+        }
+    }
+
+    /// Stops request `id` on whichever daemon is serving it. Returns its
+    /// caller's sender, or `None` if the request already finished.
+    async fn abort(&self, id: u64) -> Option<mpsc::UnboundedSender<DaemonEvent>> {
+        let mut processes = self.processes.lock().await;
+        let process = processes.values_mut().find(|process| {
+            process
+                .pending
+                .lock()
+                .is_ok_and(|pending| pending.contains_key(&id))
+        })?;
+        abort_request(&process.pending, &mut process.stdin, id).await
+    }
+
+    /// Closes every daemon's stdin so they can finish in-flight work and
+    /// exit, killing any that haven't stopped within a short grace period.
+    pub async fn shutdown(&self) {
+        let processes: Vec<DaemonProcess> = self
+            .processes
+            .lock()
+            .await
+            .drain()
+            .map(|(_, p)| p)
+            .collect();
+        let mut children = Vec::new();
+        for DaemonProcess { child, stdin, .. } in processes {
+            drop(stdin);
+            children.push(child);
+        }
+        for mut child in children {
+            if tokio::time::timeout(SHUTDOWN_GRACE, child.wait())
+                .await
+                .is_err()
+            {
+                let _ = child.kill().await;
+            }
+        }
+    }
+
+    /// Registers `tx` for the request's id and writes the request to the
+    /// daemon for `node`, (re)spawning it if needed. Returns the pending map the request
+    /// was registered in.
+    async fn send(
+        &self,
+        node: &Path,
+        script: &Path,
+        path_env: &std::ffi::OsStr,
+        request: &serde_json::Value,
+        tx: mpsc::UnboundedSender<DaemonEvent>,
+    ) -> Result<Pending, String> {
+        let id = request["id"].as_u64().unwrap_or_default();
+        let mut line = request.to_string();
+        line.push('\n');
+
+        let mut processes = self.processes.lock().await;
+        let reusable = processes
+            .get_mut(node)
+            .is_some_and(|p| p.script == script && p.is_running());
+        if !reusable {
+            if let Some(mut stale) = processes.remove(node) {
+                let _ = stale.child.start_kill();
+            }
+            processes.insert(node.to_path_buf(), self.spawn(node, script, path_env)?);
+        }
+        let process = processes
+            .get_mut(node)
+            .expect("daemon process was just spawned");
+        register(&process.pending, id, tx.clone())?;
+        if process.stdin.write_all(line.as_bytes()).await.is_ok() {
+            return Ok(process.pending.clone());
+        }
+
+        // The daemon died between the liveness check and the write; start a
+        // fresh one and retry once.
+        let _ = process.child.start_kill();
+        let process = processes
+            .entry(node.to_path_buf())
+            .insert_entry(self.spawn(node, script, path_env)?)
+            .into_mut();
+        register(&process.pending, id, tx)?;
+        process
+            .stdin
+            .write_all(line.as_bytes())
+            .await
+            .map_err(|e| format!("Failed to talk to bundler: {e}"))?;
+        Ok(process.pending.clone())
+    }
+
+    fn spawn(
+        &self,
+        node: &Path,
+        script: &Path,
+        path_env: &std::ffi::OsStr,
+    ) -> Result<DaemonProcess, String> {
+        let mut child = Command::new(node)
+            .arg(script)
+            .arg("--daemon")
+            .env("PATH", path_env)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| format!("Failed to run bundler: {e}"))?;
+
+        let stdin = child.stdin.take().ok_or("Failed to open bundler stdin")?;
+        let stdout = child.stdout.take().ok_or("Failed to open bundler stdout")?;
+        let stderr = child.stderr.take();
+
+        let pending: Pending = Arc::default();
+        let reader_pending = pending.clone();
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stdout).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                dispatch(&reader_pending, &line);
+            }
+            // The daemon exited: fail everything still waiting on it. The next
+            // request respawns the process.
+            if let Ok(mut pending) = reader_pending.lock() {
+                pending.clear();
+            }
+        });
+
+        if let Some(stderr) = stderr {
+            tokio::spawn(async move {
+                let mut lines = BufReader::new(stderr).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    log::info!("{line}");
+                }
+            });
+        }
+
+        Ok(DaemonProcess {
+            child,
+            stdin,
+            pending,
+            script: script.to_path_buf(),
+        })
+    }
+}
+
+fn exited() -> BundleError {
+    BundleError::SpawnFailed(ErrorDetails::new("Bundler process exited unexpectedly"))
+}
+
+/// Waits for the user's answer to an approval request while still
+/// forwarding progress. If the request ends first (cancelled, or the daemon
+/// died), returns its result instead; dropping `answer` drops the question.
+async fn await_approval<F: std::future::Future<Output = bool>>(
+    rx: &mut mpsc::UnboundedReceiver<DaemonEvent>,
+    answer: F,
+    on_progress: impl Fn(&str),
+) -> Result<bool, Result<BundleOutput, BundleError>> {
+    tokio::pin!(answer);
+    loop {
+        tokio::select! {
+            approved = &mut answer => return Ok(approved),
+            event = rx.recv() => match event {
+                Some(DaemonEvent::Progress(msg)) => on_progress(&msg),
+                // The daemon only asks once per request.
+                Some(DaemonEvent::Approval(_)) => {}
+                Some(DaemonEvent::Done(result)) => return Err(result),
+                None => return Err(Err(exited())),
+            },
+        }
+    }
+}
+
+/// Forgets request `id`, so lines the daemon still writes for it are
+/// dropped, and tells the daemon behind `stdin` to abort it. Returns the
+/// caller's sender if the request was still pending.
+async fn abort_request<W: AsyncWrite + Unpin>(
+    pending: &Pending,
+    stdin: &mut W,
+    id: u64,
+) -> Option<mpsc::UnboundedSender<DaemonEvent>> {
+    let tx = pending.lock().ok()?.remove(&id)?;
+    let line = format!("{}\n", serde_json::json!({ "id": id, "cancel": true }));
+    let _ = stdin.write_all(line.as_bytes()).await;
+    Some(tx)
+}
+
+fn register(
+    pending: &Pending,
+    id: u64,
+    tx: mpsc::UnboundedSender<DaemonEvent>,
+) -> Result<(), String> {
+    pending
+        .lock()
+        .map_err(|_| "Internal state error".to_string())?
+        .insert(id, tx);
+    Ok(())
+}
+
+fn dispatch(pending: &Pending, line: &str) {
+    let Ok(msg) = serde_json::from_str::<DaemonLine>(line) else {
+        log::warn!("Ignoring malformed bundler output: {line}");
+        return;
+    };
+    let Ok(pending) = pending.lock() else {
+        return;
+    };
+    let Some(tx) = pending.get(&msg.id) else {
+        return;
+    };
+    let event = if msg.progress {
+        match msg.message {
+            Some(message) => DaemonEvent::Progress(message),
+            None => return,
+        }
+    } else if let Some(packages) = msg.approve {
+        DaemonEvent::Approval(packages)
+    } else if msg.ok == Some(true) {
+        DaemonEvent::Done(Ok(BundleOutput {
+            code: msg.code.unwrap_or_default(),
+            inputs: msg.inputs,
+            packages: msg.packages,
+            package_dirs: msg.package_dirs,
+            ..BundleOutput::default()
+        }))
+    } else {
+        let error = msg.error.map(BundleError::from_payload).unwrap_or_else(|| {
+            BundleError::Unknown(ErrorDetails::new("Bundler failed without an error payload"))
+        });
+        DaemonEvent::Done(Err(error))
+    };
+    let _ = tx.send(event);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pending_with(id: u64) -> (Pending, mpsc::UnboundedReceiver<DaemonEvent>) {
+        let pending: Pending = Arc::default();
+        let (tx, rx) = mpsc::unbounded_channel();
+        pending.lock().unwrap().insert(id, tx);
+        (pending, rx)
+    }
+
+    #[test]
+    fn dispatch_routes_progress_by_id() {
+        let (pending, mut rx) = pending_with(7);
+        dispatch(
+            &pending,
+            r#"{"id":7,"progress":true,"phase":"bundling","message":"Bundling component..."}"#,
+        );
+        match rx.try_recv() {
+            Ok(DaemonEvent::Progress(msg)) => assert_eq!(msg, "Bundling component..."),
+            _ => panic!("expected a progress event"),
+        }
+    }
+
+    #[test]
+    fn dispatch_delivers_success() {
+        let (pending, mut rx) = pending_with(1);
+        dispatch(
+            &pending,
+            r#"{"id":1,"ok":true,"code":"console.log(1)","inputs":["/tmp/App.tsx"],"packages":{"react":"18.3.1"},"packageDirs":{"react":"/tmp/node_modules/react"}}"#,
+        );
+        match rx.try_recv() {
+            Ok(DaemonEvent::Done(Ok(output))) => {
+                assert_eq!(output.code, "console.log(1)");
+                assert_eq!(output.inputs, vec![PathBuf::from("/tmp/App.tsx")]);
+                assert_eq!(output.packages["react"], "18.3.1");
+                assert_eq!(
+                    output.package_dirs["react"],
+                    PathBuf::from("/tmp/node_modules/react")
+                );
+            }
+            _ => panic!("expected a successful result"),
+        }
+    }
+
+    #[test]
+    fn dispatch_delivers_approval_requests() {
+        let (pending, mut rx) = pending_with(2);
+        dispatch(&pending, r#"{"id":2,"approve":["left-pad","@acme/ui"]}"#);
+        match rx.try_recv() {
+            Ok(DaemonEvent::Approval(packages)) => {
+                assert_eq!(packages, vec!["left-pad", "@acme/ui"])
+            }
+            _ => panic!("expected an approval request"),
+        }
+    }
+
+    #[test]
+    fn dispatch_delivers_typed_error() {
+        let (pending, mut rx) = pending_with(2);
+        dispatch(
+            &pending,
+            r#"{"id":2,"ok":false,"error":{"error":true,"type":"syntax","message":"boom","errors":[]}}"#,
+        );
+        match rx.try_recv() {
+            Ok(DaemonEvent::Done(Err(BundleError::Syntax(details)))) => {
+                assert_eq!(details.message, "boom");
+            }
+            _ => panic!("expected a syntax error result"),
+        }
+    }
+
+    #[tokio::test]
+    async fn approval_wait_ends_when_the_daemon_dies() {
+        let (pending, mut rx) = pending_with(4);
+        dispatch(
+            &pending,
+            r#"{"id":4,"progress":true,"message":"Resolving..."}"#,
+        );
+        // What the reader does once the daemon's stdout closes.
+        pending.lock().unwrap().clear();
+        let progress = Mutex::new(Vec::new());
+        let outcome = await_approval(&mut rx, std::future::pending::<bool>(), |msg| {
+            progress.lock().unwrap().push(msg.to_string())
+        })
+        .await;
+        assert!(matches!(outcome, Err(Err(BundleError::SpawnFailed(_)))));
+        assert_eq!(*progress.lock().unwrap(), vec!["Resolving..."]);
+    }
+
+    #[tokio::test]
+    async fn approval_wait_returns_the_answer() {
+        let (_pending, mut rx) = pending_with(5);
+        let outcome = await_approval(&mut rx, async { true }, |_| {}).await;
+        assert!(matches!(outcome, Ok(true)));
+    }
+
+    #[tokio::test]
+    async fn aborted_requests_are_cancelled_and_their_late_lines_dropped() {
+        let (pending, mut rx) = pending_with(6);
+        let mut stdin = Vec::new();
+        assert!(abort_request(&pending, &mut stdin, 6).await.is_some());
+        let line: serde_json::Value = serde_json::from_slice(&stdin).unwrap();
+        assert_eq!(line, serde_json::json!({ "id": 6, "cancel": true }));
+
+        // The daemon finishes the request anyway; nobody hears about it.
+        dispatch(&pending, r#"{"id":6,"ok":true,"code":""}"#);
+        assert!(rx.try_recv().is_err());
+
+        // Aborting twice writes nothing more.
+        let mut again = Vec::new();
+        assert!(abort_request(&pending, &mut again, 6).await.is_none());
+        assert!(again.is_empty());
+    }
+
+    #[test]
+    fn dispatch_ignores_unknown_ids_and_garbage() {
+        let (pending, mut rx) = pending_with(3);
+        dispatch(&pending, r#"{"id":99,"ok":true,"code":""}"#);
+        dispatch(&pending, "not json");
+        assert!(rx.try_recv().is_err());
+    }
+}
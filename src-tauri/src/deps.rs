@@ -0,0 +1,530 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::sync::Mutex;
+
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+
+use crate::bundler::{bundler_script_path, cache_dir, managed};
+use crate::recent::BundleVersions;
+
+/// A package installed into the shared cache.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct InstalledPackage {
+    pub name: String,
+    /// Empty if the package is listed but not actually installed.
+    pub version: String,
+    /// Bytes on disk.
+    pub size: u64,
+}
+
+/// A locked package an upgrade would move to another version.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct VersionChange {
+    pub name: String,
+    pub from: String,
+    pub to: String,
+    /// How many recently bundled files were built with the package.
+    #[serde(default)]
+    pub used_by: usize,
+}
+
+/// The changes the last [`outdated`] check offered. Upgrades are limited
+/// to these, so a window can't have arbitrary packages installed without
+/// going through install approval.
+#[derive(Default)]
+pub struct UpgradePreview {
+    changes: Mutex<Vec<VersionChange>>,
+}
+
+impl UpgradePreview {
+    fn offer(&self, changes: &[VersionChange]) {
+        if let Ok(mut offered) = self.changes.lock() {
+            *offered = changes.to_vec();
+        }
+    }
+
+    /// The offered changes matching `wanted`, or an error if any of them
+    /// wasn't offered as is.
+    fn accept(&self, wanted: &[VersionChange]) -> Result<Vec<VersionChange>, String> {
+        let offered = self
+            .changes
+            .lock()
+            .map_err(|_| "Internal state error".to_string())?;
+        wanted
+            .iter()
+            .map(|change| {
+                offered
+                    .iter()
+                    .find(|o| o.name == change.name && o.from == change.from && o.to == change.to)
+                    .cloned()
+                    .ok_or_else(|| {
+                        format!(
+                            "{}@{} wasn't offered as an upgrade; check for upgrades again.",
+                            change.name, change.to
+                        )
+                    })
+            })
+            .collect()
+    }
+
+    /// Stops offering `changes`, once applied.
+    fn applied(&self, changes: &[VersionChange]) {
+        if let Ok(mut offered) = self.changes.lock() {
+            offered.retain(|o| !changes.contains(o));
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Removed {
+    #[serde(default)]
+    removed: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Outdated {
+    changes: Vec<VersionChange>,
+}
+
+#[derive(Deserialize)]
+struct Upgraded {
+    upgraded: Vec<String>,
+}
+
+/// Packages installed into `cache` on purpose (the dependencies in its
+/// `package.json`, not what they pulled in), sorted by name.
+pub fn installed_packages(cache: &Path) -> Vec<InstalledPackage> {
+    let Ok(data) = std::fs::read_to_string(cache.join("package.json")) else {
+        return Vec::new();
+    };
+    let Ok(manifest) = serde_json::from_str::<serde_json::Value>(&data) else {
+        return Vec::new();
+    };
+    let Some(dependencies) = manifest.get("dependencies").and_then(|d| d.as_object()) else {
+        return Vec::new();
+    };
+    let node_modules = cache.join("node_modules");
+    let mut packages: Vec<InstalledPackage> = dependencies
+        .keys()
+        .map(|name| InstalledPackage {
+            name: name.clone(),
+            version: crate::cache::installed_version(&node_modules, name).unwrap_or_default(),
+            size: disk_size(&node_modules.join(name)),
+        })
+        .collect();
+    packages.sort_by(|a, b| a.name.cmp(&b.name));
+    packages
+}
+
+/// Total size of the files under `path`. The package directory itself may
+/// be a symlink (pnpm links packages into its store); links inside it are
+/// not followed.
+fn disk_size(path: &Path) -> u64 {
+    let Ok(meta) = std::fs::metadata(path) else {
+        return 0;
+    };
+    if !meta.is_dir() {
+        return meta.len();
+    }
+    let mut total = 0;
+    let mut dirs = vec![path.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            let Ok(meta) = entry.path().symlink_metadata() else {
+                continue;
+            };
+            if meta.is_dir() {
+                dirs.push(entry.path());
+            } else if meta.is_file() {
+                total += meta.len();
+            }
+        }
+    }
+    total
+}
+
+/// Packages used by the files bundled lately, as recorded with each bundle
+/// (see [`crate::recent::record_bundle_versions`]), plus those in the cached
+/// bundles of recently opened files. The bundle cache alone isn't enough:
+/// it evicts old bundles.
+pub fn recently_used() -> BTreeSet<String> {
+    let root = crate::cache::bundle_cache_dir();
+    let cached: BTreeSet<String> = crate::recent::read_recent()
+        .iter()
+        .filter_map(|recent| crate::cache::cached_packages(&root, Path::new(&recent.path)))
+        .flat_map(|packages| packages.into_keys())
+        .collect();
+    let mut used = used_by_existing(&crate::recent::read_bundle_versions(), |file| {
+        Path::new(file).exists()
+    });
+    used.extend(cached);
+    used
+}
+
+/// Packages the bundles of the files in `bundles` that still `exist` used.
+fn used_by_existing(
+    bundles: &BTreeMap<String, BundleVersions>,
+    exists: impl Fn(&str) -> bool,
+) -> BTreeSet<String> {
+    bundles
+        .iter()
+        .filter(|(file, _)| exists(file))
+        .flat_map(|(_, bundle)| bundle.packages.keys().cloned())
+        .collect()
+}
+
+/// Fills in how many of the files in `bundles` were built with each
+/// changed package.
+fn count_users(changes: &mut [VersionChange], bundles: &BTreeMap<String, BundleVersions>) {
+    for change in changes {
+        change.used_by = bundles
+            .values()
+            .filter(|b| b.packages.contains_key(&change.name))
+            .count();
+    }
+}
+
+/// Runs a maintenance mode of `bundler.mjs` (`--uninstall`, `--prune`,
+/// `--reseed`, `--outdated` or `--upgrade`) against the shared cache,
+/// forwarding its progress as `deps-progress` events. Returns its result.
+async fn run_maintenance<R: tauri::Runtime, T: DeserializeOwned>(
+    app_handle: &tauri::AppHandle<R>,
+    mode: &str,
+    args: &[String],
+) -> Result<T, String> {
+    use tauri::Emitter;
+
+    let script = bundler_script_path(app_handle)?;
+    let node = managed::<crate::node::NodeCache, R>(app_handle)
+        .get(None)?
+        .path;
+    let path_env = crate::node::path_env(&node);
+    let package_manager = crate::package_manager::choose(
+        crate::settings::read_settings().package_manager,
+        &crate::package_manager::available(&path_env),
+    );
+
+    let mut child = tokio::process::Command::new(&node)
+        .arg(&script)
+        .arg(mode)
+        .args(args)
+        .env("PATH", &path_env)
+        .env("TERRARIUM_PACKAGE_MANAGER", package_manager.name())
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("Failed to run bundler: {e}"))?;
+
+    if let Some(stderr) = child.stderr.take() {
+        let mut lines = BufReader::new(stderr).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            let message = serde_json::from_str::<serde_json::Value>(&line)
+                .ok()
+                .filter(|v| v["progress"] == true)
+                .and_then(|v| v["message"].as_str().map(str::to_string));
+            match message {
+                Some(message) => {
+                    let _ = app_handle.emit("deps-progress", message);
+                }
+                None => log::info!("{line}"),
+            }
+        }
+    }
+    let mut stdout = String::new();
+    if let Some(mut out) = child.stdout.take() {
+        let _ = out.read_to_string(&mut stdout).await;
+    }
+    let _ = child.wait().await;
+
+    parse_result(&stdout)
+}
+
+/// Runs a maintenance mode that changes the shared cache, then tells the
+/// frontend with a `deps-changed` event.
+async fn modify<R: tauri::Runtime, T: DeserializeOwned>(
+    app_handle: &tauri::AppHandle<R>,
+    mode: &str,
+    args: &[String],
+) -> Result<T, String> {
+    use tauri::Emitter;
+
+    let result = run_maintenance(app_handle, mode, args).await;
+    if result.is_ok() {
+        let _ = app_handle.emit("deps-changed", ());
+    }
+    result
+}
+
+/// Parses what a maintenance run printed: `{"ok": true, ...}` or a bundler
+/// error payload.
+fn parse_result<T: DeserializeOwned>(stdout: &str) -> Result<T, String> {
+    let value: serde_json::Value = serde_json::from_str(stdout.trim())
+        .map_err(|_| format!("Unexpected bundler output: {stdout}"))?;
+    if value["ok"] == true {
+        return serde_json::from_value(value)
+            .map_err(|_| format!("Unexpected bundler output: {stdout}"));
+    }
+    Err(value["message"]
+        .as_str()
+        .unwrap_or("Package cache maintenance failed")
+        .to_string())
+}
+
+/// Uninstalls `packages` from the shared cache.
+pub async fn remove<R: tauri::Runtime>(
+    app_handle: &tauri::AppHandle<R>,
+    packages: &[String],
+) -> Result<Vec<String>, String> {
+    modify(app_handle, "--uninstall", packages)
+        .await
+        .map(|r: Removed| r.removed)
+}
+
+/// Uninstalls every package that no recently bundled file uses, apart from
+/// the base set, and removes isolated environments nothing uses any more.
+pub async fn prune<R: tauri::Runtime>(
+    app_handle: &tauri::AppHandle<R>,
+) -> Result<Vec<String>, String> {
+    let keep: Vec<String> = recently_used().into_iter().collect();
+    let Removed { removed } = modify(app_handle, "--prune", &keep).await?;
+    let envs = tauri::async_runtime::spawn_blocking(|| {
+        crate::envs::collect_garbage(crate::envs::MAX_ENV_AGE)
+    })
+    .await
+    .unwrap_or_default();
+    if !envs.is_empty() {
+        log::info!(
+            "Removed unused dependency environments: {}",
+            envs.join(", ")
+        );
+    }
+    Ok(removed)
+}
+
+/// Wipes the shared cache's packages and installs the base set again.
+pub async fn reseed<R: tauri::Runtime>(app_handle: &tauri::AppHandle<R>) -> Result<(), String> {
+    modify(app_handle, "--reseed", &[])
+        .await
+        .map(|_: Removed| ())
+}
+
+/// Checks the registry for newer versions of the locked packages, without
+/// changing anything.
+pub async fn outdated<R: tauri::Runtime>(
+    app_handle: &tauri::AppHandle<R>,
+) -> Result<Vec<VersionChange>, String> {
+    let Outdated { mut changes } = run_maintenance(app_handle, "--outdated", &[]).await?;
+    let bundles = tauri::async_runtime::spawn_blocking(crate::recent::read_bundle_versions)
+        .await
+        .unwrap_or_default();
+    count_users(&mut changes, &bundles);
+    managed::<UpgradePreview, R>(app_handle).offer(&changes);
+    Ok(changes)
+}
+
+/// Installs exactly the versions in `changes` and locks them. Only changes
+/// the last [`outdated`] check offered are accepted.
+pub async fn upgrade<R: tauri::Runtime>(
+    app_handle: &tauri::AppHandle<R>,
+    changes: &[VersionChange],
+) -> Result<Vec<String>, String> {
+    let preview = managed::<UpgradePreview, R>(app_handle);
+    let accepted = preview.accept(changes)?;
+    let specs: Vec<String> = accepted
+        .iter()
+        .map(|c| format!("{}@{}", c.name, c.to))
+        .collect();
+    let Upgraded { upgraded } = modify(app_handle, "--upgrade", &specs).await?;
+    preview.applied(&accepted);
+    Ok(upgraded)
+}
+
+#[tauri::command]
+pub async fn deps_list() -> Vec<InstalledPackage> {
+    tauri::async_runtime::spawn_blocking(|| installed_packages(&cache_dir()))
+        .await
+        .unwrap_or_default()
+}
+
+#[tauri::command]
+pub async fn deps_remove(
+    app: tauri::AppHandle,
+    packages: Vec<String>,
+) -> Result<Vec<String>, String> {
+    remove(&app, &packages).await
+}
+
+#[tauri::command]
+pub async fn deps_prune(app: tauri::AppHandle) -> Result<Vec<String>, String> {
+    prune(&app).await
+}
+
+#[tauri::command]
+pub async fn deps_reseed(app: tauri::AppHandle) -> Result<(), String> {
+    reseed(&app).await
+}
+
+#[tauri::command]
+pub async fn deps_outdated(app: tauri::AppHandle) -> Result<Vec<VersionChange>, String> {
+    outdated(&app).await
+}
+
+#[tauri::command]
+pub async fn deps_upgrade(
+    app: tauri::AppHandle,
+    changes: Vec<VersionChange>,
+) -> Result<Vec<String>, String> {
+    upgrade(&app, &changes).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn install(cache: &Path, name: &str, version: &str, body: &[u8]) {
+        let dir = cache.join("node_modules").join(name);
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        std::fs::write(
+            dir.join("package.json"),
+            format!(r#"{{"name":"{name}","version":"{version}"}}"#),
+        )
+        .unwrap();
+        std::fs::write(dir.join("lib/index.js"), body).unwrap();
+    }
+
+    #[test]
+    fn lists_direct_dependencies_with_versions_and_sizes() {
+        let cache = tempfile::TempDir::new().unwrap();
+        std::fs::write(
+            cache.path().join("package.json"),
+            r#"{"dependencies": {"react": "^18", "@acme/ui": "^1", "gone": "^1"}}"#,
+        )
+        .unwrap();
+        install(cache.path(), "react", "18.3.1", &[0; 100]);
+        install(cache.path(), "@acme/ui", "1.2.0", &[0; 10]);
+        // Pulled in by something else, not installed on purpose.
+        install(cache.path(), "loose-envify", "1.4.0", &[0; 10]);
+
+        let packages = installed_packages(cache.path());
+        let names: Vec<&str> = packages.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["@acme/ui", "gone", "react"]);
+        assert_eq!(packages[0].version, "1.2.0");
+        assert_eq!(packages[1].version, "");
+        assert_eq!(packages[1].size, 0);
+        let react_manifest = r#"{"name":"react","version":"18.3.1"}"#.len() as u64;
+        assert_eq!(packages[2].size, 100 + react_manifest);
+    }
+
+    #[test]
+    fn missing_package_json_lists_nothing() {
+        let cache = tempfile::TempDir::new().unwrap();
+        assert!(installed_packages(cache.path()).is_empty());
+    }
+
+    #[test]
+    fn parses_maintenance_results() {
+        let removed = |stdout| parse_result(stdout).map(|r: Removed| r.removed);
+        assert_eq!(
+            removed(r#"{"ok":true,"removed":["left-pad"]}"#),
+            Ok(vec!["left-pad".to_string()])
+        );
+        assert_eq!(
+            removed(
+                r#"{"error":true,"type":"unknown","message":"react is part of the base package set."}"#
+            ),
+            Err("react is part of the base package set.".to_string())
+        );
+        assert!(removed("").is_err());
+    }
+
+    fn change(name: &str, from: &str, to: &str) -> VersionChange {
+        VersionChange {
+            name: name.to_string(),
+            from: from.to_string(),
+            to: to.to_string(),
+            used_by: 0,
+        }
+    }
+
+    #[test]
+    fn upgrades_are_limited_to_the_offered_changes() {
+        let preview = UpgradePreview::default();
+        assert!(preview.accept(&[change("left-pad", "", "1.3.0")]).is_err());
+
+        preview.offer(&[
+            change("recharts", "2.12.7", "2.15.0"),
+            change("zod", "3.22.0", "3.23.8"),
+        ]);
+        assert_eq!(
+            preview.accept(&[change("zod", "3.22.0", "3.23.8")]),
+            Ok(vec![change("zod", "3.22.0", "3.23.8")])
+        );
+        // Not offered, or offered at another version.
+        assert!(preview
+            .accept(&[
+                change("zod", "3.22.0", "3.23.8"),
+                change("evil", "", "1.0.0")
+            ])
+            .is_err());
+        assert!(preview.accept(&[change("zod", "3.22.0", "9.9.9")]).is_err());
+
+        preview.applied(&[change("zod", "3.22.0", "3.23.8")]);
+        assert!(preview
+            .accept(&[change("zod", "3.22.0", "3.23.8")])
+            .is_err());
+        assert!(preview
+            .accept(&[change("recharts", "2.12.7", "2.15.0")])
+            .is_ok());
+    }
+
+    #[test]
+    fn packages_of_deleted_files_are_not_used() {
+        let bundle = |names: &[&str]| BundleVersions {
+            packages: names
+                .iter()
+                .map(|n| (n.to_string(), "1.0.0".to_string()))
+                .collect(),
+            bundled_at: String::new(),
+        };
+        let bundles = BTreeMap::from([
+            ("/a/Chart.tsx".to_string(), bundle(&["react", "recharts"])),
+            ("/a/Old.tsx".to_string(), bundle(&["left-pad"])),
+        ]);
+        let used = used_by_existing(&bundles, |file| file != "/a/Old.tsx");
+        assert_eq!(
+            used,
+            BTreeSet::from(["react".to_string(), "recharts".to_string()])
+        );
+    }
+
+    #[test]
+    fn counts_recent_bundles_using_each_change() {
+        let outdated: Outdated = parse_result(
+            r#"{"ok":true,"changes":[{"name":"recharts","from":"2.12.7","to":"2.15.0"},{"name":"zod","from":"3.22.0","to":"3.23.8"}]}"#,
+        )
+        .unwrap();
+        let mut changes = outdated.changes;
+        let bundle = |names: &[&str]| BundleVersions {
+            packages: names
+                .iter()
+                .map(|n| (n.to_string(), "1.0.0".to_string()))
+                .collect(),
+            bundled_at: String::new(),
+        };
+        let bundles = BTreeMap::from([
+            ("/a/Chart.tsx".to_string(), bundle(&["react", "recharts"])),
+            ("/a/Sales.tsx".to_string(), bundle(&["recharts"])),
+            ("/a/Form.tsx".to_string(), bundle(&["react"])),
+        ]);
+        count_users(&mut changes, &bundles);
+        assert_eq!(changes[0].used_by, 2);
+        assert_eq!(changes[1].used_by, 0);
+    }
+}
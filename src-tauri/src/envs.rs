@@ -0,0 +1,308 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::bundler::cache_dir;
+use crate::pins::{Pin, PinState};
+
+/// Environments no artifact has used for this long are collected.
+pub const MAX_ENV_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+/// Environment directories younger than this are never collected, so one
+/// being installed for its first bundle isn't removed before it is recorded.
+const MIN_ENV_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// An isolated dependency directory for artifacts whose pins conflict with
+/// the shared cache. Packages it doesn't have still resolve from the cache.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Environment {
+    pub key: String,
+    pub dir: PathBuf,
+    /// Install specs, e.g. `recharts@2.12.7`, sorted.
+    pub packages: Vec<String>,
+}
+
+/// Where an artifact's pinned dependencies get installed.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Plan {
+    /// Pins installed into the shared cache, which doesn't have them yet.
+    pub shared: Vec<String>,
+    /// Pins the shared cache has at another version, installed apart.
+    pub env: Option<Environment>,
+}
+
+/// Returns the directory environments live in: `~/.terrarium/envs`.
+pub fn envs_dir() -> PathBuf {
+    cache_dir().join("envs")
+}
+
+/// Returns the path to the environment usage file:
+/// `~/.terrarium/environments.json`.
+fn usage_path() -> PathBuf {
+    cache_dir().join("environments.json")
+}
+
+/// Decides where `pins` are installed. Pins the project or the shared cache
+/// already meets need nothing; missing ones go into the shared cache; ones
+/// the shared cache has at another version go into an environment keyed by
+/// exactly that set of specs, so artifacts agreeing on them share it.
+pub fn plan(pins: &[Pin], project: Option<&Path>, shared_modules: &Path, root: &Path) -> Plan {
+    let project_modules = project.map(|p| p.join("node_modules"));
+    let mut plan = Plan::default();
+    let mut isolated = Vec::new();
+    for pin in pins {
+        match crate::pins::state(pin, project_modules.as_deref(), shared_modules) {
+            PinState::Met | PinState::ProjectDiffers => {}
+            PinState::Missing => plan.shared.push(pin.install_spec()),
+            PinState::CacheDiffers => isolated.push(pin.install_spec()),
+        }
+    }
+    if !isolated.is_empty() {
+        isolated.sort();
+        let key = env_key(&isolated);
+        plan.env = Some(Environment {
+            dir: root.join(&key),
+            key,
+            packages: isolated,
+        });
+    }
+    plan
+}
+
+fn env_key(specs: &[String]) -> String {
+    let digest = Sha256::digest(specs.join("\n").as_bytes());
+    format!("{digest:x}")[..16].to_string()
+}
+
+/// Which environment each artifact last bundled with, by artifact path.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+struct Usage {
+    env: String,
+    /// Seconds since the Unix epoch.
+    used_at: u64,
+}
+
+fn read_usage(path: &Path) -> BTreeMap<String, Usage> {
+    let Ok(data) = std::fs::read_to_string(path) else {
+        return BTreeMap::new();
+    };
+    serde_json::from_str(&data).unwrap_or_default()
+}
+
+fn write_usage(path: &Path, usage: &BTreeMap<String, Usage>) {
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    if let Ok(json) = serde_json::to_string_pretty(usage) {
+        let _ = std::fs::write(path, json);
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Records that `artifact` was bundled with environment `env`, or with the
+/// shared cache only (best-effort).
+pub fn record_use(artifact: &Path, env: Option<&Environment>) {
+    record_use_in(&usage_path(), artifact, env);
+}
+
+fn record_use_in(path: &Path, artifact: &Path, env: Option<&Environment>) {
+    let mut usage = read_usage(path);
+    let artifact = artifact.to_string_lossy().into_owned();
+    match env {
+        Some(env) => {
+            usage.insert(
+                artifact,
+                Usage {
+                    env: env.key.clone(),
+                    used_at: now_secs(),
+                },
+            );
+        }
+        None => {
+            if usage.remove(&artifact).is_none() {
+                return;
+            }
+        }
+    }
+    write_usage(path, &usage);
+}
+
+/// Removes environments no artifact uses any more: usage records go once
+/// their artifact is deleted or hasn't been bundled for `max_age`, then
+/// every environment directory without a record is deleted. Returns the
+/// keys of the removed environments.
+pub fn collect_garbage(max_age: Duration) -> Vec<String> {
+    collect_garbage_in(&usage_path(), &envs_dir(), max_age)
+}
+
+fn collect_garbage_in(usage_file: &Path, root: &Path, max_age: Duration) -> Vec<String> {
+    let mut usage = read_usage(usage_file);
+    let before = usage.len();
+    let now = now_secs();
+    usage.retain(|artifact, u| {
+        Path::new(artifact).exists() && now.saturating_sub(u.used_at) <= max_age.as_secs()
+    });
+    if usage.len() != before {
+        write_usage(usage_file, &usage);
+    }
+
+    let Ok(entries) = std::fs::read_dir(root) else {
+        return Vec::new();
+    };
+    let mut removed = Vec::new();
+    for entry in entries.filter_map(|e| e.ok()) {
+        let key = entry.file_name().to_string_lossy().into_owned();
+        if usage.values().any(|u| u.env == key) {
+            continue;
+        }
+        let fresh = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .is_ok_and(|m| m.elapsed().unwrap_or_default() < MIN_ENV_AGE);
+        if fresh {
+            continue;
+        }
+        if std::fs::remove_dir_all(entry.path()).is_ok() {
+            removed.push(key);
+        }
+    }
+    removed.sort();
+    removed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn install(modules: &Path, name: &str, version: &str) {
+        let package = modules.join(name);
+        std::fs::create_dir_all(&package).unwrap();
+        std::fs::write(
+            package.join("package.json"),
+            format!(r#"{{"version": "{version}"}}"#),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn conflicting_pins_get_an_environment() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let shared = dir.path().join("node_modules");
+        install(&shared, "recharts", "2.15.0");
+        install(&shared, "react", "18.3.1");
+        install(&shared, "date-fns", "4.1.0");
+
+        let pins =
+            crate::pins::parse("// @terrarium deps: recharts@2.12.7 react@18 zod@3 date-fns@2\n");
+        let plan = plan(&pins, None, &shared, &dir.path().join("envs"));
+        assert_eq!(plan.shared, vec!["zod@3".to_string()]);
+        let env = plan.env.unwrap();
+        assert_eq!(
+            env.packages,
+            vec!["date-fns@2".to_string(), "recharts@2.12.7".to_string()]
+        );
+        assert_eq!(env.dir, dir.path().join("envs").join(&env.key));
+    }
+
+    #[test]
+    fn same_conflicts_share_an_environment() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let shared = dir.path().join("node_modules");
+        install(&shared, "recharts", "2.15.0");
+        let root = dir.path().join("envs");
+
+        let a = plan(
+            &crate::pins::parse("// @terrarium deps: recharts@2.12.7 react@18\n"),
+            None,
+            &shared,
+            &root,
+        );
+        let b = plan(
+            &crate::pins::parse("// @terrarium deps: recharts@2.12.7\n"),
+            None,
+            &shared,
+            &root,
+        );
+        let c = plan(
+            &crate::pins::parse("// @terrarium deps: recharts@2.12.6\n"),
+            None,
+            &shared,
+            &root,
+        );
+        assert_eq!(a.env, b.env);
+        assert_ne!(a.env.unwrap().key, c.env.unwrap().key);
+    }
+
+    #[test]
+    fn no_environment_without_conflicts() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let shared = dir.path().join("node_modules");
+        install(&shared, "recharts", "2.12.7");
+        let pins = crate::pins::parse("// @terrarium deps: recharts@2.12.7\n");
+        assert_eq!(
+            plan(&pins, None, &shared, &dir.path().join("envs")),
+            Plan::default()
+        );
+    }
+
+    #[test]
+    fn garbage_collection_keeps_environments_in_use() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let usage = dir.path().join("environments.json");
+        let root = dir.path().join("envs");
+        let artifact = dir.path().join("Chart.tsx");
+        std::fs::write(&artifact, "").unwrap();
+        let gone = dir.path().join("Deleted.tsx");
+
+        let env = |key: &str| Environment {
+            key: key.to_string(),
+            dir: root.join(key),
+            packages: Vec::new(),
+        };
+        for key in ["used", "orphaned", "unused"] {
+            std::fs::create_dir_all(root.join(key)).unwrap();
+        }
+        record_use_in(&usage, &artifact, Some(&env("used")));
+        record_use_in(&usage, &gone, Some(&env("orphaned")));
+
+        // Everything is brand new, so nothing goes yet.
+        assert!(collect_garbage_in(&usage, &root, MAX_ENV_AGE).is_empty());
+
+        let old = SystemTime::now() - 2 * MIN_ENV_AGE;
+        for key in ["used", "orphaned", "unused"] {
+            std::fs::File::open(root.join(key))
+                .unwrap()
+                .set_modified(old)
+                .unwrap();
+        }
+        assert_eq!(
+            collect_garbage_in(&usage, &root, MAX_ENV_AGE),
+            vec!["orphaned".to_string(), "unused".to_string()]
+        );
+        assert!(root.join("used").exists());
+        assert_eq!(read_usage(&usage).len(), 1);
+    }
+
+    #[test]
+    fn bundling_without_an_environment_drops_the_record() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let usage = dir.path().join("environments.json");
+        let artifact = dir.path().join("Chart.tsx");
+        let env = Environment {
+            key: "abc".to_string(),
+            dir: dir.path().join("abc"),
+            packages: Vec::new(),
+        };
+        record_use_in(&usage, &artifact, Some(&env));
+        assert_eq!(read_usage(&usage).len(), 1);
+        record_use_in(&usage, &artifact, None);
+        assert!(read_usage(&usage).is_empty());
+    }
+}
@@ -1,8 +1,21 @@
+pub mod approvals;
 pub mod bundler;
+pub mod cache;
+pub mod daemon;
+pub mod deps;
+pub mod envs;
+pub mod node;
+pub mod package_manager;
+pub mod pins;
+pub mod project;
 pub mod recent;
+pub mod registry;
+pub mod seed;
+pub mod settings;
 pub mod storage;
 pub mod watcher;
 
+use bundler::BundleError;
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::sync::Mutex;
@@ -24,7 +37,21 @@ fn is_supported_ext(path: &Path) -> bool {
 
 pub struct WindowState {
     pub file: PathBuf,
-    pub watcher: Option<notify::RecommendedWatcher>,
+    /// The project whose `node_modules` this file's bundles resolve from,
+    /// if it lives inside one.
+    pub project: Option<PathBuf>,
+    pub watcher: Option<watcher::FileWatcher>,
+}
+
+impl WindowState {
+    /// State for a window showing `file`, not yet watched.
+    pub fn new(file: PathBuf) -> Self {
+        Self {
+            project: project::resolution_root(&file),
+            file,
+            watcher: None,
+        }
+    }
 }
 
 pub struct AppState {
@@ -42,13 +69,13 @@ async fn open_file(
     window: tauri::Window,
     state: State<'_, AppState>,
     path: String,
-) -> Result<String, String> {
+) -> Result<String, BundleError> {
     let tsx_path = PathBuf::from(&path);
     if !tsx_path.exists() {
-        return Err(format!("File not found: {path}"));
+        return Err(format!("File not found: {path}").into());
     }
     if !is_supported_ext(&tsx_path) {
-        return Err(format!("Not a TSX/JSX file: {path}"));
+        return Err(format!("Not a TSX/JSX file: {path}").into());
     }
 
     let label = window.label().to_string();
@@ -57,14 +84,18 @@ async fn open_file(
     if let Some(wv) = app.get_webview_window(&label) {
         set_file_path_on_window(&wv, &path);
     }
+    storage::adopt_file(&app.state::<storage::StorageDb>(), &tsx_path);
 
-    let bundle_result = bundler::bundle_tsx(&app, &tsx_path).await;
+    let bundle_result = bundler::bundle_for_window(&app, &tsx_path, &label).await;
 
     if bundle_result.is_ok() {
         recent::record_recent(&path);
     }
 
     let watcher = watcher::watch_file(app.clone(), tsx_path.clone(), label.clone()).ok();
+    if let (Some(watcher), Ok(bundle)) = (&watcher, &bundle_result) {
+        watcher.set_inputs(&bundle.inputs);
+    }
 
     state
         .windows
@@ -73,12 +104,12 @@ async fn open_file(
         .insert(
             label,
             WindowState {
-                file: tsx_path,
                 watcher,
+                ..WindowState::new(tsx_path)
             },
         );
 
-    bundle_result
+    bundle_result.map(|bundle| bundle.code)
 }
 
 #[tauri::command]
@@ -86,7 +117,7 @@ async fn pick_and_open_files(
     app: tauri::AppHandle,
     window: tauri::Window,
     state: State<'_, AppState>,
-) -> Result<String, String> {
+) -> Result<String, BundleError> {
     use tauri_plugin_dialog::DialogExt;
     let picked = app
         .dialog()
@@ -96,7 +127,7 @@ async fn pick_and_open_files(
 
     let files = picked.ok_or_else(|| "No file selected".to_string())?;
     if files.is_empty() {
-        return Err("No file selected".to_string());
+        return Err("No file selected".to_string().into());
     }
 
     let paths: Vec<PathBuf> = files
@@ -105,7 +136,7 @@ async fn pick_and_open_files(
         .collect();
 
     if paths.is_empty() {
-        return Err("No file selected".to_string());
+        return Err("No file selected".to_string().into());
     }
 
     let label = window.label().to_string();
@@ -132,13 +163,7 @@ async fn pick_and_open_files(
         let _ = new_window.set_title(&format!("{filename} — Terrarium"));
 
         if let Ok(mut windows) = state.windows.lock() {
-            windows.insert(
-                new_label.clone(),
-                WindowState {
-                    file: tsx_path.clone(),
-                    watcher: None,
-                },
-            );
+            windows.insert(new_label.clone(), WindowState::new(tsx_path.clone()));
         }
 
         spawn_bundle_and_watch(app.clone(), tsx_path.clone(), new_label);
@@ -149,7 +174,7 @@ async fn pick_and_open_files(
             let path_str = path.to_string_lossy().to_string();
             open_file(app, window, state, path_str).await
         }
-        None => Err("No file selected".to_string()),
+        None => Err("No file selected".to_string().into()),
     }
 }
 
@@ -158,7 +183,7 @@ async fn request_bundle(
     app: tauri::AppHandle,
     window: tauri::Window,
     state: State<'_, AppState>,
-) -> Result<String, String> {
+) -> Result<String, BundleError> {
     let label = window.label().to_string();
     let path = state
         .windows
@@ -167,7 +192,13 @@ async fn request_bundle(
         .get(&label)
         .map(|ws| ws.file.clone())
         .ok_or_else(|| "No file loaded".to_string())?;
-    bundler::bundle_tsx(&app, &path).await
+    let bundle = bundler::bundle_for_window(&app, &path, &label).await?;
+    if let Ok(windows) = state.windows.lock() {
+        if let Some(watcher) = windows.get(&label).and_then(|ws| ws.watcher.as_ref()) {
+            watcher.set_inputs(&bundle.inputs);
+        }
+    }
+    Ok(bundle.code)
 }
 
 fn supported_paths_from_urls(urls: &[tauri::Url]) -> Vec<PathBuf> {
@@ -187,13 +218,42 @@ fn next_label(state: &AppState) -> String {
 
 /// Sets `window.__TERRARIUM_FILE_PATH__` on a webview so the storage shim
 /// knows which file's storage namespace to use.
-fn set_file_path_on_window(window: &tauri::WebviewWindow, path: &str) {
+fn set_file_path_on_window<R: tauri::Runtime>(window: &tauri::WebviewWindow<R>, path: &str) {
     let _ = window.eval(format!(
         "window.__TERRARIUM_FILE_PATH__ = {};",
         serde_json::to_string(path).unwrap_or_default()
     ));
 }
 
+/// Shows the outcome of a menu action: `Ok` as information under `title`,
+/// `Err` as an error.
+pub(crate) fn show_result<R: tauri::Runtime>(
+    app_handle: &tauri::AppHandle<R>,
+    title: &str,
+    result: Result<String, String>,
+) {
+    use tauri_plugin_dialog::DialogExt;
+
+    let (message, title, kind) = match result {
+        Ok(message) => (
+            message,
+            title.to_string(),
+            tauri_plugin_dialog::MessageDialogKind::Info,
+        ),
+        Err(e) => (
+            e,
+            format!("{title} Error"),
+            tauri_plugin_dialog::MessageDialogKind::Error,
+        ),
+    };
+    app_handle
+        .dialog()
+        .message(message)
+        .title(title)
+        .kind(kind)
+        .blocking_show();
+}
+
 fn create_window(app: &tauri::AppHandle, label: &str) -> Result<tauri::WebviewWindow, String> {
     tauri::WebviewWindowBuilder::new(app, label, tauri::WebviewUrl::App("index.html".into()))
         .title("Terrarium")
@@ -203,23 +263,34 @@ fn create_window(app: &tauri::AppHandle, label: &str) -> Result<tauri::WebviewWi
         .map_err(|e| format!("Failed to create window: {e}"))
 }
 
-fn spawn_bundle_and_watch(app: tauri::AppHandle, path: PathBuf, label: String) {
+fn spawn_bundle_and_watch<R: tauri::Runtime>(
+    app: tauri::AppHandle<R>,
+    path: PathBuf,
+    label: String,
+) {
     tauri::async_runtime::spawn(async move {
-        match bundler::bundle_tsx(&app, &path).await {
+        storage::adopt_file(&app.state::<storage::StorageDb>(), &path);
+        let mut inputs = Vec::new();
+        match bundler::bundle_for_window(&app, &path, &label).await {
             Ok(bundle) => {
+                inputs = bundle.inputs;
                 recent::record_recent(&path.to_string_lossy());
                 if let Some(w) = app.get_webview_window(&label) {
                     let filename = path.file_name().unwrap_or_default().to_string_lossy();
                     let _ = w.set_title(&format!("{filename} — Terrarium"));
                     set_file_path_on_window(&w, &path.to_string_lossy());
                 }
-                let _ = app.emit_to(&label, "bundle-ready", bundle);
+                let _ = app.emit_to(&label, "bundle-ready", bundle.code);
             }
+            Err(err) if err.is_cancelled() => {}
             Err(err) => {
                 let _ = app.emit_to(&label, "bundle-error", err);
             }
         }
         let watcher = watcher::watch_file(app.clone(), path.clone(), label.clone()).ok();
+        if let Some(watcher) = &watcher {
+            watcher.set_inputs(&inputs);
+        }
         let state = app.state::<AppState>();
         if let Ok(mut windows) = state.windows.lock() {
             if let Some(ws) = windows.get_mut(&label) {
@@ -229,6 +300,37 @@ fn spawn_bundle_and_watch(app: tauri::AppHandle, path: PathBuf, label: String) {
     });
 }
 
+/// Keeps a window on its file after the file was renamed or moved from `from`
+/// to `to`: its storage goes along, and the window reloads from the new path.
+pub(crate) fn follow_rename<R: tauri::Runtime>(
+    app: &tauri::AppHandle<R>,
+    label: &str,
+    from: &Path,
+    to: &Path,
+) {
+    let db = app.state::<storage::StorageDb>();
+    if let Err(e) = db.repoint(&from.to_string_lossy(), &to.to_string_lossy()) {
+        log::warn!("Failed to move storage of {}: {e}", from.display());
+    }
+    let state = app.state::<AppState>();
+    let Ok(mut windows) = state.windows.lock() else {
+        return;
+    };
+    let Some(ws) = windows.get_mut(label) else {
+        return;
+    };
+    *ws = WindowState::new(to.to_path_buf());
+    drop(windows);
+    // The storage shim has to send the new path right away: storage calls
+    // claiming the old one are refused, and the rebundle may fail.
+    if let Some(w) = app.get_webview_window(label) {
+        let filename = to.file_name().unwrap_or_default().to_string_lossy();
+        let _ = w.set_title(&format!("{filename} — Terrarium"));
+        set_file_path_on_window(&w, &to.to_string_lossy());
+    }
+    spawn_bundle_and_watch(app.clone(), to.to_path_buf(), label.to_string());
+}
+
 #[tauri::command]
 async fn open_in_new_windows(
     app: tauri::AppHandle,
@@ -246,13 +348,7 @@ async fn open_in_new_windows(
         let _ = window.set_title(&format!("{filename} — Terrarium"));
 
         if let Ok(mut windows) = state.windows.lock() {
-            windows.insert(
-                label.clone(),
-                WindowState {
-                    file: tsx_path.clone(),
-                    watcher: None,
-                },
-            );
+            windows.insert(label.clone(), WindowState::new(tsx_path.clone()));
         }
 
         spawn_bundle_and_watch(app.clone(), tsx_path, label);
@@ -260,21 +356,38 @@ async fn open_in_new_windows(
     Ok(())
 }
 
-#[tauri::command]
-fn check_node() -> Result<serde_json::Value, String> {
-    let (path, version) = bundler::check_node_availability()?;
-    let major: u32 = version
-        .trim_start_matches('v')
-        .split('.')
-        .next()
-        .and_then(|s| s.parse().ok())
-        .unwrap_or(0);
-    Ok(serde_json::json!({
-        "path": path,
-        "version": version,
+fn node_status(node: node::NodeInfo) -> serde_json::Value {
+    let major = node.major();
+    serde_json::json!({
+        "path": node.path,
+        "strategy": node.strategy,
+        "version": node.version,
         "major": major,
         "supported": major >= 18,
-    }))
+    })
+}
+
+#[tauri::command]
+fn check_node(node_cache: State<'_, node::NodeCache>) -> Result<serde_json::Value, String> {
+    Ok(node_status(node_cache.get(None)?))
+}
+
+/// Forgets the cached Node.js binary and runs discovery again, e.g. after
+/// the user installs or switches Node versions.
+#[tauri::command]
+fn redetect_node(node_cache: State<'_, node::NodeCache>) -> Result<serde_json::Value, String> {
+    Ok(node_status(node_cache.redetect()?))
+}
+
+/// Answers the `install-approval` question a bundle asked this window.
+#[tauri::command]
+fn answer_install_approval(
+    app: tauri::AppHandle,
+    window: tauri::Window,
+    request: u64,
+    approved: bool,
+) -> bool {
+    bundler::answer_install_approval(&app, window.label(), request, approved)
 }
 
 #[tauri::command]
@@ -344,9 +457,13 @@ pub fn run() {
             next_window_id: Mutex::new(2),
         })
         .manage(UpdateState { pending_update: Mutex::new(None) })
+        .manage(daemon::BundlerDaemon::new())
+        .manage(node::NodeCache::default())
         .manage({
             let db_path = bundler::cache_dir().join("storage.db");
-            storage::StorageDb::open(&db_path).expect("Failed to open storage database")
+            storage::StorageDb::open(&db_path)
+                .expect("Failed to open storage database")
+                .with_quota(settings::read_settings().storage_quota)
         })
         .invoke_handler(tauri::generate_handler![
             open_file,
@@ -354,6 +471,8 @@ pub fn run() {
             request_bundle,
             open_in_new_windows,
             check_node,
+            redetect_node,
+            answer_install_approval,
             is_first_run,
             mark_first_run_complete,
             get_recent_files,
@@ -362,6 +481,15 @@ pub fn run() {
             storage::storage_get,
             storage::storage_set,
             storage::storage_remove,
+            storage::storage_list,
+            storage::storage_export,
+            storage::storage_import,
+            deps::deps_list,
+            deps::deps_remove,
+            deps::deps_prune,
+            deps::deps_reseed,
+            deps::deps_outdated,
+            deps::deps_upgrade,
         ])
         .menu(|handle| {
             let open_item = tauri::menu::MenuItemBuilder::with_id("open-file", "Open...")
@@ -370,6 +498,25 @@ pub fn run() {
             let file_menu = SubmenuBuilder::new(handle, "File")
                 .item(&open_item)
                 .separator()
+                .item(
+                    &tauri::menu::MenuItemBuilder::with_id("show-storage", "Storage\u{2026}")
+                        .build(handle)?,
+                )
+                .item(
+                    &tauri::menu::MenuItemBuilder::with_id(
+                        "export-all-storage",
+                        "Export All Storage\u{2026}",
+                    )
+                    .build(handle)?,
+                )
+                .item(
+                    &tauri::menu::MenuItemBuilder::with_id(
+                        "import-all-storage",
+                        "Import All Storage\u{2026}",
+                    )
+                    .build(handle)?,
+                )
+                .separator()
                 .close_window()
                 .build()?;
             let edit_menu = SubmenuBuilder::new(handle, "Edit")
@@ -381,6 +528,37 @@ pub fn run() {
                 .paste()
                 .select_all()
                 .build()?;
+            let packages_menu = SubmenuBuilder::new(handle, "Packages")
+                .item(
+                    &tauri::menu::MenuItemBuilder::with_id(
+                        "installed-packages",
+                        "Installed Packages\u{2026}",
+                    )
+                    .build(handle)?,
+                )
+                .item(
+                    &tauri::menu::MenuItemBuilder::with_id(
+                        "upgrade-deps",
+                        "Upgrade Dependencies\u{2026}",
+                    )
+                    .build(handle)?,
+                )
+                .separator()
+                .item(
+                    &tauri::menu::MenuItemBuilder::with_id(
+                        "export-seed",
+                        "Export Seed Pack\u{2026}",
+                    )
+                    .build(handle)?,
+                )
+                .item(
+                    &tauri::menu::MenuItemBuilder::with_id(
+                        "import-seed",
+                        "Import Seed Pack\u{2026}",
+                    )
+                    .build(handle)?,
+                )
+                .build()?;
             let window_menu = SubmenuBuilder::new(handle, "Window").minimize().build()?;
             let help_menu = SubmenuBuilder::new(handle, "Help")
                 .item(
@@ -410,6 +588,7 @@ pub fn run() {
                 .item(&app_menu)
                 .item(&file_menu)
                 .item(&edit_menu)
+                .item(&packages_menu)
                 .item(&window_menu)
                 .item(&help_menu)
                 .build()
@@ -417,6 +596,27 @@ pub fn run() {
         .on_menu_event(|app, event| {
             if event.id().as_ref() == "open-file" {
                 let _ = app.emit("menu-open-file", ());
+            } else if event.id().as_ref() == "installed-packages" {
+                let _ = app.emit("menu-show-deps", ());
+            } else if event.id().as_ref() == "upgrade-deps" {
+                let _ = app.emit("menu-upgrade-deps", ());
+            } else if event.id().as_ref() == "export-seed" {
+                tauri::async_runtime::spawn(seed::export_with_dialog(app.clone()));
+            } else if event.id().as_ref() == "import-seed" {
+                tauri::async_runtime::spawn(seed::import_with_dialog(app.clone()));
+            } else if event.id().as_ref() == "show-storage" {
+                // Storage belongs to one file, so only the focused window opens its panel.
+                if let Some(window) = app
+                    .webview_windows()
+                    .into_values()
+                    .find(|w| w.is_focused().unwrap_or(false))
+                {
+                    let _ = app.emit_to(window.label(), "menu-show-storage", ());
+                }
+            } else if event.id().as_ref() == "export-all-storage" {
+                tauri::async_runtime::spawn(storage::export_all_with_dialog(app.clone()));
+            } else if event.id().as_ref() == "import-all-storage" {
+                tauri::async_runtime::spawn(storage::import_all_with_dialog(app.clone()));
             } else if event.id().as_ref() == "documentation" {
                 use tauri_plugin_opener::OpenerExt;
                 if let Err(e) = app.opener().open_url("https://github.com/michellemayes/terrarium", None::<&str>) {
@@ -473,6 +673,12 @@ pub fn run() {
             }
         })
         .setup(|app| {
+            tauri::async_runtime::spawn_blocking(|| {
+                let removed = envs::collect_garbage(envs::MAX_ENV_AGE);
+                if !removed.is_empty() {
+                    log::info!("Removed unused dependency environments: {}", removed.join(", "));
+                }
+            });
             let app_handle = app.handle().clone();
             tauri::async_runtime::spawn(async move {
                 use tauri_plugin_updater::UpdaterExt;
@@ -493,6 +699,9 @@ pub fn run() {
             Ok(())
         })
         .on_window_event(|window, event| {
+            if let tauri::WindowEvent::Focused(true) = event {
+                bundler::focus_window(window.app_handle(), window.label());
+            }
             if let tauri::WindowEvent::Destroyed = event {
                 let app = window.app_handle();
                 let state = app.state::<AppState>();
@@ -500,11 +709,20 @@ pub fn run() {
                 if let Ok(mut windows) = state.windows.lock() {
                     windows.remove(&label);
                 };
+                let app = app.clone();
+                tauri::async_runtime::spawn(async move {
+                    bundler::forget_window(&app, &label).await;
+                });
             }
         })
         .build(tauri::generate_context!())
         .expect("error while building tauri application")
         .run(|app, event| {
+            if let tauri::RunEvent::Exit = event {
+                let daemon = app.state::<daemon::BundlerDaemon>();
+                tauri::async_runtime::block_on(daemon.shutdown());
+                return;
+            }
             if let tauri::RunEvent::Opened { urls } = event {
                 let tsx_paths = supported_paths_from_urls(&urls);
                 if tsx_paths.is_empty() {
@@ -533,10 +751,7 @@ pub fn run() {
                         if let Ok(mut windows) = state.windows.lock() {
                             windows.insert(
                                 "main".to_string(),
-                                WindowState {
-                                    file: tsx_path.clone(),
-                                    watcher: None,
-                                },
+                                WindowState::new(tsx_path.clone()),
                             );
                         }
                         if let Some(window) = app.get_webview_window("main") {
@@ -564,10 +779,7 @@ pub fn run() {
                         if let Ok(mut windows) = state.windows.lock() {
                             windows.insert(
                                 label.clone(),
-                                WindowState {
-                                    file: tsx_path.clone(),
-                                    watcher: None,
-                                },
+                                WindowState::new(tsx_path.clone()),
                             );
                         }
                         let filename = tsx_path.file_name().unwrap_or_default().to_string_lossy();
@@ -600,13 +812,11 @@ mod tests {
             next_window_id: Mutex::new(2),
         };
         let path = PathBuf::from("/tmp/test.tsx");
-        state.windows.lock().unwrap().insert(
-            "main".to_string(),
-            WindowState {
-                file: path.clone(),
-                watcher: None,
-            },
-        );
+        state
+            .windows
+            .lock()
+            .unwrap()
+            .insert("main".to_string(), WindowState::new(path.clone()));
         let windows = state.windows.lock().unwrap();
         assert_eq!(windows.get("main").unwrap().file, path);
     }
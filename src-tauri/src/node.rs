@@ -0,0 +1,957 @@
+use semver::{Version, VersionReq};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Environment variable that pins the Node.js binary, bypassing discovery.
+pub const NODE_OVERRIDE_VAR: &str = "TERRARIUM_NODE";
+
+const NODE_BIN: &str = if cfg!(windows) { "node.exe" } else { "node" };
+
+/// How a Node.js binary was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Strategy {
+    /// `TERRARIUM_NODE` pointed at it.
+    Override,
+    /// Found on the app's own `PATH`.
+    Path,
+    /// A system or official-installer location (Homebrew, `/usr/bin`,
+    /// `Program Files`).
+    System,
+    Volta,
+    Nvm,
+    Fnm,
+    Asdf,
+    Mise,
+    Nodenv,
+    N,
+    /// Resolved by asking the user's login shell.
+    LoginShell,
+}
+
+/// A discovered Node.js binary and the strategy that found it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeInstall {
+    pub path: PathBuf,
+    pub strategy: Strategy,
+}
+
+/// A discovered Node.js binary together with its version.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NodeInfo {
+    pub path: PathBuf,
+    pub strategy: Strategy,
+    pub version: String,
+}
+
+impl NodeInfo {
+    /// The major version, e.g. `20` for `v20.11.0`; 0 if unparseable.
+    pub fn major(&self) -> u32 {
+        self.version
+            .trim_start_matches('v')
+            .split('.')
+            .next()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(0)
+    }
+}
+
+/// Discovery results shared by every bundle and `check_node`, keyed by the
+/// version requirement they satisfy, so a login shell is spawned at most
+/// once rather than on every rebuild.
+#[derive(Default)]
+pub struct NodeCache {
+    resolved: Mutex<HashMap<Option<String>, NodeInfo>>,
+}
+
+impl NodeCache {
+    /// Returns the Node to use for an artifact in `dir` (or the default Node
+    /// if `None`), honoring any `.nvmrc`, `.node-version` or `engines.node`
+    /// found by walking up from it. Detects Node first if nothing is cached
+    /// or the cached binary has disappeared. Failures are not cached, so
+    /// installing Node is picked up on the next call.
+    pub fn get(&self, dir: Option<&Path>) -> Result<NodeInfo, String> {
+        let requirement = dir.and_then(requirement_for);
+        let key = requirement.as_ref().map(|r| r.spec.clone());
+        self.get_or_detect(key, || detect_for(requirement.as_ref()))
+    }
+
+    /// Forgets every cached Node and detects the default one again.
+    pub fn redetect(&self) -> Result<NodeInfo, String> {
+        if let Ok(mut resolved) = self.resolved.lock() {
+            resolved.clear();
+        }
+        self.get(None)
+    }
+
+    fn get_or_detect(
+        &self,
+        key: Option<String>,
+        detect: impl FnOnce() -> Result<NodeInfo, String>,
+    ) -> Result<NodeInfo, String> {
+        let mut resolved = self
+            .resolved
+            .lock()
+            .map_err(|_| "Internal state error".to_string())?;
+        if let Some(info) = resolved.get(&key).filter(|info| info.path.is_file()) {
+            return Ok(info.clone());
+        }
+        let info = detect()?;
+        resolved.insert(key, info.clone());
+        Ok(info)
+    }
+}
+
+/// Runs discovery and asks the binary for its version.
+pub fn detect() -> Result<NodeInfo, String> {
+    let NodeInstall { path, strategy } = find_node()?;
+    let version = node_version(&path)?;
+    log::info!(
+        "Using Node.js {version} at {} ({strategy:?})",
+        path.display()
+    );
+    Ok(NodeInfo {
+        path,
+        strategy,
+        version,
+    })
+}
+
+/// Like [`detect`], but picks an installed version satisfying
+/// `requirement`. `TERRARIUM_NODE` still wins. Falls back to the default
+/// Node if it happens to match; otherwise errors naming the requirement and
+/// what is installed.
+fn detect_for(requirement: Option<&NodeRequirement>) -> Result<NodeInfo, String> {
+    let Some(requirement) = requirement else {
+        return detect();
+    };
+    let env = Env::current();
+    if env.var(NODE_OVERRIDE_VAR).is_some() {
+        return detect();
+    }
+
+    let installed = installed_versions(&env);
+    if let Some((_, path, strategy)) = installed
+        .iter()
+        .filter(|(version, _, _)| requirement.matches(version))
+        .max_by(|a, b| a.0.cmp(&b.0))
+    {
+        let version = node_version(path)?;
+        log::info!(
+            "Using Node.js {version} at {} ({strategy:?}) for {}",
+            path.display(),
+            requirement.source.display()
+        );
+        return Ok(NodeInfo {
+            path: path.clone(),
+            strategy: *strategy,
+            version,
+        });
+    }
+
+    let default = detect().ok();
+    if let Some(default) = default.as_ref() {
+        if parse_version(&default.version).is_some_and(|v| requirement.matches(&v)) {
+            return Ok(default.clone());
+        }
+    }
+
+    let mut found: Vec<String> = installed.iter().map(|(v, _, _)| format!("v{v}")).collect();
+    if let Some(default) = default {
+        found.push(default.version);
+    }
+    found.sort();
+    found.dedup();
+    let found = if found.is_empty() {
+        "none".to_string()
+    } else {
+        found.join(", ")
+    };
+    Err(format!(
+        "Node.js {} is required by {}, but it is not installed (found: {found}). \
+         Install it with nvm, fnm or asdf, e.g. `nvm install {}`.",
+        requirement.spec,
+        requirement.source.display(),
+        requirement.spec,
+    ))
+}
+
+/// A Node version an artifact asks for, and the file that asked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeRequirement {
+    /// The version as written, e.g. `18` or `>=18 <21`.
+    pub spec: String,
+    pub source: PathBuf,
+    /// Alternatives (`||`); any one matching is enough.
+    ranges: Vec<VersionReq>,
+}
+
+impl NodeRequirement {
+    pub fn matches(&self, version: &Version) -> bool {
+        self.ranges.iter().any(|range| range.matches(version))
+    }
+}
+
+/// Walks up from `dir` to the nearest `.nvmrc`, `.node-version` or
+/// `package.json` with `engines.node`, checked in that order per directory.
+/// Aliases like `lts/*` or `node` don't constrain the version and are
+/// ignored, as are ranges that can't be parsed.
+pub fn requirement_for(dir: &Path) -> Option<NodeRequirement> {
+    for dir in dir.ancestors() {
+        for name in [".nvmrc", ".node-version"] {
+            let source = dir.join(name);
+            let Ok(contents) = std::fs::read_to_string(&source) else {
+                continue;
+            };
+            let spec = contents.lines().next().unwrap_or("").trim().to_string();
+            return version_ranges(&spec).map(|ranges| NodeRequirement {
+                spec,
+                source,
+                ranges,
+            });
+        }
+
+        let source = dir.join("package.json");
+        let engines = std::fs::read_to_string(&source)
+            .ok()
+            .and_then(|data| serde_json::from_str::<serde_json::Value>(&data).ok())
+            .and_then(|pkg| Some(pkg.get("engines")?.get("node")?.as_str()?.to_string()));
+        if let Some(spec) = engines {
+            return semver_ranges(&spec).map(|ranges| NodeRequirement {
+                spec,
+                source,
+                ranges,
+            });
+        }
+    }
+    None
+}
+
+/// Parses an `.nvmrc` / `.node-version` entry: a full or partial version,
+/// with or without a leading `v`. `18` means any 18.x.
+pub(crate) fn version_ranges(spec: &str) -> Option<Vec<VersionReq>> {
+    let version = spec.trim().trim_start_matches('v');
+    if version.is_empty() || !version.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    Some(vec![VersionReq::parse(&format!("={version}")).ok()?])
+}
+
+/// Parses an npm-style range such as `^18 || ^20`, `>=18 <21` or
+/// `18 - 20`.
+pub(crate) fn semver_ranges(spec: &str) -> Option<Vec<VersionReq>> {
+    spec.split("||")
+        .map(|range| {
+            // A hyphen range includes both ends; a partial upper end such as
+            // `20` takes in all of 20.x, as `<=20` does for the semver crate.
+            let range = match range.split_once(" - ") {
+                Some((low, high)) => format!(">={}, <={}", low.trim(), high.trim()),
+                None => comparators(range).join(", "),
+            };
+            VersionReq::parse(&range).ok()
+        })
+        .collect()
+}
+
+/// Splits the space-separated comparators of one npm range, such as
+/// `>= 18 < 21`, keeping an operator with the version after it (the semver
+/// crate wants them comma-separated and `>=, 18` doesn't parse).
+fn comparators(range: &str) -> Vec<String> {
+    let mut comparators: Vec<String> = Vec::new();
+    let mut operator = String::new();
+    for token in range.split_whitespace() {
+        if token
+            .chars()
+            .all(|c| matches!(c, '<' | '>' | '=' | '~' | '^'))
+        {
+            operator.push_str(token);
+        } else {
+            comparators.push(std::mem::take(&mut operator) + token);
+        }
+    }
+    // A trailing operator is left for the parser to reject.
+    if !operator.is_empty() {
+        comparators.push(operator);
+    }
+    comparators
+}
+
+fn parse_version(version: &str) -> Option<Version> {
+    Version::parse(version.trim().trim_start_matches('v')).ok()
+}
+
+/// Every version installed by a version manager, with its binary.
+fn installed_versions(env: &Env) -> Vec<(Version, PathBuf, Strategy)> {
+    let mut installed = Vec::new();
+    for candidate in candidates(env) {
+        let Location::Versions { dir, bin } = &candidate.location else {
+            continue;
+        };
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            continue;
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            let node = entry.path().join(bin);
+            let version = parse_version(&entry.file_name().to_string_lossy());
+            if let (Some(version), true) = (version, node.is_file()) {
+                installed.push((version, node, candidate.strategy));
+            }
+        }
+    }
+    installed
+}
+
+/// Finds Node.js: `TERRARIUM_NODE` if set, then `PATH`, then the
+/// conventional locations for this platform, then the user's login shell.
+pub fn find_node() -> Result<NodeInstall, String> {
+    let env = Env::current();
+    if let Some(found) = discover(&env)? {
+        return Ok(found);
+    }
+    if let Some(path) = find_node_via_shell() {
+        return Ok(NodeInstall {
+            path,
+            strategy: Strategy::LoginShell,
+        });
+    }
+    Err("Node.js not found. Install it from https://nodejs.org".to_string())
+}
+
+pub fn node_version(node_path: &Path) -> Result<String, String> {
+    let output = std::process::Command::new(node_path)
+        .arg("--version")
+        .output()
+        .map_err(|e| format!("Failed to run node: {e}"))?;
+    if !output.status.success() {
+        return Err("Could not determine Node.js version".to_string());
+    }
+    let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    Ok(version)
+}
+
+/// `PATH` for processes running under `node`: its own directory first (so
+/// `npm` and friends from the same install win), then the app's `PATH`.
+pub fn path_env(node: &Path) -> OsString {
+    let mut dirs = vec![node.parent().unwrap_or(Path::new("")).to_path_buf()];
+    match std::env::var_os("PATH") {
+        Some(existing) => dirs.extend(std::env::split_paths(&existing)),
+        None if cfg!(windows) => {}
+        None => dirs.extend([PathBuf::from("/usr/bin"), PathBuf::from("/bin")]),
+    }
+    dirs.retain(|d| !d.as_os_str().is_empty());
+    std::env::join_paths(dirs).unwrap_or_default()
+}
+
+/// The bits of the process environment discovery looks at, so tests can
+/// point it at a fake home directory.
+struct Env {
+    home: PathBuf,
+    vars: HashMap<String, OsString>,
+}
+
+impl Env {
+    fn current() -> Self {
+        Self {
+            home: dirs::home_dir().unwrap_or_default(),
+            vars: std::env::vars_os()
+                .filter_map(|(k, v)| Some((k.into_string().ok()?, v)))
+                .collect(),
+        }
+    }
+
+    fn var(&self, key: &str) -> Option<&OsStr> {
+        self.vars
+            .get(key)
+            .map(|v| v.as_os_str())
+            .filter(|v| !v.is_empty())
+    }
+
+    /// `$key` as a directory, or `default` if unset.
+    fn dir(&self, key: &str, default: PathBuf) -> PathBuf {
+        self.var(key).map(PathBuf::from).unwrap_or(default)
+    }
+
+    /// The XDG data directory: `$XDG_DATA_HOME` or `~/.local/share`.
+    fn data_home(&self) -> PathBuf {
+        self.dir("XDG_DATA_HOME", self.home.join(".local/share"))
+    }
+}
+
+enum Location {
+    /// A fixed path to the binary.
+    Binary(PathBuf),
+    /// A version manager's install directory: one subdirectory per version,
+    /// with the binary at `<version>/<bin>`. The newest version wins.
+    Versions { dir: PathBuf, bin: &'static str },
+}
+
+struct Candidate {
+    strategy: Strategy,
+    location: Location,
+}
+
+impl Candidate {
+    fn binary(strategy: Strategy, path: PathBuf) -> Self {
+        Self {
+            strategy,
+            location: Location::Binary(path),
+        }
+    }
+
+    fn versions(strategy: Strategy, dir: PathBuf, bin: &'static str) -> Self {
+        Self {
+            strategy,
+            location: Location::Versions { dir, bin },
+        }
+    }
+
+    fn resolve(&self) -> Option<PathBuf> {
+        match &self.location {
+            Location::Binary(path) => path.is_file().then(|| path.clone()),
+            Location::Versions { dir, bin } => find_latest_node_in(dir, bin),
+        }
+    }
+}
+
+/// Runs every strategy except the login shell. An override that doesn't
+/// exist is an error rather than silently falling through to another Node.
+fn discover(env: &Env) -> Result<Option<NodeInstall>, String> {
+    if let Some(path) = env.var(NODE_OVERRIDE_VAR).map(PathBuf::from) {
+        if !path.is_file() {
+            return Err(format!(
+                "{NODE_OVERRIDE_VAR} is set to {}, which does not exist",
+                path.display()
+            ));
+        }
+        return Ok(Some(NodeInstall {
+            path,
+            strategy: Strategy::Override,
+        }));
+    }
+
+    if let Some(path) = env.var("PATH").and_then(find_on_path) {
+        return Ok(Some(NodeInstall {
+            path,
+            strategy: Strategy::Path,
+        }));
+    }
+
+    Ok(candidates(env).iter().find_map(|candidate| {
+        Some(NodeInstall {
+            path: candidate.resolve()?,
+            strategy: candidate.strategy,
+        })
+    }))
+}
+
+fn find_on_path(path: &OsStr) -> Option<PathBuf> {
+    std::env::split_paths(path)
+        .map(|dir| dir.join(NODE_BIN))
+        .find(|node| node.is_file())
+}
+
+/// Conventional install locations for this platform, in the order they are
+/// tried.
+fn candidates(env: &Env) -> Vec<Candidate> {
+    if cfg!(windows) {
+        windows_candidates(env)
+    } else {
+        unix_candidates(env)
+    }
+}
+
+fn unix_candidates(env: &Env) -> Vec<Candidate> {
+    let home = &env.home;
+    let mut list = Vec::new();
+
+    let system: &[&str] = if cfg!(target_os = "macos") {
+        // Homebrew (Apple Silicon + Intel), official installer
+        &["/opt/homebrew/bin/node", "/usr/local/bin/node"]
+    } else {
+        &["/usr/local/bin/node", "/usr/bin/node", "/snap/bin/node"]
+    };
+    for path in system {
+        list.push(Candidate::binary(Strategy::System, PathBuf::from(path)));
+    }
+
+    list.push(Candidate::binary(
+        Strategy::Volta,
+        env.dir("VOLTA_HOME", home.join(".volta")).join("bin/node"),
+    ));
+    list.push(Candidate::versions(
+        Strategy::Nvm,
+        env.dir("NVM_DIR", home.join(".nvm")).join("versions/node"),
+        "bin/node",
+    ));
+
+    let fnm_dirs = match env.var("FNM_DIR") {
+        Some(dir) => vec![PathBuf::from(dir)],
+        None if cfg!(target_os = "macos") => vec![
+            home.join("Library/Application Support/fnm"),
+            home.join(".fnm"),
+        ],
+        None => vec![env.data_home().join("fnm"), home.join(".fnm")],
+    };
+    for dir in fnm_dirs {
+        let versions = dir.join("node-versions");
+        list.push(Candidate::versions(
+            Strategy::Fnm,
+            versions.clone(),
+            "installation/bin/node",
+        ));
+        list.push(Candidate::versions(Strategy::Fnm, versions, "bin/node"));
+    }
+
+    list.push(Candidate::versions(
+        Strategy::Asdf,
+        env.dir("ASDF_DATA_DIR", home.join(".asdf"))
+            .join("installs/nodejs"),
+        "bin/node",
+    ));
+    list.push(Candidate::versions(
+        Strategy::Mise,
+        env.dir("MISE_DATA_DIR", env.data_home().join("mise"))
+            .join("installs/node"),
+        "bin/node",
+    ));
+    list.push(Candidate::versions(
+        Strategy::Nodenv,
+        env.dir("NODENV_ROOT", home.join(".nodenv"))
+            .join("versions"),
+        "bin/node",
+    ));
+    list.push(Candidate::versions(
+        Strategy::N,
+        env.dir("N_PREFIX", PathBuf::from("/usr/local"))
+            .join("n/versions/node"),
+        "bin/node",
+    ));
+    list
+}
+
+fn windows_candidates(env: &Env) -> Vec<Candidate> {
+    let home = &env.home;
+    let local = env.dir("LOCALAPPDATA", home.join("AppData/Local"));
+    let roaming = env.dir("APPDATA", home.join("AppData/Roaming"));
+    let mut list = Vec::new();
+
+    for var in ["ProgramFiles", "ProgramFiles(x86)"] {
+        if let Some(dir) = env.var(var) {
+            list.push(Candidate::binary(
+                Strategy::System,
+                PathBuf::from(dir).join("nodejs/node.exe"),
+            ));
+        }
+    }
+    list.push(Candidate::binary(
+        Strategy::Volta,
+        env.dir("VOLTA_HOME", local.join("Volta"))
+            .join("bin/node.exe"),
+    ));
+    // nvm-windows keeps versions directly under NVM_HOME as `v20.11.0\`.
+    list.push(Candidate::versions(
+        Strategy::Nvm,
+        env.dir("NVM_HOME", roaming.join("nvm")),
+        "node.exe",
+    ));
+    list.push(Candidate::versions(
+        Strategy::Fnm,
+        env.dir("FNM_DIR", roaming.join("fnm"))
+            .join("node-versions"),
+        "installation/node.exe",
+    ));
+    list.push(Candidate::versions(
+        Strategy::Mise,
+        env.dir("MISE_DATA_DIR", local.join("mise"))
+            .join("installs/node"),
+        "node.exe",
+    ));
+    list
+}
+
+/// Picks the newest version directory under `versions_dir` that contains
+/// `bin`.
+fn find_latest_node_in(versions_dir: &Path, bin: &str) -> Option<PathBuf> {
+    let entries = std::fs::read_dir(versions_dir).ok()?;
+    let mut versions: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.join(bin).is_file())
+        .collect();
+    // Sort by version number, not lexicographically (so v18 > v9)
+    versions.sort_by(|a, b| {
+        let parse = |p: &Path| -> Vec<u64> {
+            p.file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .trim_start_matches('v')
+                .split('.')
+                .filter_map(|s| s.parse().ok())
+                .collect()
+        };
+        parse(a).cmp(&parse(b))
+    });
+    versions.last().map(|p| p.join(bin))
+}
+
+fn find_node_via_shell() -> Option<PathBuf> {
+    if cfg!(windows) {
+        return None;
+    }
+    // Launch the user's login shell to get their full PATH,
+    // then use it to find node. This handles custom setups.
+    let default_shell = if cfg!(target_os = "macos") {
+        "/bin/zsh"
+    } else {
+        "/bin/sh"
+    };
+    let shell = std::env::var("SHELL").unwrap_or_else(|_| default_shell.to_string());
+    let output = std::process::Command::new(&shell)
+        .args(["-l", "-c", "command -v node"])
+        .output()
+        .ok()?;
+    // Login shells may print banners first; the path is the last line.
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).to_string())
+        .and_then(|out| out.lines().last().map(|l| l.trim().to_string()))
+        .filter(|p| !p.is_empty())
+        .map(PathBuf::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(home: &Path, vars: &[(&str, &Path)]) -> Env {
+        Env {
+            home: home.to_path_buf(),
+            vars: vars
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.as_os_str().to_os_string()))
+                .collect(),
+        }
+    }
+
+    fn touch(path: &Path) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, "").unwrap();
+    }
+
+    /// Resolves only the candidates for `strategy`, ignoring whatever is
+    /// installed on the machine running the tests.
+    fn resolve(env: &Env, strategy: Strategy) -> Option<PathBuf> {
+        candidates(env)
+            .iter()
+            .filter(|c| c.strategy == strategy)
+            .find_map(Candidate::resolve)
+    }
+
+    #[test]
+    fn override_wins() {
+        let home = tempfile::TempDir::new().unwrap();
+        let node = home.path().join("custom/node");
+        touch(&node);
+        let found = discover(&env(home.path(), &[(NODE_OVERRIDE_VAR, &node)]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.path, node);
+        assert_eq!(found.strategy, Strategy::Override);
+    }
+
+    #[test]
+    fn missing_override_is_an_error() {
+        let home = tempfile::TempDir::new().unwrap();
+        let missing = home.path().join("nope/node");
+        let err = discover(&env(home.path(), &[(NODE_OVERRIDE_VAR, &missing)])).unwrap_err();
+        assert!(err.contains(NODE_OVERRIDE_VAR));
+    }
+
+    #[test]
+    fn path_lookup_is_reported() {
+        let home = tempfile::TempDir::new().unwrap();
+        let bin = home.path().join("bin");
+        touch(&bin.join(NODE_BIN));
+        let found = discover(&env(home.path(), &[("PATH", &bin)]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.path, bin.join(NODE_BIN));
+        assert_eq!(found.strategy, Strategy::Path);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn nvm_picks_the_newest_version() {
+        let home = tempfile::TempDir::new().unwrap();
+        let versions = home.path().join(".nvm/versions/node");
+        touch(&versions.join("v9.11.2/bin/node"));
+        touch(&versions.join("v18.19.0/bin/node"));
+        assert_eq!(
+            resolve(&env(home.path(), &[]), Strategy::Nvm),
+            Some(versions.join("v18.19.0/bin/node"))
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn fnm_uses_its_installation_layout() {
+        let home = tempfile::TempDir::new().unwrap();
+        let fnm = home.path().join("fnm");
+        let node = fnm.join("node-versions/v20.11.0/installation/bin/node");
+        touch(&node);
+        assert_eq!(
+            resolve(&env(home.path(), &[("FNM_DIR", &fnm)]), Strategy::Fnm),
+            Some(node)
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn linux_finds_fnm_and_mise_under_xdg_data_home() {
+        let home = tempfile::TempDir::new().unwrap();
+        let fnm = home
+            .path()
+            .join(".local/share/fnm/node-versions/v20.0.0/installation/bin/node");
+        let mise = home
+            .path()
+            .join(".local/share/mise/installs/node/22.1.0/bin/node");
+        touch(&fnm);
+        touch(&mise);
+        let env = env(home.path(), &[]);
+        assert_eq!(resolve(&env, Strategy::Fnm), Some(fnm));
+        assert_eq!(resolve(&env, Strategy::Mise), Some(mise));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn nodenv_asdf_and_n_are_found() {
+        let home = tempfile::TempDir::new().unwrap();
+        let nodenv = home.path().join(".nodenv/versions/20.1.0/bin/node");
+        let asdf = home.path().join(".asdf/installs/nodejs/21.0.0/bin/node");
+        let n_prefix = home.path().join("n-prefix");
+        let n = n_prefix.join("n/versions/node/18.0.0/bin/node");
+        touch(&nodenv);
+        touch(&asdf);
+        touch(&n);
+        let env = env(home.path(), &[("N_PREFIX", &n_prefix)]);
+        assert_eq!(resolve(&env, Strategy::Nodenv), Some(nodenv));
+        assert_eq!(resolve(&env, Strategy::Asdf), Some(asdf));
+        assert_eq!(resolve(&env, Strategy::N), Some(n));
+    }
+
+    fn info(path: &Path) -> NodeInfo {
+        NodeInfo {
+            path: path.to_path_buf(),
+            strategy: Strategy::Path,
+            version: "v20.11.0".to_string(),
+        }
+    }
+
+    #[test]
+    fn cache_detects_once() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let node = dir.path().join("node");
+        touch(&node);
+        let cache = NodeCache::default();
+        assert_eq!(
+            cache.get_or_detect(None, || Ok(info(&node))),
+            Ok(info(&node))
+        );
+        let again = cache.get_or_detect(None, || panic!("should use the cached node"));
+        assert_eq!(again, Ok(info(&node)));
+    }
+
+    #[test]
+    fn cache_is_keyed_by_requirement() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let default = dir.path().join("default/node");
+        let pinned = dir.path().join("pinned/node");
+        touch(&default);
+        touch(&pinned);
+        let cache = NodeCache::default();
+        cache.get_or_detect(None, || Ok(info(&default))).unwrap();
+        assert_eq!(
+            cache.get_or_detect(Some("18".to_string()), || Ok(info(&pinned))),
+            Ok(info(&pinned))
+        );
+        assert_eq!(
+            cache.get_or_detect(None, || panic!("should use the cached node")),
+            Ok(info(&default))
+        );
+    }
+
+    #[test]
+    fn cache_redetects_when_binary_disappears() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let old = dir.path().join("old/node");
+        let new = dir.path().join("new/node");
+        touch(&old);
+        touch(&new);
+        let cache = NodeCache::default();
+        cache.get_or_detect(None, || Ok(info(&old))).unwrap();
+        std::fs::remove_file(&old).unwrap();
+        assert_eq!(cache.get_or_detect(None, || Ok(info(&new))), Ok(info(&new)));
+    }
+
+    #[test]
+    fn cache_does_not_remember_failures() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let node = dir.path().join("node");
+        touch(&node);
+        let cache = NodeCache::default();
+        assert!(cache
+            .get_or_detect(None, || Err("missing".to_string()))
+            .is_err());
+        assert_eq!(
+            cache.get_or_detect(None, || Ok(info(&node))),
+            Ok(info(&node))
+        );
+    }
+
+    fn v(version: &str) -> Version {
+        Version::parse(version).unwrap()
+    }
+
+    #[test]
+    fn nvmrc_major_matches_any_minor() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(dir.path().join(".nvmrc"), "v18\n").unwrap();
+        let req = requirement_for(dir.path()).unwrap();
+        assert_eq!(req.spec, "v18");
+        assert_eq!(req.source, dir.path().join(".nvmrc"));
+        assert!(req.matches(&v("18.19.0")));
+        assert!(!req.matches(&v("20.11.0")));
+    }
+
+    #[test]
+    fn node_version_file_pins_exactly() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(dir.path().join(".node-version"), "20.11.0").unwrap();
+        let req = requirement_for(dir.path()).unwrap();
+        assert!(req.matches(&v("20.11.0")));
+        assert!(!req.matches(&v("20.11.1")));
+    }
+
+    #[test]
+    fn nvmrc_aliases_do_not_constrain() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(dir.path().join(".nvmrc"), "lts/*").unwrap();
+        assert_eq!(requirement_for(dir.path()), None);
+    }
+
+    #[test]
+    fn engines_ranges_are_parsed() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(
+            dir.path().join("package.json"),
+            r#"{"engines": {"node": ">=18 <20 || ^22"}}"#,
+        )
+        .unwrap();
+        let req = requirement_for(dir.path()).unwrap();
+        assert!(req.matches(&v("18.2.0")));
+        assert!(!req.matches(&v("20.0.0")));
+        assert!(req.matches(&v("22.3.0")));
+    }
+
+    #[test]
+    fn engines_operators_may_be_followed_by_a_space() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(
+            dir.path().join("package.json"),
+            r#"{"engines": {"node": ">= 18 < 21"}}"#,
+        )
+        .unwrap();
+        let req = requirement_for(dir.path()).unwrap();
+        assert!(!req.matches(&v("17.9.0")));
+        assert!(req.matches(&v("18.0.0")));
+        assert!(req.matches(&v("20.11.1")));
+        assert!(!req.matches(&v("21.0.0")));
+    }
+
+    #[test]
+    fn engines_hyphen_ranges_include_both_ends() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(
+            dir.path().join("package.json"),
+            r#"{"engines": {"node": "18 - 20 || 22.1.0 - 22.3.0"}}"#,
+        )
+        .unwrap();
+        let req = requirement_for(dir.path()).unwrap();
+        assert!(!req.matches(&v("17.9.0")));
+        assert!(req.matches(&v("18.0.0")));
+        assert!(req.matches(&v("20.11.1")));
+        assert!(!req.matches(&v("21.0.0")));
+        assert!(req.matches(&v("22.3.0")));
+        assert!(!req.matches(&v("22.3.1")));
+    }
+
+    #[test]
+    fn nearest_requirement_wins() {
+        let root = tempfile::TempDir::new().unwrap();
+        let nested = root.path().join("components/charts");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::write(root.path().join(".nvmrc"), "18").unwrap();
+        std::fs::write(
+            root.path().join("components/package.json"),
+            r#"{"engines": {"node": "^20"}}"#,
+        )
+        .unwrap();
+        assert_eq!(requirement_for(&nested).unwrap().spec, "^20");
+        assert_eq!(requirement_for(root.path()).unwrap().spec, "18");
+    }
+
+    #[test]
+    fn package_json_without_engines_keeps_walking() {
+        let root = tempfile::TempDir::new().unwrap();
+        let nested = root.path().join("app");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::write(root.path().join(".nvmrc"), "18").unwrap();
+        std::fs::write(nested.join("package.json"), r#"{"name": "app"}"#).unwrap();
+        assert_eq!(requirement_for(&nested).unwrap().spec, "18");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn installed_versions_span_version_managers() {
+        let home = tempfile::TempDir::new().unwrap();
+        let nvm = home.path().join(".nvm/versions/node/v18.19.0/bin/node");
+        let asdf = home.path().join(".asdf/installs/nodejs/20.11.0/bin/node");
+        touch(&nvm);
+        touch(&asdf);
+        let n_prefix = home.path().join("n");
+        let mut installed = installed_versions(&env(home.path(), &[("N_PREFIX", &n_prefix)]));
+        installed.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            installed,
+            vec![
+                (v("18.19.0"), nvm, Strategy::Nvm),
+                (v("20.11.0"), asdf, Strategy::Asdf),
+            ]
+        );
+    }
+
+    #[test]
+    fn major_version_is_parsed() {
+        assert_eq!(info(Path::new("/x")).major(), 20);
+    }
+
+    #[test]
+    fn strategy_serializes_in_kebab_case() {
+        assert_eq!(
+            serde_json::to_value(Strategy::LoginShell).unwrap(),
+            "login-shell"
+        );
+    }
+
+    #[test]
+    fn path_env_puts_node_dir_first() {
+        let node = Path::new("/opt/node/bin").join(NODE_BIN);
+        let path = path_env(&node);
+        let first = std::env::split_paths(&path).next().unwrap();
+        assert_eq!(first, PathBuf::from("/opt/node/bin"));
+    }
+}
@@ -0,0 +1,121 @@
+use serde::{Deserialize, Serialize};
+use std::ffi::OsStr;
+
+/// A package manager the bundler can install dependencies with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PackageManager {
+    Npm,
+    Pnpm,
+    Yarn,
+    Bun,
+}
+
+impl PackageManager {
+    pub const ALL: [PackageManager; 4] = [Self::Npm, Self::Pnpm, Self::Yarn, Self::Bun];
+
+    /// The name the bundler knows it by.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Npm => "npm",
+            Self::Pnpm => "pnpm",
+            Self::Yarn => "yarn",
+            Self::Bun => "bun",
+        }
+    }
+
+    /// The executable's file name on this platform.
+    fn executable(self) -> &'static str {
+        match (self, cfg!(windows)) {
+            (Self::Npm, false) => "npm",
+            (Self::Npm, true) => "npm.cmd",
+            (Self::Pnpm, false) => "pnpm",
+            (Self::Pnpm, true) => "pnpm.cmd",
+            (Self::Yarn, false) => "yarn",
+            (Self::Yarn, true) => "yarn.cmd",
+            (Self::Bun, false) => "bun",
+            (Self::Bun, true) => "bun.exe",
+        }
+    }
+}
+
+/// Package managers found on `path_env` (the `PATH` the bundler runs with),
+/// in [`PackageManager::ALL`] order.
+pub fn available(path_env: &OsStr) -> Vec<PackageManager> {
+    PackageManager::ALL
+        .into_iter()
+        .filter(|pm| std::env::split_paths(path_env).any(|dir| dir.join(pm.executable()).is_file()))
+        .collect()
+}
+
+/// Picks the package manager to install with: `preferred` if it is
+/// available, otherwise npm (which ships with Node), otherwise whatever else
+/// is installed. Falls back to npm if nothing was found, so the bundler's
+/// error explains what's missing.
+pub fn choose(preferred: Option<PackageManager>, available: &[PackageManager]) -> PackageManager {
+    if let Some(preferred) = preferred {
+        if available.contains(&preferred) {
+            return preferred;
+        }
+        log::warn!("Package manager {preferred:?} not found, falling back");
+    }
+    if available.contains(&PackageManager::Npm) {
+        return PackageManager::Npm;
+    }
+    available.first().copied().unwrap_or(PackageManager::Npm)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn available_finds_executables_on_path() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(dir.path().join(PackageManager::Pnpm.executable()), "").unwrap();
+        std::fs::write(dir.path().join(PackageManager::Bun.executable()), "").unwrap();
+        let path = std::env::join_paths([dir.path()]).unwrap();
+        assert_eq!(
+            available(&path),
+            vec![PackageManager::Pnpm, PackageManager::Bun]
+        );
+    }
+
+    #[test]
+    fn choose_honors_an_available_preference() {
+        let found = [PackageManager::Npm, PackageManager::Pnpm];
+        assert_eq!(
+            choose(Some(PackageManager::Pnpm), &found),
+            PackageManager::Pnpm
+        );
+    }
+
+    #[test]
+    fn choose_falls_back_to_npm() {
+        let found = [PackageManager::Npm, PackageManager::Yarn];
+        assert_eq!(
+            choose(Some(PackageManager::Bun), &found),
+            PackageManager::Npm
+        );
+        assert_eq!(choose(None, &found), PackageManager::Npm);
+    }
+
+    #[test]
+    fn choose_uses_whatever_is_installed_without_npm() {
+        assert_eq!(
+            choose(None, &[PackageManager::Yarn, PackageManager::Bun]),
+            PackageManager::Yarn
+        );
+        assert_eq!(choose(None, &[]), PackageManager::Npm);
+    }
+
+    #[test]
+    fn serializes_lowercase() {
+        assert_eq!(serde_json::to_value(PackageManager::Pnpm).unwrap(), "pnpm");
+        for pm in PackageManager::ALL {
+            assert_eq!(serde_json::to_value(pm).unwrap(), pm.name());
+        }
+        let parsed: PackageManager = serde_json::from_str("\"yarn\"").unwrap();
+        assert_eq!(parsed, PackageManager::Yarn);
+    }
+}
@@ -0,0 +1,246 @@
+use semver::{Version, VersionReq};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Magic comment that pins dependency versions, e.g.
+/// `// @terrarium deps: recharts@2.12.7 react@18`.
+const DIRECTIVE: &str = "@terrarium deps:";
+
+/// A dependency version pinned by an artifact's header comment.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pin {
+    pub name: String,
+    /// The version as written, e.g. `2.12.7` or `^18`.
+    pub spec: String,
+    /// Alternatives (`||`); any one matching is enough.
+    ranges: Vec<VersionReq>,
+}
+
+impl Pin {
+    /// The pin as an install spec, e.g. `recharts@2.12.7`.
+    pub fn install_spec(&self) -> String {
+        format!("{}@{}", self.name, self.spec)
+    }
+
+    pub fn matches(&self, version: &str) -> bool {
+        Version::parse(version.trim().trim_start_matches('v'))
+            .is_ok_and(|version| self.ranges.iter().any(|range| range.matches(&version)))
+    }
+}
+
+/// A pinned package the bundle ended up with a different version of, e.g.
+/// because the artifact's project provides its own.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct PinMismatch {
+    pub name: String,
+    pub wanted: String,
+    pub found: String,
+}
+
+/// Parses the `@terrarium deps:` comments in the header of `source`: the
+/// comments and blank lines before the first line of code. A version works
+/// like it does in `package.json` (without spaces), except that `18` means
+/// any 18.x. Entries without a usable version are ignored.
+pub fn parse(source: &str) -> Vec<Pin> {
+    let mut pins: Vec<Pin> = Vec::new();
+    for line in source.lines().map(str::trim) {
+        if line.is_empty() {
+            continue;
+        }
+        let Some(comment) = line
+            .strip_prefix("//")
+            .or_else(|| line.strip_prefix("/*").map(|c| c.trim_end_matches("*/")))
+            .or_else(|| line.strip_prefix('*'))
+        else {
+            break;
+        };
+        let Some(deps) = comment.trim().strip_prefix(DIRECTIVE) else {
+            continue;
+        };
+        for entry in deps.split([' ', '\t', ',']).filter(|e| !e.is_empty()) {
+            match parse_entry(entry) {
+                Some(pin) => {
+                    pins.retain(|p| p.name != pin.name);
+                    pins.push(pin);
+                }
+                None => log::warn!("Ignoring dependency pin without a usable version: {entry}"),
+            }
+        }
+    }
+    pins
+}
+
+/// Splits `name@version`, where the name may itself start with `@`.
+fn parse_entry(entry: &str) -> Option<Pin> {
+    let at = entry.rfind('@').filter(|&at| at > 0)?;
+    let (name, spec) = (&entry[..at], &entry[at + 1..]);
+    let ranges = crate::node::version_ranges(spec).or_else(|| crate::node::semver_ranges(spec))?;
+    Some(Pin {
+        name: name.to_string(),
+        spec: spec.to_string(),
+        ranges,
+    })
+}
+
+/// Reads the pins from the header of the file at `path`.
+pub fn read(path: &Path) -> Vec<Pin> {
+    std::fs::read_to_string(path)
+        .map(|source| parse(&source))
+        .unwrap_or_default()
+}
+
+/// How a pin compares with what is installed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PinState {
+    /// Installed at a matching version.
+    Met,
+    /// The artifact's project has its own version, which wins over anything
+    /// Terrarium installs.
+    ProjectDiffers,
+    /// Not installed anywhere.
+    Missing,
+    /// The shared cache has another version, which other artifacts may use.
+    CacheDiffers,
+}
+
+/// Checks `pin` against the project's `node_modules` (if any), then the
+/// shared cache's.
+pub fn state(pin: &Pin, project_modules: Option<&Path>, shared_modules: &Path) -> PinState {
+    if let Some(version) =
+        project_modules.and_then(|dir| crate::cache::installed_version(dir, &pin.name))
+    {
+        return if pin.matches(&version) {
+            PinState::Met
+        } else {
+            PinState::ProjectDiffers
+        };
+    }
+    match crate::cache::installed_version(shared_modules, &pin.name) {
+        Some(version) if pin.matches(&version) => PinState::Met,
+        Some(_) => PinState::CacheDiffers,
+        None => PinState::Missing,
+    }
+}
+
+/// The pinned packages `packages` (what a bundle pulled in, with versions)
+/// has at a version that doesn't match the pin.
+pub fn mismatches(pins: &[Pin], packages: &BTreeMap<String, String>) -> Vec<PinMismatch> {
+    pins.iter()
+        .filter_map(|pin| {
+            let found = packages.get(&pin.name)?;
+            (!pin.matches(found)).then(|| PinMismatch {
+                name: pin.name.clone(),
+                wanted: pin.spec.clone(),
+                found: found.clone(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn specs(pins: &[Pin]) -> Vec<(&str, &str)> {
+        pins.iter()
+            .map(|p| (p.name.as_str(), p.spec.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_pins_from_the_header() {
+        let source = "// Revenue chart\n\
+                      // @terrarium deps: recharts@2.12.7 react@18\n\
+                      /* @terrarium deps: @acme/ui@^1.2, lodash@~4.17 */\n\
+                      \n\
+                      import { LineChart } from 'recharts';\n\
+                      // @terrarium deps: date-fns@3\n";
+        assert_eq!(
+            specs(&parse(source)),
+            vec![
+                ("recharts", "2.12.7"),
+                ("react", "18"),
+                ("@acme/ui", "^1.2"),
+                ("lodash", "~4.17"),
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_entries_without_a_version() {
+        let pins = parse("// @terrarium deps: recharts @acme/ui react@latest clsx@2\n");
+        assert_eq!(specs(&pins), vec![("clsx", "2")]);
+    }
+
+    #[test]
+    fn later_pins_replace_earlier_ones() {
+        let pins = parse("// @terrarium deps: react@17\n// @terrarium deps: react@18\n");
+        assert_eq!(specs(&pins), vec![("react", "18")]);
+    }
+
+    #[test]
+    fn bare_versions_pin_exactly_and_partials_match_any_minor() {
+        let pins = parse("// @terrarium deps: recharts@2.12.7 react@18 clsx@^2.0.0\n");
+        assert!(pins[0].matches("2.12.7"));
+        assert!(!pins[0].matches("2.13.0"));
+        assert!(pins[1].matches("18.3.1"));
+        assert!(!pins[1].matches("19.0.0"));
+        assert!(pins[2].matches("2.1.1"));
+        assert!(!pins[2].matches("not-a-version"));
+    }
+
+    #[test]
+    fn reports_bundled_versions_that_miss_the_pin() {
+        let pins = parse("// @terrarium deps: recharts@2.12.7 react@18 date-fns@3\n");
+        let packages = BTreeMap::from([
+            ("recharts".to_string(), "2.15.0".to_string()),
+            ("react".to_string(), "18.3.1".to_string()),
+        ]);
+        assert_eq!(
+            mismatches(&pins, &packages),
+            vec![PinMismatch {
+                name: "recharts".to_string(),
+                wanted: "2.12.7".to_string(),
+                found: "2.15.0".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn state_checks_the_project_before_the_cache() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let install = |modules: &str, name: &str, version: &str| {
+            let package = dir.path().join(modules).join(name);
+            std::fs::create_dir_all(&package).unwrap();
+            std::fs::write(
+                package.join("package.json"),
+                format!(r#"{{"version": "{version}"}}"#),
+            )
+            .unwrap();
+        };
+        install("project", "react", "17.0.2");
+        install("shared", "react", "18.3.1");
+        install("shared", "recharts", "2.15.0");
+        install("shared", "clsx", "2.1.1");
+        let project = dir.path().join("project");
+        let shared = dir.path().join("shared");
+
+        let pins = parse("// @terrarium deps: react@18 recharts@2.12.7 clsx@2 zod@3\n");
+        let states: Vec<PinState> = pins
+            .iter()
+            .map(|pin| state(pin, Some(&project), &shared))
+            .collect();
+        assert_eq!(
+            states,
+            vec![
+                PinState::ProjectDiffers,
+                PinState::CacheDiffers,
+                PinState::Met,
+                PinState::Missing,
+            ]
+        );
+        assert_eq!(state(&pins[0], None, &shared), PinState::Met);
+        assert_eq!(pins[1].install_spec(), "recharts@2.12.7");
+    }
+}
@@ -0,0 +1,96 @@
+use std::path::{Path, PathBuf};
+
+use crate::bundler::cache_dir;
+
+/// Returns the project an artifact belongs to: the nearest ancestor of
+/// `entry` that has both a `package.json` and an installed `node_modules`.
+///
+/// Bundles of artifacts inside a project resolve packages from that
+/// project's `node_modules` first, falling back to the shared cache in
+/// `~/.terrarium` only for packages the project doesn't have. `None` for
+/// standalone files.
+pub fn resolution_root(entry: &Path) -> Option<PathBuf> {
+    let cache = cache_dir();
+    entry
+        .parent()?
+        .ancestors()
+        .find(|dir| {
+            *dir != cache && dir.join("package.json").is_file() && dir.join("node_modules").is_dir()
+        })
+        .map(Path::to_path_buf)
+}
+
+/// Where a package is looked up, in order: the project's `node_modules`
+/// (if any), the artifact's isolated environment (if any), then the shared
+/// cache.
+pub fn node_modules_dirs(project: Option<&Path>, env: Option<&Path>) -> Vec<PathBuf> {
+    project
+        .into_iter()
+        .chain(env)
+        .map(|dir| dir.join("node_modules"))
+        .chain(std::iter::once(cache_dir().join("node_modules")))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn standalone_file_has_no_project() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let entry = dir.path().join("App.tsx");
+        assert_eq!(resolution_root(&entry), None);
+    }
+
+    #[test]
+    fn finds_the_nearest_installed_project() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().join("repo");
+        let components = root.join("src/components");
+        std::fs::create_dir_all(&components).unwrap();
+        std::fs::create_dir_all(root.join("node_modules")).unwrap();
+        std::fs::write(root.join("package.json"), "{}").unwrap();
+
+        assert_eq!(resolution_root(&components.join("Chart.tsx")), Some(root));
+    }
+
+    #[test]
+    fn skips_package_json_without_node_modules() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().join("repo");
+        let package = root.join("packages/ui");
+        std::fs::create_dir_all(&package).unwrap();
+        std::fs::write(package.join("package.json"), "{}").unwrap();
+        std::fs::create_dir_all(root.join("node_modules")).unwrap();
+        std::fs::write(root.join("package.json"), "{}").unwrap();
+
+        // A workspace package without its own install resolves from the
+        // workspace root, where the dependencies are hoisted.
+        assert_eq!(resolution_root(&package.join("Button.tsx")), Some(root));
+    }
+
+    #[test]
+    fn project_node_modules_come_before_the_cache() {
+        let dirs = node_modules_dirs(Some(Path::new("/work/repo")), None);
+        assert_eq!(dirs[0], PathBuf::from("/work/repo/node_modules"));
+        assert_eq!(dirs[1], cache_dir().join("node_modules"));
+        assert_eq!(
+            node_modules_dirs(None, None),
+            vec![cache_dir().join("node_modules")]
+        );
+    }
+
+    #[test]
+    fn environment_comes_between_the_project_and_the_cache() {
+        let dirs = node_modules_dirs(Some(Path::new("/work/repo")), Some(Path::new("/envs/abc")));
+        assert_eq!(
+            dirs,
+            vec![
+                PathBuf::from("/work/repo/node_modules"),
+                PathBuf::from("/envs/abc/node_modules"),
+                cache_dir().join("node_modules"),
+            ]
+        );
+    }
+}
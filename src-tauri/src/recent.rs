@@ -1,11 +1,13 @@
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::bundler::cache_dir;
 
 const MAX_RECENT: usize = 6;
 const NUM_PLANT_TYPES: u32 = 6; // u32 to match hash arithmetic in plant_index
+const MAX_BUNDLE_VERSIONS: usize = 200;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct RecentFile {
@@ -14,11 +16,24 @@ pub struct RecentFile {
     pub opened_at: String,
 }
 
+/// The exact package versions a file's last successful bundle used.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BundleVersions {
+    pub packages: BTreeMap<String, String>,
+    pub bundled_at: String,
+}
+
 /// Returns the path to the recent-files JSON file: `~/.terrarium/recent-files.json`.
 fn recent_file_path() -> PathBuf {
     cache_dir().join("recent-files.json")
 }
 
+/// Returns the path to the bundle versions JSON file:
+/// `~/.terrarium/bundle-versions.json`.
+fn bundle_versions_path() -> PathBuf {
+    cache_dir().join("bundle-versions.json")
+}
+
 /// Reads the recent files list from disk. Returns an empty vec if the file is
 /// missing, unreadable, or contains invalid JSON.
 pub fn read_recent() -> Vec<RecentFile> {
@@ -82,6 +97,56 @@ pub fn record_recent(file_path: &str) -> Vec<RecentFile> {
     list
 }
 
+/// Reads the package versions recorded per bundled file, by path. Returns an
+/// empty map if the file is missing or invalid.
+pub fn read_bundle_versions() -> BTreeMap<String, BundleVersions> {
+    read_bundle_versions_in(&bundle_versions_path())
+}
+
+fn read_bundle_versions_in(path: &Path) -> BTreeMap<String, BundleVersions> {
+    let Ok(data) = std::fs::read_to_string(path) else {
+        return BTreeMap::new();
+    };
+    serde_json::from_str(&data).unwrap_or_default()
+}
+
+/// Records the versions `file_path`'s bundle was built with (best-effort).
+/// Files that no longer exist are dropped, and only the most recently
+/// bundled `MAX_BUNDLE_VERSIONS` are kept.
+pub fn record_bundle_versions(file_path: &str, packages: &BTreeMap<String, String>) {
+    record_bundle_versions_in(&bundle_versions_path(), file_path, packages);
+}
+
+fn record_bundle_versions_in(path: &Path, file_path: &str, packages: &BTreeMap<String, String>) {
+    let mut versions = read_bundle_versions_in(path);
+    versions.retain(|file, _| Path::new(file).exists());
+    versions.insert(
+        file_path.to_string(),
+        BundleVersions {
+            packages: packages.clone(),
+            bundled_at: iso_timestamp(),
+        },
+    );
+    if versions.len() > MAX_BUNDLE_VERSIONS {
+        let mut by_age: Vec<(String, String)> = versions
+            .iter()
+            .map(|(file, v)| (v.bundled_at.clone(), file.clone()))
+            .collect();
+        by_age.sort();
+        for (_, file) in by_age.iter().take(versions.len() - MAX_BUNDLE_VERSIONS) {
+            if file != file_path {
+                versions.remove(file);
+            }
+        }
+    }
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    if let Ok(json) = serde_json::to_string_pretty(&versions) {
+        let _ = std::fs::write(path, json);
+    }
+}
+
 /// Deterministic plant index (0-5) based on a simple hash of the path bytes.
 fn plant_index(path: &str) -> u8 {
     let hash = path
@@ -178,6 +243,26 @@ mod tests {
         assert_eq!(days_to_ymd(19723), (2024, 1, 1));
     }
 
+    #[test]
+    fn bundle_versions_replace_the_previous_bundle_and_drop_missing_files() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let store = dir.path().join("bundle-versions.json");
+        let chart = dir.path().join("Chart.tsx");
+        std::fs::write(&chart, "").unwrap();
+        let chart = chart.to_string_lossy().into_owned();
+        let gone = dir.path().join("Gone.tsx").to_string_lossy().into_owned();
+        let packages =
+            |version: &str| BTreeMap::from([("recharts".to_string(), version.to_string())]);
+
+        record_bundle_versions_in(&store, &gone, &packages("2.12.7"));
+        record_bundle_versions_in(&store, &chart, &packages("2.12.7"));
+        record_bundle_versions_in(&store, &chart, &packages("2.15.0"));
+
+        let versions = read_bundle_versions_in(&store);
+        assert_eq!(versions.len(), 1);
+        assert_eq!(versions[&chart].packages, packages("2.15.0"));
+    }
+
     #[test]
     fn read_recent_returns_empty_for_missing_file() {
         // If the file doesn't exist, we get an empty vec.
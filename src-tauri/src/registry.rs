@@ -0,0 +1,115 @@
+use serde::{Deserialize, Serialize};
+
+/// First line of the `.npmrc` Terrarium writes into its cache. The bundler
+/// only removes a `.npmrc` that starts with it.
+const HEADER: &str =
+    "# Managed by Terrarium from ~/.terrarium/settings.json. Edits here are overwritten.";
+
+/// A package registry to install from, as configured in settings.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Registry {
+    /// Scope the registry serves, e.g. `@acme`. `None` replaces the default
+    /// registry for every unscoped package.
+    #[serde(default)]
+    pub scope: Option<String>,
+    pub url: String,
+    /// Auth token sent to the registry. `${VAR}` is expanded from the
+    /// environment by the package manager.
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+/// Renders `registries` as the `.npmrc` the bundler installs with. Empty when
+/// nothing is configured, which tells the bundler to remove a stale file.
+pub fn npmrc(registries: &[Registry]) -> String {
+    if registries.is_empty() {
+        return String::new();
+    }
+    let mut out = format!("{HEADER}\n");
+    for registry in registries {
+        let url = with_trailing_slash(registry.url.trim());
+        match registry.scope.as_deref().map(str::trim) {
+            Some(scope) if !scope.is_empty() => {
+                let scope = scope.trim_start_matches('@');
+                out.push_str(&format!("@{scope}:registry={url}\n"));
+            }
+            _ => out.push_str(&format!("registry={url}\n")),
+        }
+        if let Some(token) = registry.token.as_deref().filter(|t| !t.is_empty()) {
+            out.push_str(&format!("{}:_authToken={token}\n", auth_prefix(&url)));
+        }
+    }
+    out
+}
+
+fn with_trailing_slash(url: &str) -> String {
+    if url.ends_with('/') {
+        url.to_string()
+    } else {
+        format!("{url}/")
+    }
+}
+
+/// npm keys credentials by the registry URL without its protocol, e.g.
+/// `//npm.acme.dev/`.
+fn auth_prefix(url: &str) -> String {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    format!("//{rest}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry(scope: Option<&str>, url: &str, token: Option<&str>) -> Registry {
+        Registry {
+            scope: scope.map(str::to_string),
+            url: url.to_string(),
+            token: token.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn nothing_configured_renders_nothing() {
+        assert_eq!(npmrc(&[]), "");
+    }
+
+    #[test]
+    fn renders_scoped_registry_with_token() {
+        let rendered = npmrc(&[registry(
+            Some("@acme"),
+            "https://npm.acme.dev/repo",
+            Some("s3cret"),
+        )]);
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                HEADER,
+                "@acme:registry=https://npm.acme.dev/repo/",
+                "//npm.acme.dev/repo/:_authToken=s3cret",
+            ]
+        );
+    }
+
+    #[test]
+    fn scope_without_at_and_default_registry() {
+        let rendered = npmrc(&[
+            registry(Some("acme"), "http://127.0.0.1:4873/", None),
+            registry(None, "https://mirror.example.com", None),
+        ]);
+        assert!(rendered.contains("\n@acme:registry=http://127.0.0.1:4873/\n"));
+        assert!(rendered.contains("\nregistry=https://mirror.example.com/\n"));
+        assert!(!rendered.contains("_authToken"));
+    }
+
+    #[test]
+    fn parses_from_settings_json() {
+        let parsed: Registry =
+            serde_json::from_str(r#"{"scope": "@acme", "url": "https://npm.acme.dev/"}"#).unwrap();
+        assert_eq!(
+            parsed,
+            registry(Some("@acme"), "https://npm.acme.dev/", None)
+        );
+    }
+}
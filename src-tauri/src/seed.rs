@@ -0,0 +1,517 @@
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use crate::bundler::{cache_dir, managed};
+
+/// The first entry of every seed pack, describing what it was built with.
+const MANIFEST_NAME: &str = "terrarium-seed.json";
+const FORMAT: u32 = 1;
+/// Files next to `node_modules` that make up the cache: its `package.json`,
+/// Terrarium's lockfile, the package manager marker and the managers' own
+/// lockfiles. Everything else in `~/.terrarium` (settings, `.npmrc` with its
+/// tokens, bundle cache) stays on the machine.
+const CACHE_FILES: &[&str] = &[
+    "package.json",
+    "terrarium-lock.json",
+    ".package-manager",
+    ".yarnrc.yml",
+    "package-lock.json",
+    "pnpm-lock.yaml",
+    "yarn.lock",
+    "bun.lockb",
+    "bun.lock",
+];
+/// Same as the bundler's: the holder rewrites the lock's pid file every
+/// `LOCK_TOUCH`, so a lock untouched for `LOCK_STALE` was left behind by a
+/// process that died mid-install.
+const LOCK_TOUCH: Duration = Duration::from_secs(60);
+const LOCK_STALE: Duration = Duration::from_secs(10 * 60);
+
+/// What a seed pack was built with. Packages with native parts (esbuild's
+/// binary, above all) only work on the same platform and Node major.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SeedManifest {
+    pub format: u32,
+    pub node_major: u32,
+    /// `os-arch`, e.g. `macos-aarch64`.
+    pub platform: String,
+    pub package_manager: String,
+    /// The locked packages, by name, with their versions.
+    pub packages: BTreeMap<String, String>,
+}
+
+fn platform() -> String {
+    format!("{}-{}", std::env::consts::OS, std::env::consts::ARCH)
+}
+
+/// Whether a process with this id is running.
+fn process_alive(pid: u32) -> bool {
+    #[cfg(unix)]
+    let alive = std::process::Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stderr(std::process::Stdio::null())
+        .status()
+        .map_or(true, |status| status.success());
+    #[cfg(windows)]
+    let alive = std::process::Command::new("tasklist")
+        .args(["/FI", &format!("PID eq {pid}"), "/NH"])
+        .output()
+        .map_or(true, |output| {
+            String::from_utf8_lossy(&output.stdout).contains(&pid.to_string())
+        });
+    alive
+}
+
+/// Whether the install lock at `dir` was left behind by a process that is
+/// gone, the same way the bundler decides it. A live holder keeps touching
+/// the lock, so one untouched for `LOCK_STALE` belongs to a crashed process
+/// whose pid was reused.
+fn lock_is_stale(dir: &Path) -> bool {
+    let pid_file = dir.join("pid");
+    let holder = std::fs::read_to_string(&pid_file)
+        .ok()
+        .and_then(|pid| pid.trim().parse::<u32>().ok());
+    if holder.is_some_and(|pid| pid != std::process::id() && !process_alive(pid)) {
+        return true;
+    }
+    // Before the holder writes its pid, the directory's own time stands in.
+    std::fs::metadata(&pid_file)
+        .or_else(|_| std::fs::metadata(dir))
+        .and_then(|m| m.modified())
+        .is_ok_and(|m| m.elapsed().unwrap_or_default() > LOCK_STALE)
+}
+
+/// Holds the bundler's cross-process install lock on the cache, so no
+/// install runs while the cache is packed or replaced.
+struct InstallLock {
+    dir: PathBuf,
+    /// Keeps the lock fresh until the sender is dropped.
+    touching: Option<(mpsc::Sender<()>, std::thread::JoinHandle<()>)>,
+}
+
+impl InstallLock {
+    fn acquire(cache: &Path) -> Result<Self, String> {
+        let dir = cache.join(".install.lock");
+        if lock_is_stale(&dir) {
+            let _ = std::fs::remove_dir_all(&dir);
+        }
+        match std::fs::create_dir(&dir) {
+            Ok(()) => {
+                let pid_file = dir.join("pid");
+                let touch = move || std::fs::write(&pid_file, std::process::id().to_string());
+                let _ = touch();
+                let (stop, stopped) = mpsc::channel::<()>();
+                let thread = std::thread::spawn(move || {
+                    while let Err(mpsc::RecvTimeoutError::Timeout) =
+                        stopped.recv_timeout(LOCK_TOUCH)
+                    {
+                        let _ = touch();
+                    }
+                });
+                Ok(Self {
+                    dir,
+                    touching: Some((stop, thread)),
+                })
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                Err("Packages are being installed. Try again once they finish.".to_string())
+            }
+            Err(e) => Err(format!("Failed to lock the package cache: {e}")),
+        }
+    }
+}
+
+impl Drop for InstallLock {
+    fn drop(&mut self) {
+        // Stopped first, so it can't write into a lock someone else takes.
+        if let Some((stop, thread)) = self.touching.take() {
+            drop(stop);
+            let _ = thread.join();
+        }
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn read_locked(cache: &Path) -> BTreeMap<String, String> {
+    #[derive(Deserialize)]
+    struct Lockfile {
+        packages: BTreeMap<String, String>,
+    }
+    std::fs::read_to_string(cache.join("terrarium-lock.json"))
+        .ok()
+        .and_then(|data| serde_json::from_str::<Lockfile>(&data).ok())
+        .map(|lock| lock.packages)
+        .unwrap_or_default()
+}
+
+/// Packs the packages installed in `cache` into a gzipped tarball at
+/// `dest`, for [`import`] on a machine without internet access.
+pub fn export(cache: &Path, dest: &Path, node_major: u32) -> Result<SeedManifest, String> {
+    let node_modules = cache.join("node_modules");
+    if !node_modules.is_dir() {
+        return Err("The package cache is empty; there is nothing to export.".to_string());
+    }
+    let _lock = InstallLock::acquire(cache)?;
+    let manifest = SeedManifest {
+        format: FORMAT,
+        node_major,
+        platform: platform(),
+        package_manager: std::fs::read_to_string(cache.join(".package-manager"))
+            .map(|m| m.trim().to_string())
+            .unwrap_or_else(|_| "npm".to_string()),
+        packages: read_locked(cache),
+    };
+
+    // Written beside the destination and renamed, so a failed export never
+    // leaves a truncated pack behind.
+    let partial = dest.with_extension("partial");
+    let result = write_pack(cache, &partial, &manifest)
+        .and_then(|()| std::fs::rename(&partial, dest))
+        .map_err(|e| format!("Failed to write seed pack: {e}"));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&partial);
+    }
+    result.map(|()| manifest)
+}
+
+fn write_pack(cache: &Path, path: &Path, manifest: &SeedManifest) -> std::io::Result<()> {
+    let file = std::fs::File::create(path)?;
+    let mut tar = tar::Builder::new(GzEncoder::new(file, flate2::Compression::default()));
+    // pnpm and `.bin` entries are symlinks within node_modules; keep them so.
+    tar.follow_symlinks(false);
+
+    let json = serde_json::to_vec_pretty(manifest).map_err(std::io::Error::other)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(json.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    tar.append_data(&mut header, MANIFEST_NAME, json.as_slice())?;
+
+    for name in CACHE_FILES {
+        let file = cache.join(name);
+        if file.is_file() {
+            tar.append_path_with_name(&file, name)?;
+        }
+    }
+    tar.append_dir_all("node_modules", cache.join("node_modules"))?;
+    tar.into_inner()?.finish()?;
+    Ok(())
+}
+
+/// Checks that a pack built as `manifest` works here: same format,
+/// platform, Node major and package manager.
+fn check(manifest: &SeedManifest, node_major: u32, manager: &str) -> Result<(), String> {
+    if manifest.format != FORMAT {
+        return Err("This seed pack was made by a different version of Terrarium.".to_string());
+    }
+    if manifest.platform != platform() {
+        return Err(format!(
+            "This seed pack was made on {}, but this machine is {}.",
+            manifest.platform,
+            platform()
+        ));
+    }
+    if manifest.node_major != node_major {
+        return Err(format!(
+            "This seed pack was made with Node {}, but this machine has Node {node_major}.",
+            manifest.node_major
+        ));
+    }
+    if manifest.package_manager != manager {
+        return Err(format!(
+            "This seed pack was made with {}, but Terrarium installs with {manager} here. \
+             Choose {} in settings to use it.",
+            manifest.package_manager, manifest.package_manager
+        ));
+    }
+    Ok(())
+}
+
+/// Replaces the packages in `cache` with the ones in the seed pack at
+/// `archive`, after checking it against this machine's Node major and
+/// package manager. Once imported, bundles whose imports the pack covers
+/// never call the registry.
+pub fn import(
+    cache: &Path,
+    archive: &Path,
+    node_major: u32,
+    manager: &str,
+) -> Result<SeedManifest, String> {
+    let file =
+        std::fs::File::open(archive).map_err(|e| format!("Failed to open seed pack: {e}"))?;
+    let mut tar = tar::Archive::new(GzDecoder::new(file));
+    tar.set_preserve_permissions(true);
+    let invalid = |e: std::io::Error| format!("Not a valid seed pack: {e}");
+    let mut entries = tar.entries().map_err(invalid)?;
+
+    let mut first = entries
+        .next()
+        .ok_or_else(|| "Not a valid seed pack: it is empty.".to_string())?
+        .map_err(invalid)?;
+    if first.path().map_err(invalid)?.as_ref() != Path::new(MANIFEST_NAME) {
+        return Err("Not a valid seed pack: it has no manifest.".to_string());
+    }
+    let mut json = String::new();
+    first.read_to_string(&mut json).map_err(invalid)?;
+    let manifest: SeedManifest =
+        serde_json::from_str(&json).map_err(|e| format!("Not a valid seed pack: {e}"))?;
+    check(&manifest, node_major, manager)?;
+
+    // Unpacked next to the cache first, so a broken pack leaves it alone.
+    let staging = cache.join(".seed-import");
+    let _ = std::fs::remove_dir_all(&staging);
+    std::fs::create_dir_all(&staging).map_err(|e| format!("Failed to import seed pack: {e}"))?;
+    let unpacked = entries.try_for_each(|entry| {
+        // `unpack_in` refuses paths that would land outside `staging`.
+        if entry?.unpack_in(&staging)? {
+            Ok(())
+        } else {
+            Err(std::io::Error::other(
+                "it contains a path outside the cache",
+            ))
+        }
+    });
+    let result = unpacked.map_err(invalid).and_then(|()| {
+        if staging.join("node_modules").is_dir() {
+            replace(cache, &staging)
+        } else {
+            Err("Not a valid seed pack: it has no node_modules.".to_string())
+        }
+    });
+    let _ = std::fs::remove_dir_all(&staging);
+    result.map(|()| manifest)
+}
+
+/// Swaps the cache's packages for the ones unpacked into `staging`.
+fn replace(cache: &Path, staging: &Path) -> Result<(), String> {
+    let _lock = InstallLock::acquire(cache)?;
+    let fail = |e: std::io::Error| format!("Failed to import seed pack: {e}");
+    let _ = std::fs::remove_dir_all(cache.join("node_modules"));
+    for name in CACHE_FILES {
+        let _ = std::fs::remove_file(cache.join(name));
+    }
+    std::fs::rename(staging.join("node_modules"), cache.join("node_modules")).map_err(fail)?;
+    for name in CACHE_FILES {
+        let file = staging.join(name);
+        if file.is_file() {
+            std::fs::rename(&file, cache.join(name)).map_err(fail)?;
+        }
+    }
+    Ok(())
+}
+
+/// The running Node's major version and the package manager bundles
+/// install with.
+fn toolchain<R: tauri::Runtime>(app_handle: &tauri::AppHandle<R>) -> Result<(u32, String), String> {
+    let node = managed::<crate::node::NodeCache, R>(app_handle).get(None)?;
+    let path_env = crate::node::path_env(&node.path);
+    let package_manager = crate::package_manager::choose(
+        crate::settings::read_settings().package_manager,
+        &crate::package_manager::available(&path_env),
+    );
+    Ok((node.major(), package_manager.name().to_string()))
+}
+
+/// Asks where to save a seed pack of the shared cache and writes it.
+pub async fn export_with_dialog<R: tauri::Runtime>(app_handle: tauri::AppHandle<R>) {
+    use tauri_plugin_dialog::DialogExt;
+
+    let Some(dest) = app_handle
+        .dialog()
+        .file()
+        .add_filter("Terrarium Seed Pack", &["tgz"])
+        .set_file_name("terrarium-seed.tgz")
+        .blocking_save_file()
+        .and_then(|f| f.into_path().ok())
+    else {
+        return;
+    };
+    let result = match toolchain(&app_handle) {
+        Ok((node_major, _)) => {
+            tauri::async_runtime::spawn_blocking(move || export(&cache_dir(), &dest, node_major))
+                .await
+                .unwrap_or_else(|e| Err(e.to_string()))
+        }
+        Err(e) => Err(e),
+    };
+    crate::show_result(
+        &app_handle,
+        "Seed Pack",
+        result.map(|m| format!("Exported {} packages.", m.packages.len())),
+    );
+}
+
+/// Asks for a seed pack and imports it into the shared cache, restarting
+/// the bundler so it picks up the new packages.
+pub async fn import_with_dialog<R: tauri::Runtime>(app_handle: tauri::AppHandle<R>) {
+    use tauri::Emitter;
+    use tauri_plugin_dialog::DialogExt;
+
+    let Some(archive) = app_handle
+        .dialog()
+        .file()
+        .add_filter("Terrarium Seed Pack", &["tgz"])
+        .blocking_pick_file()
+        .and_then(|f| f.into_path().ok())
+    else {
+        return;
+    };
+    let result = match toolchain(&app_handle) {
+        Ok((node_major, manager)) => tauri::async_runtime::spawn_blocking(move || {
+            import(&cache_dir(), &archive, node_major, &manager)
+        })
+        .await
+        .unwrap_or_else(|e| Err(e.to_string())),
+        Err(e) => Err(e),
+    };
+    if result.is_ok() {
+        managed::<crate::daemon::BundlerDaemon, R>(&app_handle)
+            .shutdown()
+            .await;
+        let _ = app_handle.emit("deps-changed", ());
+    }
+    crate::show_result(
+        &app_handle,
+        "Seed Pack",
+        result.map(|m| format!("Imported {} packages.", m.packages.len())),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seed_cache(cache: &Path) {
+        let react = cache.join("node_modules/react");
+        std::fs::create_dir_all(&react).unwrap();
+        std::fs::write(react.join("package.json"), r#"{"version": "18.3.1"}"#).unwrap();
+        std::fs::write(
+            cache.join("package.json"),
+            r#"{"dependencies": {"react": "18.3.1"}}"#,
+        )
+        .unwrap();
+        std::fs::write(
+            cache.join("terrarium-lock.json"),
+            r#"{"packages": {"react": "18.3.1"}}"#,
+        )
+        .unwrap();
+        std::fs::write(cache.join(".package-manager"), "npm").unwrap();
+        std::fs::write(
+            cache.join(".npmrc"),
+            "//registry.example.com/:_authToken=secret",
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn round_trips_the_cache() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let source = dir.path().join("source");
+        seed_cache(&source);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let bin = source.join("node_modules/.bin");
+            std::fs::create_dir_all(&bin).unwrap();
+            let esbuild = source.join("node_modules/esbuild-bin");
+            std::fs::write(&esbuild, "#!/bin/sh\n").unwrap();
+            std::fs::set_permissions(&esbuild, std::fs::Permissions::from_mode(0o755)).unwrap();
+            std::os::unix::fs::symlink("../esbuild-bin", bin.join("esbuild")).unwrap();
+        }
+        let pack = dir.path().join("seed.tgz");
+        let exported = export(&source, &pack, 20).unwrap();
+        assert_eq!(exported.packages["react"], "18.3.1");
+        assert!(!source.join(".install.lock").exists());
+
+        let target = dir.path().join("target");
+        std::fs::create_dir_all(target.join("node_modules/left-pad")).unwrap();
+        std::fs::write(target.join("settings.json"), "{}").unwrap();
+        let imported = import(&target, &pack, 20, "npm").unwrap();
+        assert_eq!(imported, exported);
+        assert!(target.join("node_modules/react/package.json").is_file());
+        assert!(!target.join("node_modules/left-pad").exists());
+        assert!(target.join("terrarium-lock.json").is_file());
+        assert!(target.join("settings.json").is_file());
+        // Registry credentials never leave the machine.
+        assert!(!target.join(".npmrc").exists());
+        assert!(!target.join(".seed-import").exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let link = target.join("node_modules/.bin/esbuild");
+            assert!(link.symlink_metadata().unwrap().file_type().is_symlink());
+            let mode = std::fs::metadata(&link).unwrap().permissions().mode();
+            assert_eq!(mode & 0o111, 0o111);
+        }
+    }
+
+    #[test]
+    fn rejects_a_pack_for_another_node_major_or_manager() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let source = dir.path().join("source");
+        seed_cache(&source);
+        let pack = dir.path().join("seed.tgz");
+        export(&source, &pack, 20).unwrap();
+
+        let target = dir.path().join("target");
+        std::fs::create_dir_all(target.join("node_modules/left-pad")).unwrap();
+        let err = import(&target, &pack, 22, "npm").unwrap_err();
+        assert_eq!(
+            err,
+            "This seed pack was made with Node 20, but this machine has Node 22."
+        );
+        assert!(import(&target, &pack, 20, "pnpm")
+            .unwrap_err()
+            .contains("pnpm"));
+        // The existing cache is untouched.
+        assert!(target.join("node_modules/left-pad").is_dir());
+    }
+
+    #[test]
+    fn waits_for_running_installs() {
+        let dir = tempfile::TempDir::new().unwrap();
+        seed_cache(dir.path());
+        std::fs::create_dir(dir.path().join(".install.lock")).unwrap();
+        let err = export(dir.path(), &dir.path().join("seed.tgz"), 20).unwrap_err();
+        assert!(err.contains("being installed"));
+        assert!(!dir.path().join("seed.tgz").exists());
+    }
+
+    #[test]
+    fn takes_over_a_lock_whose_holder_is_gone() {
+        let dir = tempfile::TempDir::new().unwrap();
+        seed_cache(dir.path());
+        let lock = dir.path().join(".install.lock");
+        std::fs::create_dir(&lock).unwrap();
+        // Far above any pid the system hands out.
+        std::fs::write(lock.join("pid"), "999999999").unwrap();
+        export(dir.path(), &dir.path().join("seed.tgz"), 20).unwrap();
+        assert!(!lock.exists());
+    }
+
+    #[test]
+    fn rejects_archives_without_a_manifest() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let pack = dir.path().join("other.tgz");
+        let file = std::fs::File::create(&pack).unwrap();
+        let mut tar = tar::Builder::new(GzEncoder::new(file, flate2::Compression::default()));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(2);
+        header.set_mode(0o644);
+        header.set_cksum();
+        tar.append_data(&mut header, "package.json", &b"{}"[..])
+            .unwrap();
+        tar.into_inner().unwrap().finish().unwrap();
+
+        assert_eq!(
+            import(dir.path(), &pack, 20, "npm").unwrap_err(),
+            "Not a valid seed pack: it has no manifest."
+        );
+    }
+}
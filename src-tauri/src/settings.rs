@@ -0,0 +1,121 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use crate::bundler::cache_dir;
+use crate::package_manager::PackageManager;
+use crate::registry::Registry;
+use crate::storage::StorageQuota;
+
+/// User-tunable settings, stored in `~/.terrarium/settings.json`. Missing
+/// fields fall back to their defaults, so the file only needs the keys a
+/// user wants to change.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    /// How long a file must be quiet after a change before it is rebuilt.
+    pub debounce_ms: u64,
+    /// How many bundles may run at once across all windows.
+    pub max_concurrent_bundles: usize,
+    /// Package manager used to install dependencies; `None` picks one
+    /// automatically.
+    pub package_manager: Option<PackageManager>,
+    /// Registries packages are installed from, e.g. an internal one for a
+    /// scope. Rendered into the cache's `.npmrc`.
+    pub registries: Vec<Registry>,
+    /// Packages installed without asking, by name or as `@scope/*`.
+    pub allowed_packages: Vec<String>,
+    /// Packages whose install scripts may run; everything else is installed
+    /// with `--ignore-scripts`.
+    pub trusted_packages: Vec<String>,
+    /// Limits on each artifact's persistent storage.
+    pub storage_quota: StorageQuota,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            debounce_ms: 300,
+            max_concurrent_bundles: 2,
+            package_manager: None,
+            registries: Vec::new(),
+            allowed_packages: Vec::new(),
+            trusted_packages: Vec::new(),
+            storage_quota: StorageQuota::default(),
+        }
+    }
+}
+
+/// Returns the path to the settings JSON file: `~/.terrarium/settings.json`.
+fn settings_path() -> PathBuf {
+    cache_dir().join("settings.json")
+}
+
+/// Reads settings from disk. Returns the defaults if the file is missing,
+/// unreadable, or contains invalid JSON.
+pub fn read_settings() -> Settings {
+    let Ok(data) = std::fs::read_to_string(settings_path()) else {
+        return Settings::default();
+    };
+    parse_settings(&data)
+}
+
+fn parse_settings(data: &str) -> Settings {
+    serde_json::from_str(data).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_fields_use_defaults() {
+        assert_eq!(parse_settings("{}"), Settings::default());
+    }
+
+    #[test]
+    fn reads_debounce_window() {
+        assert_eq!(parse_settings(r#"{"debounce_ms": 50}"#).debounce_ms, 50);
+    }
+
+    #[test]
+    fn reads_package_manager() {
+        assert_eq!(
+            parse_settings(r#"{"package_manager": "pnpm"}"#).package_manager,
+            Some(PackageManager::Pnpm)
+        );
+        assert_eq!(parse_settings("{}").package_manager, None);
+    }
+
+    #[test]
+    fn reads_registries() {
+        let settings = parse_settings(
+            r#"{"registries": [{"scope": "@acme", "url": "https://npm.acme.dev/", "token": "t"}]}"#,
+        );
+        assert_eq!(settings.registries.len(), 1);
+        assert_eq!(settings.registries[0].scope.as_deref(), Some("@acme"));
+        assert_eq!(settings.registries[0].token.as_deref(), Some("t"));
+    }
+
+    #[test]
+    fn reads_package_lists() {
+        let settings =
+            parse_settings(r#"{"allowed_packages": ["@acme/*"], "trusted_packages": ["sharp"]}"#);
+        assert_eq!(settings.allowed_packages, vec!["@acme/*"]);
+        assert_eq!(settings.trusted_packages, vec!["sharp"]);
+    }
+
+    #[test]
+    fn reads_partial_storage_quota() {
+        let quota = parse_settings(r#"{"storage_quota": {"max_keys": 50}}"#).storage_quota;
+        assert_eq!(quota.max_keys, 50);
+        assert_eq!(
+            quota.max_value_bytes,
+            StorageQuota::default().max_value_bytes
+        );
+    }
+
+    #[test]
+    fn invalid_json_uses_defaults() {
+        assert_eq!(parse_settings("not json"), Settings::default());
+    }
+}
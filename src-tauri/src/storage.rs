@@ -1,12 +1,98 @@
+use std::collections::{BTreeMap, HashMap};
 use std::path::Path;
 use std::sync::Mutex;
 
 use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Namespace of values stored with `shared: true`, visible to every
+/// artifact. No file has an empty path, so it can't collide with a file's.
+const SHARED_NAMESPACE: &str = "";
+
+/// Works out the namespace a storage call from the window labelled `label`
+/// works in: the shared one, or that of the file open in the window. The
+/// webview's own idea of its file (`claimed`) is never trusted; a path
+/// other than the window's is refused.
+fn namespace(
+    windows: &HashMap<String, crate::WindowState>,
+    label: &str,
+    claimed: Option<&str>,
+    shared: bool,
+) -> Result<String, StorageError> {
+    if shared {
+        return Ok(SHARED_NAMESPACE.to_string());
+    }
+    let Some(file) = windows.get(label).map(|ws| ws.file.to_string_lossy()) else {
+        return Err(StorageError::Forbidden {
+            message: "No file is open in this window.".to_string(),
+        });
+    };
+    match claimed {
+        Some(claimed) if !claimed.is_empty() && claimed != file => Err(StorageError::Forbidden {
+            message: format!("This window can't access the storage of {claimed}."),
+        }),
+        _ => Ok(file.into_owned()),
+    }
+}
+
+/// Limits on what one artifact's namespace (or the shared one) may hold.
+/// Sizes count the UTF-8 bytes of keys and values.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct StorageQuota {
+    /// Largest single value.
+    pub max_value_bytes: u64,
+    pub max_keys: u64,
+    /// Keys plus values, across the namespace.
+    pub max_total_bytes: u64,
+}
+
+impl Default for StorageQuota {
+    /// Claude's own limits: 5MB per value, 20MB per artifact.
+    fn default() -> Self {
+        Self {
+            max_value_bytes: 5 * 1024 * 1024,
+            max_keys: 10_000,
+            max_total_bytes: 20 * 1024 * 1024,
+        }
+    }
+}
+
+/// A failed storage call. Serialized as `{ "type": "...", "message" }`, so
+/// the shim can tell a quota error from any other.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum StorageError {
+    /// The write would take the namespace over one of its quota limits.
+    Quota {
+        message: String,
+    },
+    /// The window asked for a namespace other than its own file's.
+    Forbidden {
+        message: String,
+    },
+    /// The document isn't a storage export this version can read.
+    Format {
+        message: String,
+    },
+    Database {
+        message: String,
+    },
+}
+
+impl From<String> for StorageError {
+    fn from(message: String) -> Self {
+        Self::Database { message }
+    }
+}
 
 /// SQLite-backed key-value store for artifact persistent storage.
-/// Storage is scoped per file path, matching Claude's per-artifact isolation.
+/// Storage is scoped per file path, matching Claude's per-artifact isolation,
+/// plus a shared namespace all artifacts can see.
 pub struct StorageDb {
     conn: Mutex<Connection>,
+    quota: StorageQuota,
 }
 
 impl StorageDb {
@@ -21,14 +107,28 @@ impl StorageDb {
                 value TEXT NOT NULL,
                 updated_at INTEGER NOT NULL DEFAULT (unixepoch()),
                 PRIMARY KEY (file_path, key)
+            );
+            CREATE TABLE IF NOT EXISTS artifacts (
+                file_path TEXT PRIMARY KEY,
+                artifact_id TEXT,
+                fingerprint TEXT,
+                created INTEGER,
+                seen_at INTEGER NOT NULL DEFAULT (unixepoch())
             );",
         )
         .map_err(|e| e.to_string())?;
         Ok(Self {
             conn: Mutex::new(conn),
+            quota: StorageQuota::default(),
         })
     }
 
+    /// Replaces the default quota every namespace is held to.
+    pub fn with_quota(mut self, quota: StorageQuota) -> Self {
+        self.quota = quota;
+        self
+    }
+
     /// Returns the value for a key scoped to a file path, or `None` if not set.
     pub fn get(&self, file_path: &str, key: &str) -> Result<Option<String>, String> {
         let conn = self.conn.lock().map_err(|e| e.to_string())?;
@@ -42,8 +142,11 @@ impl StorageDb {
     }
 
     /// Sets a key-value pair scoped to a file path. Overwrites if the key exists.
-    pub fn set(&self, file_path: &str, key: &str, value: &str) -> Result<(), String> {
+    /// Fails with [`StorageError::Quota`], writing nothing, if the namespace
+    /// would go over its quota.
+    pub fn set(&self, file_path: &str, key: &str, value: &str) -> Result<(), StorageError> {
         let conn = self.conn.lock().map_err(|e| e.to_string())?;
+        self.check_quota(&conn, file_path, key, value)?;
         conn.execute(
             "INSERT INTO artifact_storage (file_path, key, value, updated_at)
              VALUES (?1, ?2, ?3, unixepoch())
@@ -54,46 +157,781 @@ impl StorageDb {
         Ok(())
     }
 
-    /// Removes a key-value pair. No-op if the key doesn't exist.
-    pub fn remove(&self, file_path: &str, key: &str) -> Result<(), String> {
+    fn check_quota(
+        &self,
+        conn: &Connection,
+        file_path: &str,
+        key: &str,
+        value: &str,
+    ) -> Result<(), StorageError> {
+        let quota = &self.quota;
+        let exceeded = |message: String| Err(StorageError::Quota { message });
+        if value.len() as u64 > quota.max_value_bytes {
+            return exceeded(format!(
+                "The value for \"{key}\" is {} bytes; values are limited to {} bytes.",
+                value.len(),
+                quota.max_value_bytes
+            ));
+        }
+        // Everything else in the namespace, as it would stay after the write.
+        let (others, other_bytes): (u64, u64) = conn
+            .query_row(
+                "SELECT COUNT(*), COALESCE(SUM(length(CAST(key AS BLOB)) + length(CAST(value AS BLOB))), 0)
+                 FROM artifact_storage WHERE file_path = ?1 AND key != ?2",
+                params![file_path, key],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .map_err(|e| e.to_string())?;
+        if others + 1 > quota.max_keys {
+            return exceeded(format!(
+                "Storage is limited to {} keys, and all of them are in use.",
+                quota.max_keys
+            ));
+        }
+        let total = other_bytes + (key.len() + value.len()) as u64;
+        if total > quota.max_total_bytes {
+            return exceeded(format!(
+                "Storing \"{key}\" would use {total} bytes; storage is limited to {} bytes.",
+                quota.max_total_bytes
+            ));
+        }
+        Ok(())
+    }
+
+    /// Removes a key-value pair. Returns whether the key existed.
+    pub fn remove(&self, file_path: &str, key: &str) -> Result<bool, String> {
         let conn = self.conn.lock().map_err(|e| e.to_string())?;
-        conn.execute(
-            "DELETE FROM artifact_storage WHERE file_path = ?1 AND key = ?2",
-            params![file_path, key],
+        let removed = conn
+            .execute(
+                "DELETE FROM artifact_storage WHERE file_path = ?1 AND key = ?2",
+                params![file_path, key],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(removed > 0)
+    }
+
+    /// Returns the keys scoped to a file path that start with `prefix`
+    /// (all of them for an empty prefix), sorted.
+    pub fn list(&self, file_path: &str, prefix: &str) -> Result<Vec<String>, String> {
+        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+        // Compared by substring rather than LIKE, so `%` and `_` in the
+        // prefix match literally.
+        let mut stmt = conn
+            .prepare(
+                "SELECT key FROM artifact_storage
+                 WHERE file_path = ?1 AND substr(key, 1, length(?2)) = ?2
+                 ORDER BY key",
+            )
+            .map_err(|e| e.to_string())?;
+        let keys = stmt
+            .query_map(params![file_path, prefix], |row| row.get(0))
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<String>, _>>()
+            .map_err(|e| e.to_string())?;
+        Ok(keys)
+    }
+
+    /// Moves the storage of the file at `from` over to `to`, e.g. after the
+    /// file was renamed. Keys `to` already has are overwritten by `from`'s.
+    /// Returns whether there was anything to move.
+    pub fn repoint(&self, from: &str, to: &str) -> Result<bool, String> {
+        let mut conn = self.conn.lock().map_err(|e| e.to_string())?;
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        let moved = repoint_in(&tx, from, to)?;
+        tx.commit().map_err(|e| e.to_string())?;
+        Ok(moved)
+    }
+
+    /// Records who the file at `file_path` is, and if it has no storage yet,
+    /// takes over the storage of a file that has gone missing but was the
+    /// same artifact (see [`ArtifactIdentity::same_artifact`]), the most
+    /// recently seen one first. Returns the path the storage came from.
+    pub fn adopt(
+        &self,
+        file_path: &str,
+        identity: &ArtifactIdentity,
+        exists: impl Fn(&str) -> bool,
+    ) -> Result<Option<String>, String> {
+        let mut conn = self.conn.lock().map_err(|e| e.to_string())?;
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        let has_storage: bool = tx
+            .query_row(
+                "SELECT EXISTS (SELECT 1 FROM artifact_storage WHERE file_path = ?1)",
+                params![file_path],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        let mut adopted = None;
+        if !has_storage {
+            let mut stmt = tx
+                .prepare(
+                    "SELECT file_path, artifact_id, fingerprint, created FROM artifacts
+                     WHERE file_path != ?1
+                       AND EXISTS (SELECT 1 FROM artifact_storage s WHERE s.file_path = artifacts.file_path)
+                     ORDER BY seen_at DESC",
+                )
+                .map_err(|e| e.to_string())?;
+            let candidates = stmt
+                .query_map(params![file_path], |row| {
+                    let identity = ArtifactIdentity {
+                        id: row.get(1)?,
+                        fingerprint: row.get(2)?,
+                        created: row.get(3)?,
+                    };
+                    Ok((row.get::<_, String>(0)?, identity))
+                })
+                .map_err(|e| e.to_string())?
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| e.to_string())?;
+            drop(stmt);
+            // Only missing files give up their storage, so a copy never takes
+            // the original's.
+            let from = candidates.into_iter().find_map(|(path, other)| {
+                let same_name = Path::new(&path).file_name() == Path::new(file_path).file_name();
+                (identity.same_artifact(&other, same_name) && !exists(&path)).then_some(path)
+            });
+            if let Some(from) = from {
+                repoint_in(&tx, &from, file_path)?;
+                adopted = Some(from);
+            }
+        }
+        tx.execute(
+            "INSERT INTO artifacts (file_path, artifact_id, fingerprint, created, seen_at)
+             VALUES (?1, ?2, ?3, ?4, unixepoch())
+             ON CONFLICT(file_path) DO UPDATE SET artifact_id = excluded.artifact_id,
+                 fingerprint = excluded.fingerprint, created = excluded.created,
+                 seen_at = excluded.seen_at",
+            params![
+                file_path,
+                identity.id,
+                identity.fingerprint,
+                identity.created
+            ],
         )
         .map_err(|e| e.to_string())?;
+        tx.commit().map_err(|e| e.to_string())?;
+        Ok(adopted)
+    }
+
+    /// Exports the values of one namespace, or of every namespace for `None`.
+    pub fn export(&self, file_path: Option<&str>) -> Result<StorageExport, String> {
+        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT file_path, key, value FROM artifact_storage
+                 WHERE ?1 IS NULL OR file_path = ?1",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params![file_path], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .map_err(|e| e.to_string())?;
+        let mut namespaces: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+        for row in rows {
+            let (namespace, key, value) = row.map_err(|e| e.to_string())?;
+            namespaces.entry(namespace).or_default().insert(key, value);
+        }
+        Ok(StorageExport {
+            format: EXPORT_FORMAT,
+            namespaces,
+        })
+    }
+
+    /// Imports every namespace in `export`, all or nothing. Keys whose stored
+    /// value differs from the imported one are reported as conflicts: `Merge`
+    /// keeps the stored value, `Replace` clears each imported namespace first
+    /// so the imported value wins. Fails with [`StorageError::Quota`] if a
+    /// namespace would end up over its quota.
+    pub fn import(
+        &self,
+        export: &StorageExport,
+        mode: ImportMode,
+    ) -> Result<StorageImported, StorageError> {
+        export.check_format()?;
+        let mut conn = self.conn.lock().map_err(|e| e.to_string())?;
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        let mut imported = 0;
+        let mut conflicts = Vec::new();
+        for (namespace, values) in &export.namespaces {
+            let existing = namespace_values(&tx, namespace)?;
+            for (key, value) in values {
+                if let Some(stored) = existing.get(key).filter(|stored| *stored != value) {
+                    conflicts.push(StorageConflict {
+                        namespace: namespace.clone(),
+                        key: key.clone(),
+                        existing: stored.clone(),
+                        imported: value.clone(),
+                    });
+                }
+            }
+            let mut result = match mode {
+                ImportMode::Merge => existing.clone(),
+                ImportMode::Replace => BTreeMap::new(),
+            };
+            for (key, value) in values {
+                result.entry(key.clone()).or_insert_with(|| value.clone());
+            }
+            self.check_namespace(namespace, &result)?;
+
+            if mode == ImportMode::Replace {
+                tx.execute(
+                    "DELETE FROM artifact_storage WHERE file_path = ?1",
+                    params![namespace],
+                )
+                .map_err(|e| e.to_string())?;
+            }
+            for (key, value) in values {
+                if mode == ImportMode::Merge && existing.contains_key(key) {
+                    continue;
+                }
+                tx.execute(
+                    "INSERT INTO artifact_storage (file_path, key, value, updated_at)
+                     VALUES (?1, ?2, ?3, unixepoch())",
+                    params![namespace, key, value],
+                )
+                .map_err(|e| e.to_string())?;
+                imported += 1;
+            }
+        }
+        tx.commit().map_err(|e| e.to_string())?;
+        Ok(StorageImported {
+            mode,
+            imported,
+            conflicts,
+        })
+    }
+
+    /// Checks a namespace's complete contents against the quota.
+    fn check_namespace(
+        &self,
+        namespace: &str,
+        values: &BTreeMap<String, String>,
+    ) -> Result<(), StorageError> {
+        let quota = &self.quota;
+        let exceeded = |message: String| Err(StorageError::Quota { message });
+        let name = namespace_name(namespace);
+        if let Some((key, value)) = values
+            .iter()
+            .find(|(_, value)| value.len() as u64 > quota.max_value_bytes)
+        {
+            return exceeded(format!(
+                "The value for \"{key}\" in {name} is {} bytes; values are limited to {} bytes.",
+                value.len(),
+                quota.max_value_bytes
+            ));
+        }
+        if values.len() as u64 > quota.max_keys {
+            return exceeded(format!(
+                "Importing would leave {} keys in {name}; storage is limited to {} keys.",
+                values.len(),
+                quota.max_keys
+            ));
+        }
+        let total: u64 = values
+            .iter()
+            .map(|(key, value)| (key.len() + value.len()) as u64)
+            .sum();
+        if total > quota.max_total_bytes {
+            return exceeded(format!(
+                "Importing would leave {total} bytes in {name}; storage is limited to {} bytes.",
+                quota.max_total_bytes
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// How a namespace is named in messages.
+fn namespace_name(namespace: &str) -> &str {
+    if namespace == SHARED_NAMESPACE {
+        "the shared values"
+    } else {
+        namespace
+    }
+}
+
+fn namespace_values(
+    conn: &Connection,
+    file_path: &str,
+) -> Result<BTreeMap<String, String>, String> {
+    let mut stmt = conn
+        .prepare("SELECT key, value FROM artifact_storage WHERE file_path = ?1")
+        .map_err(|e| e.to_string())?;
+    let values = stmt
+        .query_map(params![file_path], |row| Ok((row.get(0)?, row.get(1)?)))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<BTreeMap<String, String>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(values)
+}
+
+fn repoint_in(conn: &Connection, from: &str, to: &str) -> Result<bool, String> {
+    let moved = conn
+        .execute(
+            "UPDATE OR REPLACE artifact_storage SET file_path = ?2 WHERE file_path = ?1",
+            params![from, to],
+        )
+        .map_err(|e| e.to_string())?;
+    conn.execute("DELETE FROM artifacts WHERE file_path = ?1", params![to])
+        .map_err(|e| e.to_string())?;
+    conn.execute(
+        "UPDATE artifacts SET file_path = ?2 WHERE file_path = ?1",
+        params![from, to],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(moved > 0)
+}
+
+/// What identifies an artifact apart from its path.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ArtifactIdentity {
+    /// The file's identity on disk (device and inode), which a rename or a
+    /// move within the same disk keeps. Only known on Unix.
+    pub id: Option<String>,
+    /// SHA-256 of the file's contents.
+    pub fingerprint: Option<String>,
+    /// When the file was created, in nanoseconds since the Unix epoch, where
+    /// the file system records it.
+    pub created: Option<i64>,
+}
+
+impl ArtifactIdentity {
+    pub fn of(path: &Path) -> Self {
+        #[cfg(unix)]
+        let id = {
+            use std::os::unix::fs::MetadataExt;
+            std::fs::metadata(path)
+                .ok()
+                .map(|m| format!("{}:{}", m.dev(), m.ino()))
+        };
+        #[cfg(not(unix))]
+        let id = None;
+        let fingerprint = std::fs::read(path)
+            .ok()
+            .map(|data| format!("{:x}", Sha256::digest(&data)));
+        let created = std::fs::metadata(path)
+            .and_then(|m| m.created())
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .and_then(|d| i64::try_from(d.as_nanos()).ok());
+        Self {
+            id,
+            fingerprint,
+            created,
+        }
+    }
+
+    /// Whether `other` is the same artifact. The same contents are enough.
+    /// The same artifact ID alone isn't, since file systems reuse a deleted
+    /// file's inode for new files, so it also takes the same file name
+    /// (`same_name`) and creation time.
+    pub fn same_artifact(&self, other: &Self, same_name: bool) -> bool {
+        fn known_and_equal<T: PartialEq>(a: &Option<T>, b: &Option<T>) -> bool {
+            a.is_some() && a == b
+        }
+        known_and_equal(&self.fingerprint, &other.fingerprint)
+            || (known_and_equal(&self.id, &other.id)
+                && same_name
+                && known_and_equal(&self.created, &other.created))
+    }
+}
+
+/// Lets the file at `path`, just opened in a window, pick up the storage it
+/// had under an earlier path (best-effort).
+pub fn adopt_file(db: &StorageDb, path: &Path) {
+    let file_path = path.to_string_lossy();
+    match db.adopt(&file_path, &ArtifactIdentity::of(path), |p| {
+        Path::new(p).exists()
+    }) {
+        Ok(Some(from)) => log::info!("Storage of {from} moved to {file_path}"),
+        Ok(None) => {}
+        Err(e) => log::warn!("Failed to record artifact {file_path}: {e}"),
+    }
+}
+
+/// A stored value, shaped like the results of Claude's `window.storage`.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct StorageItem {
+    pub key: String,
+    pub value: String,
+    pub shared: bool,
+}
+
+/// The result of deleting a key, as Claude's `window.storage.delete`
+/// returns it.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct StorageDeleted {
+    pub key: String,
+    pub deleted: bool,
+    pub shared: bool,
+}
+
+/// The keys under a prefix, as Claude's `window.storage.list` returns them.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct StorageKeys {
+    pub keys: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    pub shared: bool,
+}
+
+/// Version of the [`StorageExport`] document; bumped when its shape changes.
+pub const EXPORT_FORMAT: u32 = 1;
+
+/// Saved values as a JSON document, for handing an artifact to someone
+/// along with its state, or restoring state between demos.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StorageExport {
+    pub format: u32,
+    /// Values by namespace: a file's path, or `""` for the shared values.
+    pub namespaces: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl StorageExport {
+    fn check_format(&self) -> Result<(), StorageError> {
+        if self.format != EXPORT_FORMAT {
+            return Err(StorageError::Format {
+                message: format!(
+                    "This storage export is format {}; this version of Terrarium reads format {EXPORT_FORMAT}.",
+                    self.format
+                ),
+            });
+        }
         Ok(())
     }
+
+    /// Reads an export written by [`StorageExport::write`].
+    fn read(path: &Path) -> Result<Self, StorageError> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+        let export: Self = serde_json::from_str(&json).map_err(|e| StorageError::Format {
+            message: format!("Not a Terrarium storage export: {e}"),
+        })?;
+        Ok(export)
+    }
+
+    fn write(&self, path: &Path) -> Result<(), StorageError> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        std::fs::write(path, json)
+            .map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+        Ok(())
+    }
+
+    fn values(&self) -> usize {
+        self.namespaces.values().map(BTreeMap::len).sum()
+    }
+
+    /// Moves the values of a single-namespace export into `namespace`, so
+    /// an export of one file can be imported into another.
+    fn into_namespace(self, namespace: &str) -> Result<Self, StorageError> {
+        if self.namespaces.len() > 1 {
+            return Err(StorageError::Forbidden {
+                message: format!(
+                    "This export holds {} namespaces; a window can only import one.",
+                    self.namespaces.len()
+                ),
+            });
+        }
+        Ok(Self {
+            format: self.format,
+            namespaces: self
+                .namespaces
+                .into_values()
+                .map(|values| (namespace.to_string(), values))
+                .collect(),
+        })
+    }
+}
+
+/// How [`StorageDb::import`] treats the values already stored.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportMode {
+    /// Add the imported keys, keeping stored values on conflict.
+    #[default]
+    Merge,
+    /// Clear each imported namespace, then write the imported values.
+    Replace,
+}
+
+/// A key both stored and imported, with different values.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct StorageConflict {
+    pub namespace: String,
+    pub key: String,
+    pub existing: String,
+    pub imported: String,
+}
+
+/// What an import did: how many values it wrote, and the conflicts it met.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct StorageImported {
+    pub mode: ImportMode,
+    pub imported: usize,
+    pub conflicts: Vec<StorageConflict>,
 }
 
 use tauri::State;
 
+/// The namespace for a call from `window`; see [`namespace`].
+fn window_namespace(
+    app_state: &crate::AppState,
+    window: &tauri::Window,
+    claimed: Option<&str>,
+    shared: bool,
+) -> Result<String, StorageError> {
+    let windows = app_state
+        .windows
+        .lock()
+        .map_err(|_| "Internal state error".to_string())?;
+    namespace(&windows, window.label(), claimed, shared)
+}
+
 #[tauri::command]
 pub async fn storage_get(
     db: State<'_, StorageDb>,
-    file_path: String,
+    app_state: State<'_, crate::AppState>,
+    window: tauri::Window,
+    file_path: Option<String>,
     key: String,
-) -> Result<Option<String>, String> {
-    db.get(&file_path, &key)
+    shared: Option<bool>,
+) -> Result<Option<StorageItem>, StorageError> {
+    let shared = shared.unwrap_or(false);
+    let namespace = window_namespace(&app_state, &window, file_path.as_deref(), shared)?;
+    let value = db.get(&namespace, &key)?;
+    Ok(value.map(|value| StorageItem { key, value, shared }))
 }
 
 #[tauri::command]
 pub async fn storage_set(
     db: State<'_, StorageDb>,
-    file_path: String,
+    app_state: State<'_, crate::AppState>,
+    window: tauri::Window,
+    file_path: Option<String>,
     key: String,
     value: String,
-) -> Result<(), String> {
-    db.set(&file_path, &key, &value)
+    shared: Option<bool>,
+) -> Result<StorageItem, StorageError> {
+    let shared = shared.unwrap_or(false);
+    let namespace = window_namespace(&app_state, &window, file_path.as_deref(), shared)?;
+    db.set(&namespace, &key, &value)?;
+    Ok(StorageItem { key, value, shared })
 }
 
 #[tauri::command]
 pub async fn storage_remove(
     db: State<'_, StorageDb>,
-    file_path: String,
+    app_state: State<'_, crate::AppState>,
+    window: tauri::Window,
+    file_path: Option<String>,
     key: String,
-) -> Result<(), String> {
-    db.remove(&file_path, &key)
+    shared: Option<bool>,
+) -> Result<StorageDeleted, StorageError> {
+    let shared = shared.unwrap_or(false);
+    let namespace = window_namespace(&app_state, &window, file_path.as_deref(), shared)?;
+    let deleted = db.remove(&namespace, &key)?;
+    Ok(StorageDeleted {
+        key,
+        deleted,
+        shared,
+    })
+}
+
+#[tauri::command]
+pub async fn storage_list(
+    db: State<'_, StorageDb>,
+    app_state: State<'_, crate::AppState>,
+    window: tauri::Window,
+    file_path: Option<String>,
+    prefix: Option<String>,
+    shared: Option<bool>,
+) -> Result<StorageKeys, StorageError> {
+    let shared = shared.unwrap_or(false);
+    let namespace = window_namespace(&app_state, &window, file_path.as_deref(), shared)?;
+    let keys = db.list(&namespace, prefix.as_deref().unwrap_or(""))?;
+    Ok(StorageKeys {
+        keys,
+        prefix,
+        shared,
+    })
+}
+
+/// The name a window's values go under in an export: the file's name
+/// rather than its path, which means nothing on a teammate's machine.
+fn export_name(namespace: &str) -> String {
+    Path::new(namespace)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Asks where to save the calling window's values, or the shared ones, and
+/// writes them as JSON. Returns how many values were saved, or `None` if the
+/// user cancelled.
+#[tauri::command]
+pub async fn storage_export(
+    app: tauri::AppHandle,
+    db: State<'_, StorageDb>,
+    app_state: State<'_, crate::AppState>,
+    window: tauri::Window,
+    shared: Option<bool>,
+) -> Result<Option<usize>, StorageError> {
+    use tauri_plugin_dialog::DialogExt;
+
+    let namespace = window_namespace(&app_state, &window, None, shared.unwrap_or(false))?;
+    let name = export_name(&namespace);
+    let file_name = match Path::new(&name).file_stem() {
+        Some(stem) => format!("{}-storage.json", stem.to_string_lossy()),
+        None => "shared-storage.json".to_string(),
+    };
+    let Some(dest) = app
+        .dialog()
+        .file()
+        .add_filter("Terrarium Storage", &["json"])
+        .set_file_name(file_name)
+        .blocking_save_file()
+        .and_then(|f| f.into_path().ok())
+    else {
+        return Ok(None);
+    };
+    let export = db.export(Some(&namespace))?.into_namespace(&name)?;
+    export.write(&dest)?;
+    Ok(Some(export.values()))
+}
+
+/// Asks for a storage export of a single file and imports it into the
+/// calling window's namespace, or the shared one, whichever file it was
+/// exported from. Returns `None` if the user cancelled.
+#[tauri::command]
+pub async fn storage_import(
+    app: tauri::AppHandle,
+    db: State<'_, StorageDb>,
+    app_state: State<'_, crate::AppState>,
+    window: tauri::Window,
+    mode: Option<ImportMode>,
+    shared: Option<bool>,
+) -> Result<Option<StorageImported>, StorageError> {
+    use tauri_plugin_dialog::DialogExt;
+
+    let namespace = window_namespace(&app_state, &window, None, shared.unwrap_or(false))?;
+    let Some(source) = app
+        .dialog()
+        .file()
+        .add_filter("Terrarium Storage", &["json"])
+        .blocking_pick_file()
+        .and_then(|f| f.into_path().ok())
+    else {
+        return Ok(None);
+    };
+    let export = StorageExport::read(&source)?.into_namespace(&namespace)?;
+    db.import(&export, mode.unwrap_or_default()).map(Some)
+}
+
+fn error_message(error: StorageError) -> String {
+    match error {
+        StorageError::Quota { message }
+        | StorageError::Forbidden { message }
+        | StorageError::Format { message }
+        | StorageError::Database { message } => message,
+    }
+}
+
+/// Asks where to save every file's storage and writes it as JSON, keyed by
+/// path, for restoring this machine's state later.
+pub async fn export_all_with_dialog<R: tauri::Runtime>(app_handle: tauri::AppHandle<R>) {
+    use tauri::Manager;
+    use tauri_plugin_dialog::DialogExt;
+
+    let Some(dest) = app_handle
+        .dialog()
+        .file()
+        .add_filter("Terrarium Storage", &["json"])
+        .set_file_name("terrarium-storage.json")
+        .blocking_save_file()
+        .and_then(|f| f.into_path().ok())
+    else {
+        return;
+    };
+    let result = app_handle
+        .state::<StorageDb>()
+        .export(None)
+        .map_err(StorageError::from)
+        .and_then(|export| {
+            export.write(&dest)?;
+            Ok(format!(
+                "Exported {} values from {} namespaces.",
+                export.values(),
+                export.namespaces.len()
+            ))
+        })
+        .map_err(error_message);
+    crate::show_result(&app_handle, "Storage", result);
+}
+
+/// Asks for a storage export and imports it under the paths it was
+/// exported from, merging or replacing as the user chooses.
+pub async fn import_all_with_dialog<R: tauri::Runtime>(app_handle: tauri::AppHandle<R>) {
+    use tauri::Manager;
+    use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogResult};
+
+    let Some(source) = app_handle
+        .dialog()
+        .file()
+        .add_filter("Terrarium Storage", &["json"])
+        .blocking_pick_file()
+        .and_then(|f| f.into_path().ok())
+    else {
+        return;
+    };
+    let export = match StorageExport::read(&source) {
+        Ok(export) => export,
+        Err(e) => return crate::show_result(&app_handle, "Storage", Err(error_message(e))),
+    };
+    let choice = app_handle
+        .dialog()
+        .message("Merge keeps values already stored. Replace clears the storage of each file in the export before importing.")
+        .title("Import Storage")
+        .buttons(MessageDialogButtons::YesNoCancelCustom(
+            "Merge".to_string(),
+            "Replace".to_string(),
+            "Cancel".to_string(),
+        ))
+        .blocking_show_with_result();
+    // Closing the dialog or pressing Escape cancels the import.
+    let mode = match choice {
+        MessageDialogResult::Yes => ImportMode::Merge,
+        MessageDialogResult::No => ImportMode::Replace,
+        MessageDialogResult::Custom(label) if label == "Merge" => ImportMode::Merge,
+        MessageDialogResult::Custom(label) if label == "Replace" => ImportMode::Replace,
+        _ => return,
+    };
+    let result = app_handle
+        .state::<StorageDb>()
+        .import(&export, mode)
+        .map_err(error_message)
+        .map(|imported| {
+            let mut message = format!("Imported {} values.", imported.imported);
+            if !imported.conflicts.is_empty() {
+                let kept = match mode {
+                    ImportMode::Merge => "kept the stored value",
+                    ImportMode::Replace => "replaced the stored value",
+                };
+                message.push_str(&format!(
+                    " {} keys already had a different value; the import {kept}:",
+                    imported.conflicts.len()
+                ));
+                for conflict in imported.conflicts.iter().take(10) {
+                    message.push_str(&format!(
+                        "\n{} in {}",
+                        conflict.key,
+                        namespace_name(&conflict.namespace)
+                    ));
+                }
+            }
+            message
+        });
+    crate::show_result(&app_handle, "Storage", result);
 }
 
 #[cfg(test)]
@@ -154,4 +992,436 @@ mod tests {
         let result = db.remove("file.tsx", "nope");
         assert!(result.is_ok());
     }
+
+    fn quota_db(quota: StorageQuota) -> (StorageDb, NamedTempFile) {
+        let (db, tmp) = test_db();
+        (db.with_quota(quota), tmp)
+    }
+
+    fn is_quota_error(result: Result<(), StorageError>) -> bool {
+        matches!(result, Err(StorageError::Quota { .. }))
+    }
+
+    #[test]
+    fn rejects_values_over_the_size_limit() {
+        let (db, _tmp) = quota_db(StorageQuota {
+            max_value_bytes: 4,
+            ..StorageQuota::default()
+        });
+        assert!(db.set("file.tsx", "key", "four").is_ok());
+        assert!(is_quota_error(db.set("file.tsx", "key", "fives")));
+        assert_eq!(db.get("file.tsx", "key").unwrap(), Some("four".to_string()));
+    }
+
+    #[test]
+    fn rejects_keys_over_the_count_limit() {
+        let (db, _tmp) = quota_db(StorageQuota {
+            max_keys: 2,
+            ..StorageQuota::default()
+        });
+        db.set("file.tsx", "a", "1").unwrap();
+        db.set("file.tsx", "b", "2").unwrap();
+        assert!(is_quota_error(db.set("file.tsx", "c", "3")));
+        // Overwriting an existing key doesn't add one.
+        assert!(db.set("file.tsx", "b", "22").is_ok());
+        // The limit is per namespace.
+        assert!(db.set("other.tsx", "c", "3").is_ok());
+    }
+
+    #[test]
+    fn rejects_writes_over_the_total_limit() {
+        let (db, _tmp) = quota_db(StorageQuota {
+            max_total_bytes: 10,
+            ..StorageQuota::default()
+        });
+        db.set("file.tsx", "a", "1234").unwrap();
+        db.set("file.tsx", "b", "1234").unwrap();
+        let err = db.set("file.tsx", "c", "1").unwrap_err();
+        assert_eq!(
+            err,
+            StorageError::Quota {
+                message: "Storing \"c\" would use 12 bytes; storage is limited to 10 bytes."
+                    .to_string()
+            }
+        );
+        // Shrinking a value to fit is fine.
+        assert!(db.set("file.tsx", "b", "1").is_ok());
+    }
+
+    #[test]
+    fn quota_errors_serialize_with_their_type() {
+        let err = StorageError::Quota {
+            message: "full".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_value(err).unwrap(),
+            serde_json::json!({ "type": "quota", "message": "full" })
+        );
+    }
+
+    #[test]
+    fn remove_reports_whether_the_key_existed() {
+        let (db, _tmp) = test_db();
+        db.set("file.tsx", "key", "val").unwrap();
+        assert_eq!(db.remove("file.tsx", "key"), Ok(true));
+        assert_eq!(db.remove("file.tsx", "key"), Ok(false));
+    }
+
+    #[test]
+    fn list_filters_by_prefix_within_a_file() {
+        let (db, _tmp) = test_db();
+        db.set("file.tsx", "todo:2", "b").unwrap();
+        db.set("file.tsx", "todo:1", "a").unwrap();
+        db.set("file.tsx", "theme", "dark").unwrap();
+        db.set("file.tsx", "todo%", "literal").unwrap();
+        db.set("other.tsx", "todo:3", "c").unwrap();
+        assert_eq!(
+            db.list("file.tsx", "todo:").unwrap(),
+            vec!["todo:1".to_string(), "todo:2".to_string()]
+        );
+        assert_eq!(
+            db.list("file.tsx", "todo%").unwrap(),
+            vec!["todo%".to_string()]
+        );
+        assert_eq!(db.list("file.tsx", "").unwrap().len(), 4);
+        assert!(db.list("empty.tsx", "").unwrap().is_empty());
+    }
+
+    fn windows() -> HashMap<String, crate::WindowState> {
+        HashMap::from([
+            (
+                "main".to_string(),
+                crate::WindowState::new("/projects/a.tsx".into()),
+            ),
+            (
+                "window-2".to_string(),
+                crate::WindowState::new("/projects/b.tsx".into()),
+            ),
+        ])
+    }
+
+    #[test]
+    fn namespace_comes_from_the_window() {
+        let windows = windows();
+        assert_eq!(
+            namespace(&windows, "window-2", None, false),
+            Ok("/projects/b.tsx".to_string())
+        );
+        assert_eq!(
+            namespace(&windows, "window-2", Some("/projects/b.tsx"), false),
+            Ok("/projects/b.tsx".to_string())
+        );
+    }
+
+    #[test]
+    fn namespace_refuses_another_windows_file() {
+        let windows = windows();
+        assert!(matches!(
+            namespace(&windows, "window-2", Some("/projects/a.tsx"), false),
+            Err(StorageError::Forbidden { .. })
+        ));
+        assert!(matches!(
+            namespace(&windows, "window-9", Some("/projects/a.tsx"), false),
+            Err(StorageError::Forbidden { .. })
+        ));
+    }
+
+    #[test]
+    fn shared_values_are_visible_from_every_window() {
+        let (db, _tmp) = test_db();
+        let windows = windows();
+        let shared = |label| namespace(&windows, label, None, true).unwrap();
+        let own = |label| namespace(&windows, label, None, false).unwrap();
+        db.set(&shared("main"), "leaderboard", "[]").unwrap();
+        db.set(&own("main"), "leaderboard", "mine").unwrap();
+        assert_eq!(
+            db.get(&shared("window-2"), "leaderboard").unwrap(),
+            Some("[]".to_string())
+        );
+        assert_eq!(db.get(&own("window-2"), "leaderboard").unwrap(), None);
+        assert_eq!(
+            db.get(&own("main"), "leaderboard").unwrap(),
+            Some("mine".to_string())
+        );
+        // Even a window without a file can use shared values.
+        assert_eq!(
+            namespace(&windows, "window-9", None, true),
+            Ok(SHARED_NAMESPACE.to_string())
+        );
+    }
+
+    #[test]
+    fn repoint_moves_storage_and_overwrites_clashing_keys() {
+        let (db, _tmp) = test_db();
+        db.set("/old/a.tsx", "count", "3").unwrap();
+        db.set("/old/a.tsx", "theme", "dark").unwrap();
+        db.set("/new/a.tsx", "count", "0").unwrap();
+        assert!(db.repoint("/old/a.tsx", "/new/a.tsx").unwrap());
+        assert_eq!(
+            db.get("/new/a.tsx", "count").unwrap(),
+            Some("3".to_string())
+        );
+        assert_eq!(
+            db.get("/new/a.tsx", "theme").unwrap(),
+            Some("dark".to_string())
+        );
+        assert!(db.list("/old/a.tsx", "").unwrap().is_empty());
+        assert!(!db.repoint("/old/a.tsx", "/new/a.tsx").unwrap());
+    }
+
+    fn identity(id: Option<&str>, fingerprint: &str) -> ArtifactIdentity {
+        ArtifactIdentity {
+            id: id.map(str::to_string),
+            fingerprint: Some(fingerprint.to_string()),
+            created: id.map(|_| 1_700_000_000_000_000_000),
+        }
+    }
+
+    #[test]
+    fn moved_file_adopts_storage_by_artifact_id() {
+        let (db, _tmp) = test_db();
+        db.adopt("/old/a.tsx", &identity(Some("1:42"), "aaa"), |_| true)
+            .unwrap();
+        db.set("/old/a.tsx", "count", "3").unwrap();
+        // Edited and moved: the contents differ, the inode, name and
+        // creation time don't.
+        let adopted = db
+            .adopt("/new/a.tsx", &identity(Some("1:42"), "bbb"), |p| {
+                p != "/old/a.tsx"
+            })
+            .unwrap();
+        assert_eq!(adopted, Some("/old/a.tsx".to_string()));
+        assert_eq!(
+            db.get("/new/a.tsx", "count").unwrap(),
+            Some("3".to_string())
+        );
+    }
+
+    #[test]
+    fn reused_inode_does_not_adopt_storage() {
+        let (db, _tmp) = test_db();
+        db.adopt("/old/a.tsx", &identity(Some("1:42"), "aaa"), |_| true)
+            .unwrap();
+        db.set("/old/a.tsx", "count", "3").unwrap();
+        // a.tsx was deleted and an unrelated file got its inode.
+        let unrelated = ArtifactIdentity {
+            created: Some(1_800_000_000_000_000_000),
+            ..identity(Some("1:42"), "bbb")
+        };
+        let missing = |p: &str| p != "/old/a.tsx";
+        assert_eq!(db.adopt("/old/b.tsx", &unrelated, missing).unwrap(), None);
+        assert_eq!(db.get("/old/b.tsx", "count").unwrap(), None);
+        // Even under the same name, a different creation time gives it away.
+        assert_eq!(db.adopt("/new/a.tsx", &unrelated, missing).unwrap(), None);
+        assert_eq!(
+            db.get("/old/a.tsx", "count").unwrap(),
+            Some("3".to_string())
+        );
+    }
+
+    #[test]
+    fn moved_file_adopts_storage_by_fingerprint() {
+        let (db, _tmp) = test_db();
+        db.adopt("/old/a.tsx", &identity(Some("1:42"), "aaa"), |_| true)
+            .unwrap();
+        db.set("/old/a.tsx", "count", "3").unwrap();
+        // Copied to another disk, then the original deleted.
+        let adopted = db
+            .adopt("/usb/a.tsx", &identity(Some("2:7"), "aaa"), |p| {
+                p != "/old/a.tsx"
+            })
+            .unwrap();
+        assert_eq!(adopted, Some("/old/a.tsx".to_string()));
+        assert_eq!(
+            db.get("/usb/a.tsx", "count").unwrap(),
+            Some("3".to_string())
+        );
+    }
+
+    #[test]
+    fn copy_does_not_take_the_originals_storage() {
+        let (db, _tmp) = test_db();
+        db.adopt("/old/a.tsx", &identity(Some("1:42"), "aaa"), |_| true)
+            .unwrap();
+        db.set("/old/a.tsx", "count", "3").unwrap();
+        let adopted = db
+            .adopt("/old/copy.tsx", &identity(Some("1:43"), "aaa"), |_| true)
+            .unwrap();
+        assert_eq!(adopted, None);
+        assert_eq!(db.get("/old/copy.tsx", "count").unwrap(), None);
+        assert_eq!(
+            db.get("/old/a.tsx", "count").unwrap(),
+            Some("3".to_string())
+        );
+    }
+
+    #[test]
+    fn file_with_storage_keeps_its_own() {
+        let (db, _tmp) = test_db();
+        db.adopt("/old/a.tsx", &identity(None, "aaa"), |_| true)
+            .unwrap();
+        db.set("/old/a.tsx", "count", "3").unwrap();
+        db.set("/new/a.tsx", "count", "9").unwrap();
+        let adopted = db
+            .adopt("/new/a.tsx", &identity(None, "aaa"), |_| false)
+            .unwrap();
+        assert_eq!(adopted, None);
+        assert_eq!(
+            db.get("/new/a.tsx", "count").unwrap(),
+            Some("9".to_string())
+        );
+    }
+
+    #[test]
+    fn artifact_identity_reads_the_file() {
+        let tmp = NamedTempFile::new().unwrap();
+        std::fs::write(tmp.path(), "export default () => null").unwrap();
+        let identity = ArtifactIdentity::of(tmp.path());
+        assert_eq!(identity.fingerprint.as_ref().map(String::len), Some(64));
+        assert_eq!(identity.id.is_some(), cfg!(unix));
+        assert_eq!(
+            ArtifactIdentity::of(Path::new("/nonexistent/a.tsx")),
+            ArtifactIdentity::default()
+        );
+    }
+
+    fn export_of(namespace: &str, values: &[(&str, &str)]) -> StorageExport {
+        StorageExport {
+            format: EXPORT_FORMAT,
+            namespaces: BTreeMap::from([(
+                namespace.to_string(),
+                values
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+            )]),
+        }
+    }
+
+    #[test]
+    fn export_one_namespace_or_all() {
+        let (db, _tmp) = test_db();
+        db.set("/a.tsx", "count", "3").unwrap();
+        db.set("/b.tsx", "theme", "dark").unwrap();
+        db.set(SHARED_NAMESPACE, "scores", "[]").unwrap();
+        assert_eq!(
+            db.export(Some("/a.tsx")).unwrap(),
+            export_of("/a.tsx", &[("count", "3")])
+        );
+        let all = db.export(None).unwrap();
+        assert_eq!(all.format, EXPORT_FORMAT);
+        assert_eq!(
+            all.namespaces.keys().collect::<Vec<_>>(),
+            vec!["", "/a.tsx", "/b.tsx"]
+        );
+        let json = serde_json::to_value(&all).unwrap();
+        assert_eq!(json["namespaces"]["/b.tsx"]["theme"], "dark");
+    }
+
+    #[test]
+    fn export_round_trips_through_import() {
+        let (db, _tmp) = test_db();
+        db.set("/a.tsx", "count", "3").unwrap();
+        db.set(SHARED_NAMESPACE, "scores", "[]").unwrap();
+        let json = serde_json::to_string(&db.export(None).unwrap()).unwrap();
+
+        let (fresh, _tmp2) = test_db();
+        let export: StorageExport = serde_json::from_str(&json).unwrap();
+        let imported = fresh.import(&export, ImportMode::Merge).unwrap();
+        assert_eq!(imported.imported, 2);
+        assert!(imported.conflicts.is_empty());
+        assert_eq!(fresh.export(None).unwrap(), db.export(None).unwrap());
+    }
+
+    #[test]
+    fn merge_keeps_stored_values_and_reports_conflicts() {
+        let (db, _tmp) = test_db();
+        db.set("/a.tsx", "count", "3").unwrap();
+        db.set("/a.tsx", "theme", "dark").unwrap();
+        let export = export_of(
+            "/a.tsx",
+            &[("count", "0"), ("theme", "dark"), ("name", "demo")],
+        );
+        let imported = db.import(&export, ImportMode::Merge).unwrap();
+        assert_eq!(imported.imported, 1);
+        assert_eq!(
+            imported.conflicts,
+            vec![StorageConflict {
+                namespace: "/a.tsx".to_string(),
+                key: "count".to_string(),
+                existing: "3".to_string(),
+                imported: "0".to_string(),
+            }]
+        );
+        assert_eq!(db.get("/a.tsx", "count").unwrap(), Some("3".to_string()));
+        assert_eq!(db.get("/a.tsx", "name").unwrap(), Some("demo".to_string()));
+    }
+
+    #[test]
+    fn replace_clears_the_namespace_first() {
+        let (db, _tmp) = test_db();
+        db.set("/a.tsx", "count", "3").unwrap();
+        db.set("/a.tsx", "stale", "x").unwrap();
+        db.set("/b.tsx", "count", "9").unwrap();
+        let imported = db
+            .import(&export_of("/a.tsx", &[("count", "0")]), ImportMode::Replace)
+            .unwrap();
+        assert_eq!(imported.imported, 1);
+        assert_eq!(imported.conflicts.len(), 1);
+        assert_eq!(db.list("/a.tsx", "").unwrap(), vec!["count".to_string()]);
+        assert_eq!(db.get("/a.tsx", "count").unwrap(), Some("0".to_string()));
+        // Namespaces missing from the export are untouched.
+        assert_eq!(db.get("/b.tsx", "count").unwrap(), Some("9".to_string()));
+    }
+
+    #[test]
+    fn import_over_quota_writes_nothing() {
+        let (db, _tmp) = test_db();
+        let db = db.with_quota(StorageQuota {
+            max_keys: 2,
+            ..StorageQuota::default()
+        });
+        db.set("/a.tsx", "count", "3").unwrap();
+        let mut export = export_of("/a.tsx", &[("x", "1"), ("y", "2")]);
+        export.namespaces.insert(
+            "/b.tsx".to_string(),
+            BTreeMap::from([("z".to_string(), "1".to_string())]),
+        );
+        assert!(matches!(
+            db.import(&export, ImportMode::Merge),
+            Err(StorageError::Quota { .. })
+        ));
+        assert_eq!(db.get("/b.tsx", "z").unwrap(), None);
+        // Replacing frees the keys the merge would have kept.
+        assert!(db.import(&export, ImportMode::Replace).is_ok());
+    }
+
+    #[test]
+    fn import_refuses_other_formats() {
+        let (db, _tmp) = test_db();
+        let export = StorageExport {
+            format: EXPORT_FORMAT + 1,
+            ..export_of("/a.tsx", &[("count", "3")])
+        };
+        assert!(matches!(
+            db.import(&export, ImportMode::Merge),
+            Err(StorageError::Format { .. })
+        ));
+        assert_eq!(db.get("/a.tsx", "count").unwrap(), None);
+    }
+
+    #[test]
+    fn single_namespace_export_moves_to_another_file() {
+        let export = export_of("/home/alex/a.tsx", &[("count", "3")]);
+        assert_eq!(
+            export.into_namespace("/projects/a.tsx").unwrap(),
+            export_of("/projects/a.tsx", &[("count", "3")])
+        );
+        let mut two = export_of("/a.tsx", &[]);
+        two.namespaces.insert("/b.tsx".to_string(), BTreeMap::new());
+        assert!(matches!(
+            two.into_namespace("/projects/a.tsx"),
+            Err(StorageError::Forbidden { .. })
+        ));
+    }
 }
@@ -1,14 +1,185 @@
+use notify::event::{ModifyKind, RenameMode};
 use notify::{Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
-use std::path::PathBuf;
-use std::sync::mpsc;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex, Weak};
 use std::time::{Duration, Instant};
 use tauri::Emitter;
 
+use crate::storage::ArtifactIdentity;
+
+/// Watches an entry file and every local file its last bundle read.
+///
+/// The watched set starts as just the entry and is replaced with the
+/// bundler's reported inputs after every successful rebuild, so editing an
+/// imported `data.ts` or `theme.css` reloads the preview too. Dropping the
+/// `FileWatcher` stops watching and ends its background thread.
+pub struct FileWatcher {
+    shared: Arc<Mutex<Watched>>,
+}
+
+struct Watched {
+    watcher: RecommendedWatcher,
+    entry: PathBuf,
+    files: HashSet<PathBuf>,
+    dirs: HashSet<PathBuf>,
+}
+
+impl FileWatcher {
+    /// Replaces the watched set with `inputs` (plus the entry file).
+    pub fn set_inputs(&self, inputs: &[PathBuf]) {
+        if let Ok(mut watched) = self.shared.lock() {
+            watched.set_inputs(inputs);
+        }
+    }
+
+    /// The files currently being watched, sorted.
+    pub fn files(&self) -> Vec<PathBuf> {
+        let Ok(watched) = self.shared.lock() else {
+            return Vec::new();
+        };
+        let mut files: Vec<PathBuf> = watched.files.iter().cloned().collect();
+        files.sort();
+        files
+    }
+}
+
+impl Watched {
+    fn set_inputs(&mut self, inputs: &[PathBuf]) {
+        let files: HashSet<PathBuf> = std::iter::once(self.entry.clone())
+            .chain(inputs.iter().cloned())
+            .collect();
+        let dirs = watch_dirs(&files);
+
+        for stale in self.dirs.difference(&dirs) {
+            let _ = self.watcher.unwatch(stale);
+        }
+        // Best-effort: an input whose directory has vanished just isn't watched
+        // until a later bundle stops reporting it.
+        let mut watching: HashSet<PathBuf> = self.dirs.intersection(&dirs).cloned().collect();
+        for dir in dirs.difference(&self.dirs) {
+            if self.watcher.watch(dir, RecursiveMode::NonRecursive).is_ok() {
+                watching.insert(dir.clone());
+            }
+        }
+
+        self.files = files;
+        self.dirs = watching;
+    }
+}
+
+/// Directories to watch for `files`. Parents are watched rather than the
+/// files themselves to catch renames (Vim-style save: write temp → delete →
+/// rename).
+fn watch_dirs(files: &HashSet<PathBuf>) -> HashSet<PathBuf> {
+    files
+        .iter()
+        .map(|f| f.parent().unwrap_or(f).to_path_buf())
+        .collect()
+}
+
+fn is_watched(shared: &Weak<Mutex<Watched>>, paths: &[PathBuf]) -> Option<bool> {
+    let shared = shared.upgrade()?;
+    let watched = shared.lock().ok()?;
+    Some(paths.iter().any(|p| watched.files.contains(p)))
+}
+
+/// Where `entry` went, if `event` is it being renamed to another TSX/JSX
+/// file. Renames to anything else (an editor's `App.tsx~` backup) don't count.
+fn renamed_to(event: &Event, entry: &Path) -> Option<PathBuf> {
+    if event.kind != EventKind::Modify(ModifyKind::Name(RenameMode::Both)) {
+        return None;
+    }
+    match event.paths.as_slice() {
+        [from, to] if from == entry && to != entry && crate::is_supported_ext(to) => {
+            Some(to.clone())
+        }
+        _ => None,
+    }
+}
+
+/// Where `entry` went, if `event` shows it being renamed or moved away and
+/// it is gone from disk. A rename with both paths (inotify, within the
+/// directory) says so directly. Otherwise, as with a move out of the
+/// directory (inotify reports only the old path) or any rename on macOS
+/// (one event per path), the new path is looked for by `identity`: among
+/// the event's paths, then under the entry's directory.
+fn moved_to(event: &Event, entry: &Path, identity: &ArtifactIdentity) -> Option<PathBuf> {
+    if !matches!(event.kind, EventKind::Modify(ModifyKind::Name(_)))
+        || !event.paths.iter().any(|p| p == entry)
+        || entry.exists()
+    {
+        return None;
+    }
+    if let Some(to) = renamed_to(event, entry) {
+        return Some(to);
+    }
+    let dir = entry.parent()?;
+    let mut candidates: Vec<PathBuf> = event.paths.clone();
+    supported_files(dir, MOVE_SEARCH_DEPTH, &mut candidates);
+    find_moved(entry, identity, candidates)
+}
+
+/// How many directories below the entry's a moved entry is looked for.
+const MOVE_SEARCH_DEPTH: usize = 2;
+/// Upper bound on the files looked at, so a huge tree doesn't stall the
+/// watcher.
+const MOVE_SEARCH_LIMIT: usize = 2000;
+
+/// Collects the TSX/JSX files under `dir`, `depth` levels deep, skipping
+/// hidden directories and `node_modules`.
+fn supported_files(dir: &Path, depth: usize, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        if files.len() >= MOVE_SEARCH_LIMIT {
+            return;
+        }
+        let path = entry.path();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            let name = entry.file_name();
+            let skip = name.to_string_lossy().starts_with('.') || name == "node_modules";
+            if depth > 0 && !skip {
+                supported_files(&path, depth - 1, files);
+            }
+        } else if crate::is_supported_ext(&path) {
+            files.push(path);
+        }
+    }
+}
+
+/// The first of `candidates` that is the same artifact as the vanished
+/// `entry`, preferring the same file on disk over the same contents.
+fn find_moved(
+    entry: &Path,
+    identity: &ArtifactIdentity,
+    candidates: Vec<PathBuf>,
+) -> Option<PathBuf> {
+    let matches: Vec<(PathBuf, ArtifactIdentity)> = candidates
+        .into_iter()
+        .filter(|p| p != entry && crate::is_supported_ext(p))
+        .map(|p| {
+            let other = ArtifactIdentity::of(&p);
+            (p, other)
+        })
+        .filter(|(p, other)| identity.same_artifact(other, p.file_name() == entry.file_name()))
+        .collect();
+    let same_file = matches
+        .iter()
+        .position(|(_, other)| identity.id.is_some() && other.id == identity.id);
+    let index = same_file.unwrap_or(0);
+    matches.into_iter().nth(index).map(|(p, _)| p)
+}
+
 pub fn watch_file<R: tauri::Runtime>(
     app_handle: tauri::AppHandle<R>,
     path: PathBuf,
     window_label: String,
-) -> Result<RecommendedWatcher, String> {
+) -> Result<FileWatcher, String> {
     let (tx, rx) = mpsc::channel();
 
     let mut watcher = RecommendedWatcher::new(
@@ -21,69 +192,316 @@ pub fn watch_file<R: tauri::Runtime>(
     )
     .map_err(|e| format!("Failed to create watcher: {e}"))?;
 
-    // Watch the parent directory to catch renames (Vim-style save: write temp → delete → rename)
-    let watch_dir = path.parent().unwrap_or(&path);
+    let watch_dir = path.parent().unwrap_or(&path).to_path_buf();
     watcher
-        .watch(watch_dir, RecursiveMode::NonRecursive)
+        .watch(&watch_dir, RecursiveMode::NonRecursive)
         .map_err(|e| format!("Failed to watch file: {e}"))?;
 
-    let watched_path = path.clone();
+    let shared = Arc::new(Mutex::new(Watched {
+        watcher,
+        entry: path.clone(),
+        files: HashSet::from([path.clone()]),
+        dirs: HashSet::from([watch_dir]),
+    }));
+    // The thread only holds a weak reference: once the `FileWatcher` is
+    // dropped, the notify watcher (and with it the event sender) goes away and
+    // the loop below ends.
+    let weak = Arc::downgrade(&shared);
+
+    let window = Duration::from_millis(crate::settings::read_settings().debounce_ms);
 
     std::thread::spawn(move || {
-        let mut last_rebuild = Instant::now();
+        let mut debouncer = Debouncer::new(window);
+        // Who the entry is, to recognise it if it is moved; refreshed with
+        // every rebuild.
+        let mut identity = ArtifactIdentity::of(&path);
 
-        for event in rx {
-            if !event.paths.iter().any(|p| p == &watched_path) {
-                continue;
+        loop {
+            // Sleep until the next event, or until a pending rebuild is due.
+            let event = match debouncer.time_left(Instant::now()) {
+                Some(wait) => match rx.recv_timeout(wait) {
+                    Ok(event) => Some(event),
+                    Err(mpsc::RecvTimeoutError::Timeout) => None,
+                    Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                },
+                None => match rx.recv() {
+                    Ok(event) => Some(event),
+                    Err(_) => break,
+                },
+            };
+
+            if let Some(event) = event {
+                // The window follows its file; the watcher for the new path
+                // replaces this one.
+                if let Some(to) = moved_to(&event, &path, &identity) {
+                    crate::follow_rename(&app_handle, &window_label, &path, &to);
+                    break;
+                }
+                if !matches!(event.kind, EventKind::Modify(_) | EventKind::Create(_)) {
+                    continue;
+                }
+                match is_watched(&weak, &event.paths) {
+                    Some(true) => debouncer.event(Instant::now()),
+                    Some(false) => {}
+                    None => break,
+                }
             }
-            if !matches!(event.kind, EventKind::Modify(_) | EventKind::Create(_)) {
+            if !debouncer.fire(Instant::now()) {
                 continue;
             }
-            if !should_rebuild(last_rebuild, 300) {
-                continue;
+            if path.exists() {
+                identity = ArtifactIdentity::of(&path);
             }
-            last_rebuild = Instant::now();
 
             let app = app_handle.clone();
-            let path = watched_path.clone();
+            let path = path.clone();
             let label = window_label.clone();
+            let weak = weak.clone();
             tauri::async_runtime::spawn(async move {
-                let (event_name, payload) = match crate::bundler::bundle_tsx(&app, &path).await {
-                    Ok(bundle) => ("bundle-ready", bundle),
-                    Err(err) => ("bundle-error", err),
+                let _ = match crate::bundler::bundle_for_window(&app, &path, &label).await {
+                    Ok(bundle) => {
+                        if let Some(shared) = weak.upgrade() {
+                            if let Ok(mut watched) = shared.lock() {
+                                watched.set_inputs(&bundle.inputs);
+                            }
+                        }
+                        app.emit_to(&label, "bundle-ready", bundle.code)
+                    }
+                    Err(err) if err.is_cancelled() => Ok(()),
+                    Err(err) => app.emit_to(&label, "bundle-error", err),
                 };
-                let _ = app.emit_to(&label, event_name, payload);
             });
         }
     });
 
-    Ok(watcher)
+    Ok(FileWatcher { shared })
 }
 
-/// Debounce helper — returns true if enough time has passed since the last event.
-pub fn should_rebuild(last_rebuild: Instant, debounce_ms: u64) -> bool {
-    last_rebuild.elapsed() >= Duration::from_millis(debounce_ms)
+/// Trailing-edge debouncer: every event pushes the deadline out by `window`,
+/// and [`Debouncer::fire`] reports true once, after the last event in a
+/// burst has been quiet for the whole window. The final save of a burst
+/// always triggers a rebuild.
+#[derive(Debug)]
+pub struct Debouncer {
+    window: Duration,
+    deadline: Option<Instant>,
+}
+
+impl Debouncer {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            deadline: None,
+        }
+    }
+
+    /// Records an event at `now`, (re)starting the quiet window.
+    pub fn event(&mut self, now: Instant) {
+        self.deadline = Some(now + self.window);
+    }
+
+    /// Time until the pending rebuild is due, or `None` if nothing is pending.
+    pub fn time_left(&self, now: Instant) -> Option<Duration> {
+        self.deadline
+            .map(|deadline| deadline.saturating_duration_since(now))
+    }
+
+    /// Returns true, and clears the pending rebuild, if it is due at `now`.
+    pub fn fire(&mut self, now: Instant) -> bool {
+        match self.deadline {
+            Some(deadline) if now >= deadline => {
+                self.deadline = None;
+                true
+            }
+            _ => false,
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn debounce_idle_never_fires() {
+        let mut d = Debouncer::new(ms(300));
+        let t0 = Instant::now();
+        assert_eq!(d.time_left(t0), None);
+        assert!(!d.fire(t0 + ms(1000)));
+    }
+
+    #[test]
+    fn debounce_fires_after_quiet_window() {
+        let mut d = Debouncer::new(ms(300));
+        let t0 = Instant::now();
+        d.event(t0);
+        assert!(!d.fire(t0 + ms(299)));
+        assert!(d.fire(t0 + ms(300)));
+        // Fires once per burst.
+        assert!(!d.fire(t0 + ms(400)));
+    }
+
+    #[test]
+    fn debounce_burst_fires_once_after_last_event() {
+        let mut d = Debouncer::new(ms(300));
+        let t0 = Instant::now();
+        // Editor writes, then a formatter rewrites 50ms later, then again.
+        d.event(t0);
+        d.event(t0 + ms(50));
+        d.event(t0 + ms(250));
+        // Still inside the window of the last event.
+        assert!(!d.fire(t0 + ms(500)));
+        assert_eq!(d.time_left(t0 + ms(500)), Some(ms(50)));
+        assert!(d.fire(t0 + ms(550)));
+    }
+
+    #[test]
+    fn debounce_event_after_fire_starts_a_new_burst() {
+        let mut d = Debouncer::new(ms(300));
+        let t0 = Instant::now();
+        d.event(t0);
+        assert!(d.fire(t0 + ms(300)));
+        // A save right after the rebuild is not dropped.
+        d.event(t0 + ms(310));
+        assert!(!d.fire(t0 + ms(500)));
+        assert!(d.fire(t0 + ms(610)));
+    }
+
+    #[test]
+    fn debounce_zero_window_fires_immediately() {
+        let mut d = Debouncer::new(Duration::ZERO);
+        let t0 = Instant::now();
+        d.event(t0);
+        assert_eq!(d.time_left(t0), Some(Duration::ZERO));
+        assert!(d.fire(t0));
+    }
+
+    #[test]
+    fn watch_dirs_are_the_parents_of_each_file() {
+        let files = HashSet::from([
+            PathBuf::from("/work/App.tsx"),
+            PathBuf::from("/work/data.ts"),
+            PathBuf::from("/work/styles/theme.css"),
+        ]);
+        assert_eq!(
+            watch_dirs(&files),
+            HashSet::from([PathBuf::from("/work"), PathBuf::from("/work/styles")])
+        );
+    }
+
+    #[test]
+    fn set_inputs_replaces_the_watched_set_but_keeps_the_entry() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let nested = dir.path().join("styles");
+        std::fs::create_dir(&nested).unwrap();
+        let entry = dir.path().join("App.tsx");
+        let data = dir.path().join("data.ts");
+        let theme = nested.join("theme.css");
+
+        let mut watched = Watched {
+            watcher: RecommendedWatcher::new(
+                |_: Result<Event, notify::Error>| {},
+                Config::default(),
+            )
+            .unwrap(),
+            entry: entry.clone(),
+            files: HashSet::from([entry.clone()]),
+            dirs: HashSet::new(),
+        };
+
+        watched.set_inputs(&[entry.clone(), data.clone(), theme.clone()]);
+        assert_eq!(
+            watched.files,
+            HashSet::from([entry.clone(), data.clone(), theme])
+        );
+        assert_eq!(
+            watched.dirs,
+            HashSet::from([dir.path().to_path_buf(), nested])
+        );
+
+        // The entry stays watched even if a bundle didn't report it.
+        watched.set_inputs(std::slice::from_ref(&data));
+        assert_eq!(watched.files, HashSet::from([entry, data]));
+        assert_eq!(watched.dirs, HashSet::from([dir.path().to_path_buf()]));
+    }
+
+    fn rename(from: &str, to: &str) -> Event {
+        Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::Both)))
+            .add_path(PathBuf::from(from))
+            .add_path(PathBuf::from(to))
+    }
+
     #[test]
-    fn debounce_allows_after_delay() {
-        let last = Instant::now() - Duration::from_millis(500);
-        assert!(should_rebuild(last, 300));
+    fn renamed_to_follows_the_entry() {
+        let entry = Path::new("/work/App.tsx");
+        assert_eq!(
+            renamed_to(&rename("/work/App.tsx", "/work/Dashboard.jsx"), entry),
+            Some(PathBuf::from("/work/Dashboard.jsx"))
+        );
     }
 
     #[test]
-    fn debounce_blocks_within_window() {
-        let last = Instant::now();
-        assert!(!should_rebuild(last, 300));
+    fn renamed_to_ignores_saves_backups_and_other_files() {
+        let entry = Path::new("/work/App.tsx");
+        // Vim-style save: a temp file renamed over the entry.
+        assert_eq!(
+            renamed_to(&rename("/work/.App.tsx.swp", "/work/App.tsx"), entry),
+            None
+        );
+        assert_eq!(
+            renamed_to(&rename("/work/App.tsx", "/work/App.tsx~"), entry),
+            None
+        );
+        assert_eq!(
+            renamed_to(&rename("/work/data.ts", "/work/info.ts"), entry),
+            None
+        );
+        let write =
+            Event::new(EventKind::Modify(ModifyKind::Any)).add_path(PathBuf::from("/work/App.tsx"));
+        assert_eq!(renamed_to(&write, entry), None);
     }
 
     #[test]
-    fn debounce_exact_boundary() {
-        let last = Instant::now() - Duration::from_millis(300);
-        assert!(should_rebuild(last, 300));
+    fn moved_to_finds_an_entry_moved_out_of_its_directory() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let entry = dir.path().join("App.tsx");
+        std::fs::write(&entry, "export default () => <p>hi</p>").unwrap();
+        std::fs::write(dir.path().join("Other.tsx"), "export default 1").unwrap();
+        let identity = ArtifactIdentity::of(&entry);
+        let archive = dir.path().join("archive");
+        std::fs::create_dir(&archive).unwrap();
+        let moved = archive.join("Dashboard.tsx");
+        std::fs::rename(&entry, &moved).unwrap();
+
+        // What inotify reports for a move out of the watched directory, and
+        // FSEvents for any rename.
+        for mode in [RenameMode::From, RenameMode::Any] {
+            let event =
+                Event::new(EventKind::Modify(ModifyKind::Name(mode))).add_path(entry.clone());
+            assert_eq!(moved_to(&event, &entry, &identity), Some(moved.clone()));
+        }
+    }
+
+    #[test]
+    fn moved_to_ignores_an_entry_still_on_disk_or_not_found() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let entry = dir.path().join("App.tsx");
+        std::fs::write(&entry, "export default () => <p>hi</p>").unwrap();
+        let identity = ArtifactIdentity::of(&entry);
+        let event = Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::Any)))
+            .add_path(entry.clone());
+        // Renamed over by an editor's save: still there.
+        assert_eq!(moved_to(&event, &entry, &identity), None);
+
+        // Moved somewhere the search doesn't reach.
+        let elsewhere = tempfile::TempDir::new().unwrap();
+        std::fs::rename(&entry, elsewhere.path().join("App.tsx")).unwrap();
+        std::fs::write(dir.path().join("New.tsx"), "export default 2").unwrap();
+        assert_eq!(moved_to(&event, &entry, &identity), None);
     }
 
     #[test]
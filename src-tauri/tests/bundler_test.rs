@@ -1,3 +1,5 @@
+use terrarium_lib::bundler::BundleError;
+
 #[test]
 fn cache_dir_returns_home_terrarium() {
     let dir = terrarium_lib::bundler::cache_dir();
@@ -6,13 +8,17 @@ fn cache_dir_returns_home_terrarium() {
 }
 
 #[test]
-fn parse_bundler_output_success() {
-    let output = "console.log('hello');";
-    assert!(!output.starts_with("{\"error\":true"));
+fn parse_bundler_output_error() {
+    let output = r#"{"error":true,"type":"resolve","message":"Failed to resolve","errors":[]}"#;
+    let err = BundleError::from_payload(serde_json::from_str(output).unwrap());
+    assert!(matches!(err, BundleError::Resolve(_)));
+    assert_eq!(err.message(), "Failed to resolve");
 }
 
 #[test]
-fn parse_bundler_output_error() {
+fn parse_bundler_output_error_without_type() {
     let output = r#"{"error":true,"message":"Failed to resolve"}"#;
-    assert!(output.starts_with("{\"error\":true"));
+    let err = BundleError::from_payload(serde_json::from_str(output).unwrap());
+    assert!(matches!(err, BundleError::Unknown(_)));
+    assert_eq!(err.message(), "Failed to resolve");
 }
@@ -187,3 +187,64 @@ async fn watcher_emits_error_on_invalid_tsx() {
         ready_events
     );
 }
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn watcher_triggers_rebundle_when_an_imported_file_changes() {
+    set_bundler_path();
+
+    let app = mock_app();
+    let handle = app.handle().clone();
+
+    let dir = tempfile::TempDir::new().unwrap();
+    // Canonicalize to resolve macOS /var -> /private/var symlink
+    let canon_dir = dir.path().canonicalize().unwrap();
+    let file = canon_dir.join("graph_test.tsx");
+    let data = canon_dir.join("data.ts");
+    std::fs::write(&data, r#"export const label = "first-value";"#).unwrap();
+    std::fs::write(
+        &file,
+        r#"import { label } from './data';
+export default function App() { return <div>{label}</div>; }"#,
+    )
+    .unwrap();
+
+    let received = Arc::new(Mutex::new(Vec::<String>::new()));
+    let received_clone = received.clone();
+    handle.listen_any("bundle-ready", move |event| {
+        let payload = event.payload().to_string();
+        received_clone.lock().unwrap().push(payload);
+    });
+
+    let bundle = terrarium_lib::bundler::bundle_for_window(&handle, &file, "main")
+        .await
+        .expect("initial bundle failed");
+    let watcher =
+        terrarium_lib::watcher::watch_file(handle.clone(), file.clone(), "main".to_string())
+            .expect("watch_file failed");
+    watcher.set_inputs(&bundle.inputs);
+    assert!(
+        watcher.files().contains(&data),
+        "imported file should be watched: {:?}",
+        watcher.files()
+    );
+
+    // Heuristic: give the OS file watcher time to register.
+    tokio::time::sleep(Duration::from_millis(2000)).await;
+    std::fs::write(&data, r#"export const label = "second-value";"#).unwrap();
+
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(30);
+    loop {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        if received
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|p| p.contains("second-value"))
+        {
+            break;
+        }
+        if tokio::time::Instant::now() > deadline {
+            panic!("Timed out waiting for a rebuild after editing an imported file");
+        }
+    }
+}
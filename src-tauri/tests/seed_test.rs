@@ -0,0 +1,62 @@
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn fixture() -> std::path::PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../tests/fixtures/simple-counter.tsx")
+}
+
+fn run_bundler(cache: &Path, offline: bool) -> Output {
+    let mut command = Command::new("node");
+    command
+        .arg(Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/bundler.mjs"))
+        .arg(fixture())
+        .env("TERRARIUM_CACHE_DIR", cache);
+    if offline {
+        command
+            .env("npm_config_registry", "http://localhost:1")
+            .env("npm_config_fetch_retries", "0")
+            .env("npm_config_fetch_timeout", "1000");
+    }
+    command.output().expect("failed to run node")
+}
+
+fn node_major() -> u32 {
+    let output = Command::new("node").arg("--version").output().unwrap();
+    let version = String::from_utf8(output.stdout).unwrap();
+    version
+        .trim()
+        .trim_start_matches('v')
+        .split('.')
+        .next()
+        .and_then(|major| major.parse().ok())
+        .expect("unexpected node --version output")
+}
+
+/// The point of a seed pack: a machine that can't reach the registry bundles
+/// from a cache it imported.
+#[test]
+fn imported_seed_pack_bundles_without_the_registry() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let source = dir.path().join("source");
+    let output = run_bundler(&source, false);
+    assert!(
+        output.status.success(),
+        "filling the source cache failed: {}",
+        String::from_utf8_lossy(&output.stdout)
+    );
+
+    let pack = dir.path().join("seed.tgz");
+    let exported = terrarium_lib::seed::export(&source, &pack, node_major()).unwrap();
+
+    let target = dir.path().join("target");
+    let imported = terrarium_lib::seed::import(&target, &pack, node_major(), "npm").unwrap();
+    assert_eq!(imported, exported);
+
+    let output = run_bundler(&target, true);
+    assert!(
+        output.status.success(),
+        "bundling from the imported cache failed: {}",
+        String::from_utf8_lossy(&output.stdout)
+    );
+    assert!(String::from_utf8_lossy(&output.stdout).contains("createRoot"));
+}
@@ -12,6 +12,7 @@ const nodeBanner = document.getElementById('node-banner');
 const nodeBannerText = document.getElementById('node-banner-text');
 const nodeBannerLink = document.getElementById('node-banner-link');
 const nodeBannerClose = document.getElementById('node-banner-close');
+const nodeBannerRetry = document.getElementById('node-banner-retry');
 const firstRunHint = document.getElementById('first-run-hint');
 const firstRunDismiss = document.getElementById('first-run-dismiss');
 const NODEJS_URL = 'https://nodejs.org';
@@ -27,15 +28,25 @@ function showNodeBanner(message) {
   nodeBanner.classList.add('visible');
 }
 
-invoke('check_node')
-  .then(info => {
-    if (!info.supported) {
-      showNodeBanner(`Node.js ${info.version} found, but Terrarium needs v18+.`);
-    }
-  })
-  .catch(() => {
-    showNodeBanner('Node.js not found. Terrarium needs Node.js 18+ to run.');
-  });
+function checkNode(command) {
+  return invoke(command)
+    .then(info => {
+      if (info.supported) {
+        nodeBanner?.classList.remove('visible');
+      } else {
+        showNodeBanner(`Node.js ${info.version} found, but Terrarium needs v18+.`);
+      }
+    })
+    .catch(() => {
+      showNodeBanner('Node.js not found. Terrarium needs Node.js 18+ to run.');
+    });
+}
+
+checkNode('check_node');
+
+if (nodeBannerRetry) {
+  nodeBannerRetry.addEventListener('click', () => checkNode('redetect_node'));
+}
 
 if (nodeBannerClose && nodeBanner) {
   nodeBannerClose.addEventListener('click', () => {
@@ -64,32 +75,56 @@ if (firstRunDismiss && firstRunHint) {
 
 const errorTitle = document.getElementById('error-title');
 
-function showError(message) {
-  let title = 'Build Error';
-  let detail = message;
-
+// Bundle errors arrive as `{ type, message, errors }` objects from the Rust
+// side. Plain strings (render errors, older payloads) are still accepted.
+function parseBundleError(error) {
+  if (error && typeof error === 'object') return error;
   try {
-    const parsed = JSON.parse(message);
-    if (parsed.error) {
-      detail = parsed.message || message;
-      if (parsed.type === 'syntax') {
-        title = 'Syntax Error';
-        const loc = parsed.errors?.[0]?.location;
-        if (loc) {
-          detail = `${loc.file || 'file'}:${loc.line}:${loc.column}\n\n${parsed.errors[0].text}`;
-        }
-      } else if (parsed.type === 'resolve') {
-        title = 'Missing Package';
-        const pkg = parsed.errors?.[0]?.text?.match(/Could not resolve "([^"]+)"/)?.[1] || '';
-        detail = pkg
-          ? `Can't find package '${pkg}'. Check the package name or your network connection.`
-          : detail;
-      } else if (parsed.type === 'network') {
-        title = 'Network Error';
-        detail = 'Failed to install dependencies. Check your internet connection and try again.';
+    const parsed = JSON.parse(error);
+    return parsed && typeof parsed === 'object' ? parsed : null;
+  } catch {
+    return null;
+  }
+}
+
+function errorMessage(error) {
+  return typeof error === 'string' ? error : error?.message;
+}
+
+function showError(error) {
+  let title = 'Build Error';
+  let detail = typeof error === 'string' ? error : (error?.message || String(error));
+
+  const parsed = parseBundleError(error);
+  if (parsed?.type) {
+    detail = parsed.message || detail;
+    if (parsed.type === 'syntax') {
+      title = 'Syntax Error';
+      const loc = parsed.errors?.[0]?.location;
+      if (loc) {
+        detail = `${loc.file || 'file'}:${loc.line}:${loc.column}\n\n${parsed.errors[0].text}`;
       }
+    } else if (parsed.type === 'resolve') {
+      title = 'Missing Package';
+      const pkg = parsed.errors?.[0]?.text?.match(/Could not resolve "([^"]+)"/)?.[1] || '';
+      detail = pkg
+        ? `Can't find package '${pkg}'. Check the package name or your network connection.`
+        : detail;
+    } else if (parsed.type === 'network') {
+      title = 'Network Error';
+      detail = 'Failed to install dependencies. Check your internet connection and try again.';
+    } else if (parsed.type === 'registry') {
+      title = 'Registry Error';
+    } else if (parsed.type === 'not-approved') {
+      title = 'Install Not Approved';
+    } else if (parsed.type === 'timeout') {
+      title = 'Build Timed Out';
+    } else if (parsed.type === 'node-missing') {
+      title = 'Node.js Not Found';
+    } else if (parsed.type === 'spawn-failed') {
+      title = 'Bundler Failed to Start';
     }
-  } catch {}
+  }
 
   errorTitle.textContent = title;
   errorDetail.textContent = detail;
@@ -129,6 +164,16 @@ async function renderBundle(bundledCode) {
   }
 }
 
+function showLoadError(err) {
+  // A newer bundle for this window replaced this one; its result is coming.
+  if (err?.type === 'cancelled') return;
+  if (err && typeof err === 'object' && err.type && err.type !== 'unknown') {
+    showError(err);
+  } else {
+    showError(`Failed to load:\n${errorMessage(err) ?? err}`);
+  }
+}
+
 function openFilePicker() {
   invoke('pick_and_open_files')
     .then(bundledCode => {
@@ -136,8 +181,8 @@ function openFilePicker() {
       renderBundle(bundledCode);
     })
     .catch(err => {
-      if (err !== 'No file selected') {
-        showError(`Failed to load:\n${err}`);
+      if (errorMessage(err) !== 'No file selected') {
+        showLoadError(err);
       }
     });
 }
@@ -148,7 +193,7 @@ function openFileByPath(filePath) {
       fileLoaded = true;
       renderBundle(bundledCode);
     })
-    .catch(err => showError(`Failed to load:\n${err}`));
+    .catch(err => showLoadError(err));
 }
 
 const openBtn = document.getElementById('open-btn');
@@ -221,8 +266,8 @@ invoke('request_bundle')
     renderBundle(bundledCode);
   })
   .catch(err => {
-    if (err !== 'No file loaded') {
-      showError(`Failed to load:\n${err}`);
+    if (errorMessage(err) !== 'No file loaded') {
+      showLoadError(err);
     }
   });
 
@@ -370,6 +415,337 @@ listen('update-error', (event) => {
   showUpdateError(event.payload);
 });
 
+// --- Install approval ---
+
+const approvalPrompt = document.getElementById('approval-prompt');
+const approvalPackages = document.getElementById('approval-packages');
+const approvalAllow = document.getElementById('approval-allow');
+const approvalDeny = document.getElementById('approval-deny');
+// Questions waiting on an answer, oldest first; the first one is shown.
+const approvalQueue = [];
+
+function showApprovalPrompt() {
+  if (!approvalPrompt || !approvalPackages) return;
+  const next = approvalQueue[0];
+  if (!next) {
+    approvalPrompt.classList.remove('visible');
+    return;
+  }
+  clearChildren(approvalPackages);
+  for (const name of next.packages) {
+    const item = document.createElement('li');
+    item.textContent = name;
+    approvalPackages.appendChild(item);
+  }
+  approvalPrompt.classList.add('visible');
+}
+
+function answerApproval(approved) {
+  const current = approvalQueue.shift();
+  if (!current) return;
+  invoke('answer_install_approval', { request: current.request, approved }).catch(() => {});
+  showApprovalPrompt();
+}
+
+if (approvalAllow && approvalDeny) {
+  approvalAllow.addEventListener('click', () => answerApproval(true));
+  approvalDeny.addEventListener('click', () => answerApproval(false));
+}
+
+listen('install-approval', (event) => {
+  approvalQueue.push(event.payload);
+  showApprovalPrompt();
+});
+
+// The bundle that asked ended before anyone answered.
+listen('install-approval-closed', (event) => {
+  const index = approvalQueue.findIndex(q => q.request === event.payload);
+  if (index === -1) return;
+  approvalQueue.splice(index, 1);
+  showApprovalPrompt();
+});
+
+// --- Pinned dependency mismatches ---
+
+const pinWarning = document.getElementById('pin-warning');
+const pinWarningList = document.getElementById('pin-warning-list');
+const pinWarningDismiss = document.getElementById('pin-warning-dismiss');
+
+function showPinMismatches(mismatches) {
+  if (!pinWarning || !pinWarningList) return;
+  clearChildren(pinWarningList);
+  for (const { name, wanted, found } of mismatches) {
+    const item = document.createElement('li');
+    item.textContent = `${name}: pinned ${wanted}, got ${found}`;
+    pinWarningList.appendChild(item);
+  }
+  pinWarning.classList.toggle('visible', mismatches.length > 0);
+}
+
+if (pinWarning && pinWarningDismiss) {
+  pinWarningDismiss.addEventListener('click', () => {
+    pinWarning.classList.remove('visible');
+  });
+}
+
+listen('dependency-mismatches', (event) => {
+  showPinMismatches(event.payload || []);
+});
+
+// --- Project ---
+
+const projectBadge = document.getElementById('project-badge');
+
+// Files inside a project bundle against its node_modules; says which one.
+function showProject(project) {
+  if (!projectBadge) return;
+  const name = project ? project.split(/[\\/]/).filter(Boolean).pop() : '';
+  projectBadge.textContent = project ? `Packages from ${name}` : '';
+  projectBadge.title = project || '';
+  projectBadge.classList.toggle('visible', Boolean(project));
+}
+
+listen('bundle-project', (event) => {
+  showProject(event.payload);
+});
+
+// --- Installed packages ---
+
+const depsPanel = document.getElementById('deps-panel');
+const depsList = document.getElementById('deps-list');
+const depsStatus = document.getElementById('deps-status');
+const depsPrune = document.getElementById('deps-prune');
+const depsReset = document.getElementById('deps-reset');
+const depsClose = document.getElementById('deps-close');
+const depsUpgrades = document.getElementById('deps-upgrades');
+const depsCheck = document.getElementById('deps-check');
+const depsApply = document.getElementById('deps-apply');
+let depsBusy = false;
+let resetArmed = false;
+let pendingUpgrades = [];
+
+function formatSize(bytes) {
+  if (bytes < 1024) return `${bytes} B`;
+  if (bytes < 1024 * 1024) return `${(bytes / 1024).toFixed(1)} KB`;
+  return `${(bytes / (1024 * 1024)).toFixed(1)} MB`;
+}
+
+function renderDeps(packages) {
+  clearChildren(depsList);
+  for (const pkg of packages) {
+    const item = document.createElement('li');
+    const name = document.createElement('span');
+    name.className = 'deps-name';
+    name.textContent = pkg.name;
+    const meta = document.createElement('span');
+    meta.className = 'deps-meta';
+    meta.textContent = `${pkg.version || 'missing'} \u00b7 ${formatSize(pkg.size)}`;
+    item.appendChild(name);
+    item.appendChild(meta);
+    item.appendChild(createBtn('Remove', 'update-dismiss-btn', () => {
+      runDepsCommand('deps_remove', { packages: [pkg.name] }, `Removed ${pkg.name}.`);
+    }));
+    depsList.appendChild(item);
+  }
+}
+
+// Shows what an upgrade would change before anything is installed.
+function renderUpgrades(changes) {
+  pendingUpgrades = changes;
+  clearChildren(depsUpgrades);
+  for (const change of changes) {
+    const item = document.createElement('li');
+    const name = document.createElement('span');
+    name.className = 'deps-name';
+    name.textContent = `${change.name} ${change.from} \u2192 ${change.to}`;
+    const meta = document.createElement('span');
+    meta.className = 'deps-meta';
+    meta.textContent = `used by ${change.used_by} ${change.used_by === 1 ? 'file' : 'files'}`;
+    item.appendChild(name);
+    item.appendChild(meta);
+    depsUpgrades.appendChild(item);
+  }
+  depsUpgrades.hidden = changes.length === 0;
+  depsApply.hidden = changes.length === 0;
+}
+
+function refreshDeps() {
+  if (!depsPanel) return Promise.resolve();
+  return invoke('deps_list').then(renderDeps).catch(() => {});
+}
+
+function setDepsBusy(busy) {
+  depsBusy = busy;
+  for (const btn of depsPanel.querySelectorAll('button:not(#deps-close)')) {
+    btn.disabled = busy;
+  }
+}
+
+function runDepsCommand(command, args, done) {
+  if (depsBusy) return Promise.resolve();
+  setDepsBusy(true);
+  return invoke(command, args)
+    .then(result => {
+      depsStatus.textContent = typeof done === 'function' ? done(result) : done;
+    })
+    .catch(err => {
+      depsStatus.textContent = String(err);
+    })
+    .finally(() => {
+      setDepsBusy(false);
+      refreshDeps();
+    });
+}
+
+function checkUpgrades() {
+  renderUpgrades([]);
+  return runDepsCommand('deps_outdated', undefined, changes => {
+    renderUpgrades(changes);
+    return changes.length > 0
+      ? `${changes.length} ${changes.length === 1 ? 'upgrade' : 'upgrades'} available.`
+      : 'Every package is up to date.';
+  });
+}
+
+function disarmReset() {
+  resetArmed = false;
+  depsReset.textContent = 'Reset Cache';
+}
+
+if (depsPanel && depsList) {
+  depsClose.addEventListener('click', () => {
+    depsPanel.classList.remove('visible');
+    disarmReset();
+    renderUpgrades([]);
+  });
+  depsCheck.addEventListener('click', checkUpgrades);
+  depsApply.addEventListener('click', () => {
+    const changes = pendingUpgrades;
+    renderUpgrades([]);
+    runDepsCommand('deps_upgrade', { changes }, upgraded => `Upgraded ${upgraded.join(', ')}.`);
+  });
+  depsPrune.addEventListener('click', () => {
+    runDepsCommand('deps_prune', undefined, removed => removed.length > 0
+      ? `Removed ${removed.join(', ')}.`
+      : 'Every installed package is in use.');
+  });
+  // Resetting reinstalls everything, so it takes a second click.
+  depsReset.addEventListener('click', () => {
+    if (!resetArmed) {
+      resetArmed = true;
+      depsReset.textContent = 'Click again to reset';
+      return;
+    }
+    disarmReset();
+    runDepsCommand('deps_reseed', undefined, 'Package cache reset.');
+  });
+}
+
+listen('menu-show-deps', () => {
+  if (!depsPanel) return;
+  depsStatus.textContent = '';
+  depsPanel.classList.add('visible');
+  refreshDeps();
+});
+
+listen('menu-upgrade-deps', () => {
+  if (!depsPanel) return;
+  depsStatus.textContent = '';
+  depsPanel.classList.add('visible');
+  refreshDeps();
+  checkUpgrades();
+});
+
+listen('deps-progress', (event) => {
+  if (depsStatus) depsStatus.textContent = event.payload;
+});
+
+listen('deps-changed', () => {
+  if (depsPanel && depsPanel.classList.contains('visible')) refreshDeps();
+});
+
+// --- Storage export and import ---
+
+const storagePanel = document.getElementById('storage-panel');
+const storageStatus = document.getElementById('storage-status');
+const storageConflicts = document.getElementById('storage-conflicts');
+const storageShared = document.getElementById('storage-shared-toggle');
+let storageBusy = false;
+
+// Lists the keys an import found already stored with a different value.
+function renderConflicts(conflicts, mode) {
+  clearChildren(storageConflicts);
+  for (const conflict of conflicts) {
+    const item = document.createElement('li');
+    const name = document.createElement('span');
+    name.className = 'deps-name';
+    name.textContent = conflict.key;
+    const meta = document.createElement('span');
+    meta.className = 'deps-meta';
+    meta.textContent = mode === 'replace' ? 'replaced' : 'kept stored value';
+    item.appendChild(name);
+    item.appendChild(meta);
+    storageConflicts.appendChild(item);
+  }
+  storageConflicts.hidden = conflicts.length === 0;
+}
+
+// The export and import commands ask for the file themselves; a null
+// result means the user cancelled the dialog.
+function runStorageCommand(command, args, done) {
+  if (storageBusy) return Promise.resolve();
+  storageBusy = true;
+  for (const btn of storagePanel.querySelectorAll('#storage-actions button')) {
+    btn.disabled = true;
+  }
+  renderConflicts([]);
+  return invoke(command, { shared: storageShared.checked, ...args })
+    .then(result => {
+      storageStatus.textContent = result === null ? '' : done(result);
+    })
+    .catch(err => {
+      storageStatus.textContent = err && err.message ? err.message : String(err);
+    })
+    .finally(() => {
+      storageBusy = false;
+      for (const btn of storagePanel.querySelectorAll('#storage-actions button')) {
+        btn.disabled = false;
+      }
+    });
+}
+
+function importStorage(mode) {
+  return runStorageCommand('storage_import', { mode }, imported => {
+    renderConflicts(imported.conflicts, mode);
+    const count = imported.conflicts.length;
+    const conflicts = count === 0 ? ''
+      : mode === 'replace'
+        ? ` ${count} stored ${count === 1 ? 'value was' : 'values were'} replaced.`
+        : ` ${count} ${count === 1 ? 'key' : 'keys'} kept the stored value.`;
+    return `Imported ${imported.imported} ${imported.imported === 1 ? 'value' : 'values'}.${conflicts}`;
+  });
+}
+
+if (storagePanel && storageStatus) {
+  document.getElementById('storage-close').addEventListener('click', () => {
+    storagePanel.classList.remove('visible');
+    renderConflicts([]);
+  });
+  document.getElementById('storage-export').addEventListener('click', () => {
+    runStorageCommand('storage_export', {}, count =>
+      `Exported ${count} ${count === 1 ? 'value' : 'values'}.`);
+  });
+  document.getElementById('storage-merge').addEventListener('click', () => importStorage('merge'));
+  document.getElementById('storage-replace').addEventListener('click', () => importStorage('replace'));
+}
+
+listen('menu-show-storage', () => {
+  if (!storagePanel) return;
+  storageStatus.textContent = '';
+  renderConflicts([]);
+  storagePanel.classList.add('visible');
+});
+
 // --- Ambient particles ---
 
 (function initParticles() {
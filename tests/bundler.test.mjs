@@ -1,5 +1,8 @@
 import { describe, it, expect, beforeAll, afterAll } from 'vitest';
-import { execFileSync } from 'child_process';
+import { execFile, execFileSync, spawn } from 'child_process';
+import { createHash } from 'crypto';
+import { createServer } from 'http';
+import { gzipSync } from 'zlib';
 import * as fs from 'fs';
 import * as path from 'path';
 import * as os from 'os';
@@ -33,6 +36,156 @@ function runBundlerRaw(fixtureName, env = {}) {
   }
 }
 
+// Runs the daemon on `requests`. Approval requests are answered with
+// `approve(packages)`; stdin closes once every request has a result.
+function runDaemon(requests, env = {}, approve = () => true) {
+  return new Promise((resolve, reject) => {
+    const child = spawn('node', [BUNDLER, '--daemon'], {
+      env: { ...process.env, TERRARIUM_CACHE_DIR: TEST_CACHE, ...env },
+    });
+    const lines = [];
+    const waiting = new Set(requests.map(request => request.id));
+    let buffered = '';
+    child.stdout.on('data', chunk => {
+      buffered += chunk;
+      const complete = buffered.split('\n');
+      buffered = complete.pop();
+      for (const line of complete.filter(Boolean).map(text => JSON.parse(text))) {
+        lines.push(line);
+        if (line.approve) {
+          child.stdin.write(JSON.stringify({ id: line.id, approved: approve(line.approve) }) + '\n');
+        } else if (!line.progress) {
+          waiting.delete(line.id);
+          if (waiting.size === 0) child.stdin.end();
+        }
+      }
+    });
+    child.on('error', reject);
+    child.on('exit', code => resolve({ lines, exitCode: code }));
+    for (const request of requests) {
+      child.stdin.write(JSON.stringify(request) + '\n');
+    }
+    if (waiting.size === 0) child.stdin.end();
+  });
+}
+
+// A daemon driven step by step: `waitFor` resolves with the first output
+// line matching `predicate`, `close` ends stdin and resolves with the exit
+// code. Approval requests are approved.
+function startDaemon(env = {}) {
+  const child = spawn('node', [BUNDLER, '--daemon'], {
+    env: { ...process.env, TERRARIUM_CACHE_DIR: TEST_CACHE, ...env },
+  });
+  const lines = [];
+  const waiters = [];
+  let stderr = '';
+  let buffered = '';
+  child.stderr.on('data', chunk => { stderr += chunk; });
+  child.stdout.on('data', chunk => {
+    buffered += chunk;
+    const complete = buffered.split('\n');
+    buffered = complete.pop();
+    for (const line of complete.filter(Boolean).map(text => JSON.parse(text))) {
+      lines.push(line);
+      if (line.approve) child.stdin.write(JSON.stringify({ id: line.id, approved: true }) + '\n');
+      for (const waiter of waiters.filter(w => w.predicate(line))) {
+        waiters.splice(waiters.indexOf(waiter), 1);
+        waiter.resolve(line);
+      }
+    }
+  });
+  return {
+    lines,
+    stderr: () => stderr,
+    send: request => child.stdin.write(JSON.stringify(request) + '\n'),
+    waitFor: predicate => {
+      const seen = lines.find(predicate);
+      return seen ? Promise.resolve(seen) : new Promise(resolve => waiters.push({ predicate, resolve }));
+    },
+    close: () => {
+      child.stdin.end();
+      return new Promise(resolve => child.on('exit', resolve));
+    },
+  };
+}
+
+// A ustar archive of `files` (name -> contents), gzipped like an npm tarball.
+function tarball(files) {
+  const blocks = [];
+  for (const [name, contents] of Object.entries(files)) {
+    const body = Buffer.from(contents);
+    const header = Buffer.alloc(512);
+    header.write(name, 0);
+    header.write('0000644\0', 100);
+    header.write('0000000\0', 108);
+    header.write('0000000\0', 116);
+    header.write(body.length.toString(8).padStart(11, '0') + '\0', 124);
+    header.write('00000000000\0', 136);
+    header.write('        ', 148);
+    header.write('0', 156);
+    header.write('ustar\0' + '00', 257);
+    const checksum = header.reduce((sum, byte) => sum + byte, 0);
+    header.write(checksum.toString(8).padStart(6, '0') + '\0 ', 148);
+    blocks.push(header, body, Buffer.alloc((512 - (body.length % 512)) % 512));
+  }
+  blocks.push(Buffer.alloc(1024));
+  return gzipSync(Buffer.concat(blocks));
+}
+
+// A stand-in for a private registry (verdaccio and the like) serving a
+// single package. Records the Authorization header of every request.
+function startRegistry(name, version, source, manifest = {}) {
+  return startRegistryVersions(name, { [version]: source }, manifest);
+}
+
+// Like startRegistry, but serving several versions (`version -> source`);
+// the last one is `latest`.
+function startRegistryVersions(name, sources, manifest = {}) {
+  const authorizations = [];
+  const tarballs = {};
+  for (const [version, source] of Object.entries(sources)) {
+    tarballs[`/${name}/-/${name.split('/').pop()}-${version}.tgz`] = tarball({
+      'package/package.json': JSON.stringify({ name, version, main: 'index.js', ...manifest }),
+      'package/index.js': source,
+    });
+  }
+  const versions = Object.keys(sources);
+  const server = createServer((req, res) => {
+    authorizations.push(req.headers.authorization);
+    const { port } = server.address();
+    if (decodeURIComponent(req.url) === `/${name}`) {
+      res.setHeader('content-type', 'application/json');
+      res.end(JSON.stringify({
+        name,
+        'dist-tags': { latest: versions[versions.length - 1] },
+        versions: Object.fromEntries(versions.map(version => {
+          const tarballPath = `/${name}/-/${name.split('/').pop()}-${version}.tgz`;
+          return [version, {
+            name,
+            version,
+            main: 'index.js',
+            ...manifest,
+            dist: {
+              tarball: `http://127.0.0.1:${port}${tarballPath}`,
+              shasum: createHash('sha1').update(tarballs[tarballPath]).digest('hex'),
+            },
+          }];
+        })),
+      }));
+    } else if (tarballs[req.url]) {
+      res.end(tarballs[req.url]);
+    } else {
+      res.statusCode = 404;
+      res.end('{}');
+    }
+  });
+  return new Promise(resolve => {
+    server.listen(0, '127.0.0.1', () => {
+      resolve({ url: `http://127.0.0.1:${server.address().port}/`, authorizations, server });
+    });
+  });
+}
+
 describe('bundler.mjs', () => {
   beforeAll(() => {
     fs.mkdirSync(TEST_CACHE, { recursive: true });
@@ -197,6 +350,461 @@ describe('bundler.mjs', () => {
     });
   });
 
+  describe('daemon mode', () => {
+    it('answers each request by id and exits when stdin closes', async () => {
+      const { lines, exitCode } = await runDaemon([
+        { id: 1, file: path.join(FIXTURES, 'simple-counter.tsx') },
+        { id: 2, file: path.join(FIXTURES, 'syntax-error.tsx') },
+      ]);
+      expect(exitCode).toBe(0);
+
+      const results = lines.filter(line => !line.progress);
+      const ok = results.find(line => line.id === 1);
+      const failed = results.find(line => line.id === 2);
+      expect(ok.ok).toBe(true);
+      expect(ok.code).toContain('createRoot');
+      expect(ok.inputs).toContain(path.join(FIXTURES, 'simple-counter.tsx'));
+      expect(ok.inputs.some(file => file.includes('node_modules'))).toBe(false);
+      expect(ok.packages.react).toMatch(/^\d+\.\d+\.\d+/);
+      expect(failed.ok).toBe(false);
+      expect(failed.error.error).toBe(true);
+      expect(failed.error.type).toBe('syntax');
+    });
+
+    it('resolves packages from the project root before the shared cache', async () => {
+      const project = fs.mkdtempSync(path.join(os.tmpdir(), 'terrarium-project-'));
+      const pkgDir = path.join(project, 'node_modules', 'terrarium-local-widget');
+      fs.mkdirSync(pkgDir, { recursive: true });
+      fs.writeFileSync(path.join(project, 'package.json'), JSON.stringify({ name: 'app', private: true }));
+      fs.writeFileSync(path.join(pkgDir, 'package.json'), JSON.stringify({ name: 'terrarium-local-widget', version: '4.5.6', main: 'index.js' }));
+      fs.writeFileSync(path.join(pkgDir, 'index.js'), "export const marker = 'local-widget-marker';");
+      const file = path.join(project, 'App.tsx');
+      fs.writeFileSync(file, "import { marker } from 'terrarium-local-widget';\nexport default function App() { return <div>{marker}</div>; }");
+
+      try {
+        const { lines } = await runDaemon([{ id: 1, file, root: project }]);
+        const result = lines.find(line => line.id === 1 && !line.progress);
+        expect(result.ok).toBe(true);
+        expect(result.code).toContain('local-widget-marker');
+        expect(result.packages['terrarium-local-widget']).toBe('4.5.6');
+        expect(fs.existsSync(path.join(TEST_CACHE, 'node_modules', 'terrarium-local-widget'))).toBe(false);
+      } finally {
+        fs.rmSync(project, { recursive: true, force: true });
+      }
+    });
+
+    it('tags progress updates with the request id', async () => {
+      const { lines } = await runDaemon([{ id: 7, file: path.join(FIXTURES, 'simple-counter.tsx') }]);
+      const progress = lines.filter(line => line.progress);
+      expect(progress.length).toBeGreaterThan(0);
+      expect(progress.every(line => line.id === 7)).toBe(true);
+    });
+  });
+
+  describe('package managers', () => {
+    it('records which package manager built the cache', async () => {
+      await runDaemon([{ id: 1, file: path.join(FIXTURES, 'simple-counter.tsx'), packageManager: 'npm' }]);
+      expect(fs.readFileSync(path.join(TEST_CACHE, '.package-manager'), 'utf-8')).toBe('npm');
+    });
+
+    it('rejects an unsupported package manager', async () => {
+      const { lines } = await runDaemon([
+        { id: 1, file: path.join(FIXTURES, 'simple-counter.tsx'), packageManager: 'pip' },
+      ]);
+      const result = lines.find(line => line.id === 1 && !line.progress);
+      expect(result.ok).toBe(false);
+      expect(result.error.message).toContain('Unsupported package manager: pip');
+    });
+  });
+
+  describe('private registries', () => {
+    it('installs scoped packages from the registry in npmrc with its token', async () => {
+      const registry = await startRegistry('@acme/ui', '1.2.3', "export const Button = () => 'acme-button-marker';");
+      const host = registry.url.replace(/^http:/, '');
+      const npmrc = `# Managed by Terrarium\n@acme:registry=${registry.url}\n${host}:_authToken=fixture-token\n`;
+      const dir = fs.mkdtempSync(path.join(os.tmpdir(), 'terrarium-registry-'));
+      const file = path.join(dir, 'App.tsx');
+      fs.writeFileSync(file, "import { Button } from '@acme/ui';\nexport default function App() { return <div>{Button()}</div>; }");
+
+      try {
+        const { lines } = await runDaemon([{ id: 1, file, npmrc }]);
+        const result = lines.find(line => line.id === 1 && line.ok !== undefined);
+        expect(result.ok).toBe(true);
+        expect(result.code).toContain('acme-button-marker');
+        expect(result.packages['@acme/ui']).toBe('1.2.3');
+        expect(registry.authorizations).toContain('Bearer fixture-token');
+        expect(fs.readFileSync(path.join(TEST_CACHE, '.npmrc'), 'utf-8')).toBe(npmrc);
+
+        // Dropping the registries from settings removes the managed file.
+        await runDaemon([{ id: 2, file: path.join(FIXTURES, 'simple-counter.tsx'), npmrc: '' }]);
+        expect(fs.existsSync(path.join(TEST_CACHE, '.npmrc'))).toBe(false);
+      } finally {
+        registry.server.close();
+        fs.rmSync(dir, { recursive: true, force: true });
+      }
+    }, 130000);
+
+    it('keeps the managed npmrc private and leaves a hand-written one alone', async () => {
+      const npmrcPath = path.join(TEST_CACHE, '.npmrc');
+      const file = path.join(FIXTURES, 'simple-counter.tsx');
+      const npmrc = '# Managed by Terrarium\n//registry.example.com/:_authToken=secret\n';
+      try {
+        fs.writeFileSync(npmrcPath, '# Managed by Terrarium\n', { mode: 0o644 });
+        fs.chmodSync(npmrcPath, 0o644);
+        await runDaemon([{ id: 1, file, npmrc }]);
+        expect(fs.readFileSync(npmrcPath, 'utf-8')).toBe(npmrc);
+        if (process.platform !== 'win32') {
+          expect(fs.statSync(npmrcPath).mode & 0o777).toBe(0o600);
+        }
+
+        const handWritten = 'registry=https://mirror.example.com/\n';
+        fs.writeFileSync(npmrcPath, handWritten);
+        await runDaemon([{ id: 2, file, npmrc }]);
+        expect(fs.readFileSync(npmrcPath, 'utf-8')).toBe(handWritten);
+        await runDaemon([{ id: 3, file, npmrc: '' }]);
+        expect(fs.readFileSync(npmrcPath, 'utf-8')).toBe(handWritten);
+      } finally {
+        fs.rmSync(npmrcPath, { force: true });
+      }
+    }, 130000);
+
+    it('reports an unreachable private registry as a registry error', async () => {
+      const npmrc = '# Managed by Terrarium\n@acme:registry=http://127.0.0.1:1/\n';
+      const dir = fs.mkdtempSync(path.join(os.tmpdir(), 'terrarium-registry-'));
+      const file = path.join(dir, 'App.tsx');
+      fs.writeFileSync(file, "import { Button } from '@acme/ui';\nexport default function App() { return <Button />; }");
+
+      try {
+        const { lines } = await runDaemon([{ id: 1, file, npmrc }], {
+          npm_config_fetch_retries: '0',
+          npm_config_fetch_timeout: '1000',
+        });
+        const result = lines.find(line => line.id === 1 && line.ok !== undefined);
+        expect(result.ok).toBe(false);
+        expect(result.error.type).toBe('registry');
+        expect(result.error.message).toContain('127.0.0.1:1');
+      } finally {
+        fs.rmSync(path.join(TEST_CACHE, '.npmrc'), { force: true });
+        fs.rmSync(dir, { recursive: true, force: true });
+      }
+    }, 130000);
+  });
+
+  describe('install approval', () => {
+    // A package from the fixture registry whose postinstall script leaves a
+    // marker file behind.
+    async function withScriptedPackage(name, run) {
+      const marker = path.join(os.tmpdir(), `terrarium-postinstall-${name.split('/')[1]}-${Date.now()}`);
+      const registry = await startRegistry(name, '1.0.0', "export const scripted = 'scripted-marker';", {
+        scripts: { postinstall: `node -e "require('fs').writeFileSync('${marker.replace(/\\/g, '/')}', 'ran')"` },
+      });
+      const npmrc = `# Managed by Terrarium\n@terrarium-fixture:registry=${registry.url}\n`;
+      const dir = fs.mkdtempSync(path.join(os.tmpdir(), 'terrarium-approval-'));
+      const file = path.join(dir, 'App.tsx');
+      fs.writeFileSync(file, `import { scripted } from '${name}';\nexport default function App() { return <div>{scripted}</div>; }`);
+      try {
+        await run({ file, marker, npmrc });
+      } finally {
+        registry.server.close();
+        fs.rmSync(path.join(TEST_CACHE, '.npmrc'), { force: true });
+        fs.rmSync(dir, { recursive: true, force: true });
+        fs.rmSync(marker, { force: true });
+      }
+    }
+
+    it('asks before installing imported packages and stops when denied', async () => {
+      await withScriptedPackage('@terrarium-fixture/denied', async ({ file, npmrc }) => {
+        const asked = [];
+        const { lines } = await runDaemon([{ id: 1, file, npmrc }], {}, packages => {
+          asked.push(...packages);
+          return false;
+        });
+        const result = lines.find(line => line.id === 1 && line.ok !== undefined);
+        expect(asked).toEqual(['@terrarium-fixture/denied']);
+        expect(result.ok).toBe(false);
+        expect(result.error.type).toBe('not-approved');
+        expect(fs.existsSync(path.join(TEST_CACHE, 'node_modules', '@terrarium-fixture/denied'))).toBe(false);
+      });
+    }, 130000);
+
+    it('installs approved packages without running their scripts', async () => {
+      await withScriptedPackage('@terrarium-fixture/untrusted', async ({ file, marker, npmrc }) => {
+        const { lines } = await runDaemon([{ id: 1, file, npmrc }]);
+        const result = lines.find(line => line.id === 1 && line.ok !== undefined);
+        expect(result.ok).toBe(true);
+        expect(result.code).toContain('scripted-marker');
+        expect(fs.existsSync(marker)).toBe(false);
+      });
+    }, 130000);
+
+    it('runs install scripts for trusted packages', async () => {
+      await withScriptedPackage('@terrarium-fixture/trusted', async ({ file, marker, npmrc }) => {
+        const { lines } = await runDaemon([{ id: 1, file, npmrc, trusted: ['@terrarium-fixture/trusted'] }]);
+        const result = lines.find(line => line.id === 1 && line.ok !== undefined);
+        expect(result.ok).toBe(true);
+        expect(fs.readFileSync(marker, 'utf-8')).toBe('ran');
+      });
+    }, 130000);
+
+    it('denies pending approvals when stdin closes', async () => {
+      await withScriptedPackage('@terrarium-fixture/orphaned', async ({ file, npmrc }) => {
+        const child = spawn('node', [BUNDLER, '--daemon'], {
+          env: { ...process.env, TERRARIUM_CACHE_DIR: TEST_CACHE },
+        });
+        let stdout = '';
+        child.stdout.on('data', chunk => {
+          stdout += chunk;
+          if (stdout.includes('"approve"')) child.stdin.end();
+        });
+        child.stdin.write(JSON.stringify({ id: 1, file, npmrc }) + '\n');
+        const exitCode = await new Promise(resolve => child.on('exit', resolve));
+        const result = stdout.split('\n').filter(Boolean).map(line => JSON.parse(line))
+          .find(line => line.ok !== undefined);
+        expect(exitCode).toBe(0);
+        expect(result.error.type).toBe('not-approved');
+      });
+    }, 130000);
+  });
+
+  describe('pinned versions', () => {
+    it('installs a pinned version over the one in the cache', async () => {
+      const registry = await startRegistryVersions('@terrarium-pin/pkg', {
+        '1.0.0': "export const value = 'pinned-one';",
+        '2.0.0': "export const value = 'pinned-two';",
+      });
+      const npmrc = `# Managed by Terrarium\n@terrarium-pin:registry=${registry.url}\n`;
+      const dir = fs.mkdtempSync(path.join(os.tmpdir(), 'terrarium-pin-'));
+      const file = path.join(dir, 'App.tsx');
+      fs.writeFileSync(file, "import { value } from '@terrarium-pin/pkg';\nexport default function App() { return <div>{value}</div>; }");
+      try {
+        const { lines } = await runDaemon([
+          { id: 1, file, npmrc },
+        ]);
+        const latest = lines.find(line => line.id === 1 && line.ok !== undefined);
+        expect(latest.code).toContain('pinned-two');
+        expect(latest.packages['@terrarium-pin/pkg']).toBe('2.0.0');
+
+        const { lines: pinnedLines } = await runDaemon([
+          { id: 2, file, npmrc, pins: ['@terrarium-pin/pkg@1.0.0'] },
+        ]);
+        const pinned = pinnedLines.find(line => line.id === 2 && line.ok !== undefined);
+        expect(pinned.ok).toBe(true);
+        expect(pinned.code).toContain('pinned-one');
+        expect(pinned.packages['@terrarium-pin/pkg']).toBe('1.0.0');
+        // Already installed, so no approval was needed.
+        expect(pinnedLines.some(line => line.approve)).toBe(false);
+      } finally {
+        registry.server.close();
+        fs.rmSync(path.join(TEST_CACHE, '.npmrc'), { force: true });
+        fs.rmSync(dir, { recursive: true, force: true });
+      }
+    }, 130000);
+
+    const hasBun = (() => {
+      try {
+        execFileSync('bun', ['--version'], { stdio: 'ignore' });
+        return true;
+      } catch {
+        return false;
+      }
+    })();
+
+    // Bun gets a cache of its own, so the rest of the suite keeps npm's.
+    for (const manager of ['npm', 'bun']) {
+      it.skipIf(manager === 'bun' && !hasBun)(`installs conflicting pins into an isolated environment with ${manager}`, async () => {
+        const registry = await startRegistryVersions('@terrarium-env/pkg', {
+          '1.0.0': "export const value = 'isolated-one';",
+          '2.0.0': "export const value = 'shared-two';",
+        }, { peerDependencies: { '@terrarium-env/missing-peer': '1' } });
+        const cache = manager === 'npm' ? TEST_CACHE : fs.mkdtempSync(path.join(os.tmpdir(), 'terrarium-bun-cache-'));
+        const npmrc = `# Managed by Terrarium\n@terrarium-env:registry=${registry.url}\n`;
+        const dir = fs.mkdtempSync(path.join(os.tmpdir(), 'terrarium-env-'));
+        const file = path.join(dir, 'App.tsx');
+        fs.writeFileSync(file, "import { value } from '@terrarium-env/pkg';\nexport default function App() { return <div>{value}</div>; }");
+        const env = { dir: path.join(cache, 'envs', 'test-env'), packages: ['@terrarium-env/pkg@1.0.0'] };
+        const sharedVersion = () => JSON.parse(fs.readFileSync(
+          path.join(cache, 'node_modules', '@terrarium-env', 'pkg', 'package.json'), 'utf-8')).version;
+        const run = requests => runDaemon(
+          requests.map(request => ({ ...request, packageManager: manager })),
+          { TERRARIUM_CACHE_DIR: cache },
+        );
+        try {
+          const { lines } = await run([{ id: 1, file, npmrc }]);
+          expect(lines.find(line => line.id === 1 && line.ok !== undefined).ok).toBe(true);
+          expect(sharedVersion()).toBe('2.0.0');
+
+          const { lines: envLines } = await run([{ id: 2, file, npmrc, env }]);
+          const result = envLines.find(line => line.id === 2 && line.ok !== undefined);
+          expect(result.ok).toBe(true);
+          expect(result.code).toContain('isolated-one');
+          expect(result.packages['@terrarium-env/pkg']).toBe('1.0.0');
+          // The shared cache keeps its version, and peers stay out of the
+          // environment (this one doesn't even exist).
+          expect(sharedVersion()).toBe('2.0.0');
+          expect(fs.readFileSync(path.join(env.dir, '.terrarium-env'), 'utf-8')).toBe(manager);
+          if (manager === 'bun') {
+            expect(fs.readFileSync(path.join(env.dir, 'bunfig.toml'), 'utf-8')).toContain('peer = false');
+          }
+
+          // A second bundle reuses the environment.
+          const { lines: again } = await run([{ id: 3, file, npmrc, env }]);
+          expect(again.some(line => line.phase === 'installing')).toBe(false);
+          expect(again.find(line => line.id === 3 && line.ok !== undefined).code).toContain('isolated-one');
+        } finally {
+          registry.server.close();
+          fs.rmSync(path.join(cache, '.npmrc'), { force: true });
+          fs.rmSync(env.dir, { recursive: true, force: true });
+          fs.rmSync(dir, { recursive: true, force: true });
+          if (cache !== TEST_CACHE) fs.rmSync(cache, { recursive: true, force: true });
+        }
+      }, 300000);
+    }
+  });
+
+  describe('shared cache install lock', () => {
+    const LOCK = path.join(TEST_CACHE, '.install.lock');
+
+    async function withFixtureFiles(names, run) {
+      const registries = await Promise.all(names.map(name =>
+        startRegistry(name, '1.0.0', `export const value = '${name}-marker';`)));
+      const npmrc = '# Managed by Terrarium\n' + names
+        .map((name, i) => `${name.split('/')[0]}:registry=${registries[i].url}\n`).join('');
+      const dir = fs.mkdtempSync(path.join(os.tmpdir(), 'terrarium-lock-'));
+      const files = names.map((name, i) => {
+        const file = path.join(dir, `App${i}.tsx`);
+        fs.writeFileSync(file, `import { value } from '${name}';\nexport default function App() { return <div>{value}</div>; }`);
+        return file;
+      });
+      try {
+        await run({ files, npmrc });
+      } finally {
+        registries.forEach(registry => registry.server.close());
+        fs.rmSync(path.join(TEST_CACHE, '.npmrc'), { force: true });
+        fs.rmSync(LOCK, { recursive: true, force: true });
+        fs.rmSync(dir, { recursive: true, force: true });
+      }
+    }
+
+    it('waits for the lock and merges the installs queued meanwhile', async () => {
+      await withFixtureFiles(['@terrarium-lock-a/pkg', '@terrarium-lock-b/pkg'], async ({ files, npmrc }) => {
+        // Another live process (this one) holds the lock.
+        fs.mkdirSync(LOCK);
+        fs.writeFileSync(path.join(LOCK, 'pid'), String(process.pid));
+
+        const daemon = startDaemon();
+        daemon.send({ id: 1, file: files[0], npmrc });
+        daemon.send({ id: 2, file: files[1], npmrc });
+        const installing = id => line => line.id === id && line.phase === 'installing';
+        await Promise.all([daemon.waitFor(installing(1)), daemon.waitFor(installing(2))]);
+        expect(daemon.lines.some(line => line.ok !== undefined)).toBe(false);
+
+        fs.rmSync(LOCK, { recursive: true, force: true });
+        const [first, second] = await Promise.all([
+          daemon.waitFor(line => line.id === 1 && line.ok !== undefined),
+          daemon.waitFor(line => line.id === 2 && line.ok !== undefined),
+        ]);
+        await daemon.close();
+
+        expect(first.ok).toBe(true);
+        expect(second.ok).toBe(true);
+        const installs = daemon.stderr().split('\n').filter(line => line.includes('Installing with'));
+        expect(installs).toHaveLength(1);
+        expect(installs[0]).toContain('@terrarium-lock-a/pkg');
+        expect(installs[0]).toContain('@terrarium-lock-b/pkg');
+        expect(fs.existsSync(LOCK)).toBe(false);
+      });
+    }, 130000);
+
+    it('clears a lock left behind by a process that died', async () => {
+      await withFixtureFiles(['@terrarium-lock-c/pkg'], async ({ files, npmrc }) => {
+        const dead = spawn('node', ['-e', '']);
+        await new Promise(resolve => dead.on('exit', resolve));
+        fs.mkdirSync(LOCK);
+        fs.writeFileSync(path.join(LOCK, 'pid'), String(dead.pid));
+
+        const { lines } = await runDaemon([{ id: 1, file: files[0], npmrc }]);
+        const result = lines.find(line => line.id === 1 && line.ok !== undefined);
+        expect(result.ok).toBe(true);
+        expect(result.code).toContain('@terrarium-lock-c/pkg-marker');
+      });
+    }, 130000);
+  });
+
+  describe('cache maintenance', () => {
+    function runMaintenance(args) {
+      return new Promise(resolve => {
+        execFile('node', [BUNDLER, ...args], {
+          encoding: 'utf-8',
+          env: { ...process.env, TERRARIUM_CACHE_DIR: TEST_CACHE },
+        }, (error, stdout, stderr) => resolve({ exitCode: error ? error.code : 0, result: JSON.parse(stdout), stderr }));
+      });
+    }
+
+    it('refuses to uninstall base packages', async () => {
+      const { exitCode, result } = await runMaintenance(['--uninstall', 'react', 'left-pad']);
+      expect(exitCode).toBe(1);
+      expect(result.error).toBe(true);
+      expect(result.message).toContain('react is part of the base package set');
+    });
+
+    it('prunes packages that are not kept', async () => {
+      const registry = await startRegistry('@terrarium-prune/pkg', '1.0.0', "export const value = 'pruned';");
+      const npmrc = `# Managed by Terrarium\n@terrarium-prune:registry=${registry.url}\n`;
+      const dir = fs.mkdtempSync(path.join(os.tmpdir(), 'terrarium-prune-'));
+      const file = path.join(dir, 'App.tsx');
+      fs.writeFileSync(file, "import { value } from '@terrarium-prune/pkg';\nexport default function App() { return <div>{value}</div>; }");
+      try {
+        const { lines } = await runDaemon([{ id: 1, file, npmrc }]);
+        expect(lines.find(line => line.id === 1 && line.ok !== undefined).ok).toBe(true);
+
+        const kept = await runMaintenance(['--prune', '@terrarium-prune/pkg']);
+        expect(kept.result).toEqual({ ok: true, removed: [] });
+
+        const pruned = await runMaintenance(['--prune']);
+        expect(pruned.exitCode).toBe(0);
+        expect(pruned.result).toEqual({ ok: true, removed: ['@terrarium-prune/pkg'] });
+        expect(pruned.stderr).toContain('Removing @terrarium-prune/pkg');
+        expect(fs.existsSync(path.join(TEST_CACHE, 'node_modules', '@terrarium-prune', 'pkg'))).toBe(false);
+        expect(fs.existsSync(path.join(TEST_CACHE, 'node_modules', 'react'))).toBe(true);
+      } finally {
+        registry.server.close();
+        fs.rmSync(path.join(TEST_CACHE, '.npmrc'), { force: true });
+        fs.rmSync(dir, { recursive: true, force: true });
+      }
+    }, 130000);
+
+    it('locks installed versions and upgrades to exactly the previewed ones', async () => {
+      const registry = await startRegistryVersions('@terrarium-lock/pkg', {
+        '1.0.0': "export const value = 'locked-one';",
+        '2.0.0': "export const value = 'locked-two';",
+      });
+      const npmrc = `# Managed by Terrarium\n@terrarium-lock:registry=${registry.url}\n`;
+      const dir = fs.mkdtempSync(path.join(os.tmpdir(), 'terrarium-lock-'));
+      const file = path.join(dir, 'App.tsx');
+      fs.writeFileSync(file, "import { value } from '@terrarium-lock/pkg';\nexport default function App() { return <div>{value}</div>; }");
+      const lockfile = () => JSON.parse(fs.readFileSync(path.join(TEST_CACHE, 'terrarium-lock.json'), 'utf-8'));
+      try {
+        const { lines } = await runDaemon([{ id: 1, file, npmrc, pins: ['@terrarium-lock/pkg@1.0.0'] }]);
+        expect(lines.find(line => line.id === 1 && line.ok !== undefined).ok).toBe(true);
+        expect(lockfile().packages['@terrarium-lock/pkg']).toBe('1.0.0');
+        expect(lockfile().packages.react).toMatch(/^18\./);
+
+        const outdated = await runMaintenance(['--outdated']);
+        expect(outdated.result.ok).toBe(true);
+        expect(outdated.result.changes).toContainEqual({ name: '@terrarium-lock/pkg', from: '1.0.0', to: '2.0.0' });
+
+        const upgraded = await runMaintenance(['--upgrade', '@terrarium-lock/pkg@2.0.0']);
+        expect(upgraded.result).toEqual({ ok: true, upgraded: ['@terrarium-lock/pkg@2.0.0'] });
+        expect(lockfile().packages['@terrarium-lock/pkg']).toBe('2.0.0');
+      } finally {
+        registry.server.close();
+        await runMaintenance(['--uninstall', '@terrarium-lock/pkg']);
+        fs.rmSync(path.join(TEST_CACHE, '.npmrc'), { force: true });
+        fs.rmSync(dir, { recursive: true, force: true });
+      }
+    }, 130000);
+  });
+
   describe('isInstalled', () => {
     it('returns true for react after base packages are installed', () => {
       runBundler('simple-counter.tsx');
@@ -221,5 +829,16 @@ describe('bundler.mjs', () => {
       expect(parsed.type).toBe('network');
       expect(parsed.message).toContain('Network error: could not install');
     }, 130000);
+
+    it('bundles without the network once every import is installed', () => {
+      runBundler('simple-counter.tsx');
+      const result = runBundlerRaw('simple-counter.tsx', {
+        npm_config_registry: 'http://localhost:1',
+        npm_config_fetch_retries: '0',
+        npm_config_fetch_timeout: '1000',
+      });
+      expect(result.exitCode).toBe(0);
+      expect(result.stdout).toContain('createRoot');
+    }, 130000);
   });
 });
@@ -111,6 +111,28 @@ describe('renderer', () => {
       const banner = dom.window.document.getElementById('node-banner');
       expect(banner.classList.contains('visible')).toBe(false);
     });
+
+    it('check again re-detects Node.js and hides the banner once found', async () => {
+      let installed = false;
+      const { document, window } = createRendererEnv((command) => {
+        if (command === 'check_node') return Promise.reject('Node.js not found');
+        if (command === 'redetect_node') {
+          return installed
+            ? Promise.resolve({ supported: true, version: 'v20.0.0' })
+            : Promise.reject('Node.js not found');
+        }
+        return Promise.reject('No file loaded');
+      });
+      await new Promise(r => setTimeout(r, 0));
+      const banner = document.getElementById('node-banner');
+      expect(banner.classList.contains('visible')).toBe(true);
+
+      installed = true;
+      document.getElementById('node-banner-retry').click();
+      await new Promise(r => setTimeout(r, 0));
+      expect(window.__TAURI__.core.invoke).toHaveBeenCalledWith('redetect_node');
+      expect(banner.classList.contains('visible')).toBe(false);
+    });
   });
 
   describe('showError / hideError', () => {
@@ -181,6 +203,66 @@ describe('renderer', () => {
       expect(document.getElementById('error-detail').textContent)
         .toContain('Failed to install dependencies. Check your internet connection and try again.');
     });
+
+    it('keeps the registry message for private registry errors', () => {
+      const { document, emit } = createRendererEnv();
+      emit('bundle-error', {
+        type: 'registry',
+        message: 'Registry error: could not install @acme/ui from npm.acme.dev.',
+        errors: []
+      });
+      expect(document.getElementById('error-title').textContent).toBe('Registry Error');
+      expect(document.getElementById('error-detail').textContent).toContain('npm.acme.dev');
+    });
+
+    it('accepts typed error objects from the bundle-error event', () => {
+      const { document, emit } = createRendererEnv();
+      emit('bundle-error', {
+        type: 'syntax',
+        message: 'Build failed with 1 error',
+        errors: [
+          {
+            text: 'Expected ";" but found "{"',
+            location: { file: '/tmp/App.tsx', line: 3, column: 14, lineText: 'return {{{' }
+          }
+        ]
+      });
+      expect(document.getElementById('error-title').textContent).toBe('Syntax Error');
+      expect(document.getElementById('error-detail').textContent).toContain('/tmp/App.tsx:3:14');
+    });
+
+    it('titles errors raised on the Rust side', () => {
+      const { document, emit } = createRendererEnv();
+      emit('bundle-error', { type: 'timeout', message: 'Bundler timed out after 120 seconds', errors: [] });
+      expect(document.getElementById('error-title').textContent).toBe('Build Timed Out');
+      expect(document.getElementById('error-detail').textContent)
+        .toContain('Bundler timed out after 120 seconds');
+
+      emit('bundle-error', { type: 'node-missing', message: 'Node.js not found.', errors: [] });
+      expect(document.getElementById('error-title').textContent).toBe('Node.js Not Found');
+    });
+
+    it('ignores a typed "No file loaded" rejection from request_bundle', async () => {
+      const { document } = createRendererEnv((command) => {
+        if (command === 'request_bundle') {
+          return Promise.reject({ type: 'unknown', message: 'No file loaded', errors: [] });
+        }
+        return Promise.reject('No file loaded');
+      });
+      await new Promise(r => setTimeout(r, 0));
+      expect(document.getElementById('error-banner').classList.contains('visible')).toBe(false);
+    });
+
+    it('does not show superseded bundles as errors', async () => {
+      const { document } = createRendererEnv((command) => {
+        if (command === 'request_bundle') {
+          return Promise.reject({ type: 'cancelled', message: 'Superseded by a newer bundle', errors: [] });
+        }
+        return Promise.reject('No file loaded');
+      });
+      await new Promise(r => setTimeout(r, 0));
+      expect(document.getElementById('error-banner').classList.contains('visible')).toBe(false);
+    });
   });
 
   describe('toggleError', () => {
@@ -250,6 +332,221 @@ describe('renderer', () => {
     });
   });
 
+  describe('install approval', () => {
+    it('lists the packages and sends the answer back', () => {
+      const { document, emit, window } = createRendererEnv((command) => {
+        if (command === 'answer_install_approval') return Promise.resolve(true);
+        return Promise.reject('No file loaded');
+      });
+      emit('install-approval', { request: 5, packages: ['left-pad', '@acme/ui'] });
+
+      const prompt = document.getElementById('approval-prompt');
+      expect(prompt.classList.contains('visible')).toBe(true);
+      const listed = [...document.querySelectorAll('#approval-packages li')].map(li => li.textContent);
+      expect(listed).toEqual(['left-pad', '@acme/ui']);
+
+      document.getElementById('approval-allow').click();
+      expect(window.__TAURI__.core.invoke).toHaveBeenCalledWith(
+        'answer_install_approval', { request: 5, approved: true });
+      expect(prompt.classList.contains('visible')).toBe(false);
+    });
+
+    it('denies with the deny button', () => {
+      const { document, emit, window } = createRendererEnv((command) => {
+        if (command === 'answer_install_approval') return Promise.resolve(true);
+        return Promise.reject('No file loaded');
+      });
+      emit('install-approval', { request: 6, packages: ['reqeusts'] });
+      document.getElementById('approval-deny').click();
+      expect(window.__TAURI__.core.invoke).toHaveBeenCalledWith(
+        'answer_install_approval', { request: 6, approved: false });
+    });
+
+    it('queues a second question until the first is answered', () => {
+      const { document, emit, window } = createRendererEnv((command) => {
+        if (command === 'answer_install_approval') return Promise.resolve(true);
+        return Promise.reject('No file loaded');
+      });
+      emit('install-approval', { request: 1, packages: ['left-pad'] });
+      emit('install-approval', { request: 2, packages: ['lodash'] });
+      const listed = () => [...document.querySelectorAll('#approval-packages li')].map(li => li.textContent);
+      expect(listed()).toEqual(['left-pad']);
+
+      document.getElementById('approval-deny').click();
+      expect(window.__TAURI__.core.invoke).toHaveBeenCalledWith(
+        'answer_install_approval', { request: 1, approved: false });
+      expect(listed()).toEqual(['lodash']);
+      expect(document.getElementById('approval-prompt').classList.contains('visible')).toBe(true);
+
+      document.getElementById('approval-allow').click();
+      expect(window.__TAURI__.core.invoke).toHaveBeenCalledWith(
+        'answer_install_approval', { request: 2, approved: true });
+      expect(document.getElementById('approval-prompt').classList.contains('visible')).toBe(false);
+    });
+
+    it('hides the prompt when the asking bundle ends', () => {
+      const { document, emit, window } = createRendererEnv(() => Promise.reject('No file loaded'));
+      emit('install-approval', { request: 7, packages: ['left-pad'] });
+      emit('install-approval-closed', 8);
+      const prompt = document.getElementById('approval-prompt');
+      expect(prompt.classList.contains('visible')).toBe(true);
+      emit('install-approval-closed', 7);
+      expect(prompt.classList.contains('visible')).toBe(false);
+      document.getElementById('approval-allow').click();
+      expect(window.__TAURI__.core.invoke).not.toHaveBeenCalledWith(
+        'answer_install_approval', expect.anything());
+    });
+  });
+
+  describe('pinned dependency mismatches', () => {
+    it('lists mismatches and hides once they are gone', () => {
+      const { document, emit } = createRendererEnv();
+      emit('dependency-mismatches', [{ name: 'recharts', wanted: '2.12.7', found: '2.15.0' }]);
+      const warning = document.getElementById('pin-warning');
+      expect(warning.classList.contains('visible')).toBe(true);
+      expect(document.querySelector('#pin-warning-list li').textContent)
+        .toBe('recharts: pinned 2.12.7, got 2.15.0');
+
+      emit('dependency-mismatches', []);
+      expect(warning.classList.contains('visible')).toBe(false);
+    });
+  });
+
+  describe('project', () => {
+    it('shows the project a bundle resolved from', () => {
+      const { document, emit } = createRendererEnv();
+      emit('bundle-project', '/work/dashboard');
+      const badge = document.getElementById('project-badge');
+      expect(badge.classList.contains('visible')).toBe(true);
+      expect(badge.textContent).toBe('Packages from dashboard');
+      expect(badge.title).toBe('/work/dashboard');
+
+      emit('bundle-project', null);
+      expect(badge.classList.contains('visible')).toBe(false);
+    });
+  });
+
+  describe('installed packages', () => {
+    const packages = [
+      { name: 'left-pad', version: '1.3.0', size: 2048 },
+      { name: 'react', version: '18.3.1', size: 300000 },
+    ];
+
+    function depsEnv(overrides = {}) {
+      return createRendererEnv((command, payload) => {
+        if (overrides[command]) return overrides[command](payload);
+        if (command === 'deps_list') return Promise.resolve(packages);
+        return Promise.reject('No file loaded');
+      });
+    }
+
+    async function flush() {
+      for (let i = 0; i < 5; i++) await Promise.resolve();
+    }
+
+    it('lists packages with version and size from the menu', async () => {
+      const { document, emit } = depsEnv();
+      emit('menu-show-deps');
+      await flush();
+      expect(document.getElementById('deps-panel').classList.contains('visible')).toBe(true);
+      const rows = [...document.querySelectorAll('#deps-list li')];
+      expect(rows.map(li => li.querySelector('.deps-name').textContent)).toEqual(['left-pad', 'react']);
+      expect(rows[0].querySelector('.deps-meta').textContent).toBe('1.3.0 \u00b7 2.0 KB');
+    });
+
+    it('removes a package and shows progress', async () => {
+      const { document, emit, window } = depsEnv({ deps_remove: () => Promise.resolve(['left-pad']) });
+      emit('menu-show-deps');
+      await flush();
+      document.querySelector('#deps-list li button').click();
+      expect(window.__TAURI__.core.invoke).toHaveBeenCalledWith('deps_remove', { packages: ['left-pad'] });
+      emit('deps-progress', 'Removing left-pad...');
+      expect(document.getElementById('deps-status').textContent).toBe('Removing left-pad...');
+      await flush();
+      expect(document.getElementById('deps-status').textContent).toBe('Removed left-pad.');
+    });
+
+    it('asks for a second click before resetting the cache', async () => {
+      const { document, emit, window } = depsEnv({ deps_reseed: () => Promise.resolve() });
+      emit('menu-show-deps');
+      await flush();
+      const reset = document.getElementById('deps-reset');
+      reset.click();
+      expect(window.__TAURI__.core.invoke).not.toHaveBeenCalledWith('deps_reseed', undefined);
+      reset.click();
+      expect(window.__TAURI__.core.invoke).toHaveBeenCalledWith('deps_reseed', undefined);
+    });
+
+    it('previews upgrades before applying them', async () => {
+      const changes = [{ name: 'react', from: '18.2.0', to: '18.3.1', used_by: 2 }];
+      const { document, emit, window } = depsEnv({
+        deps_outdated: () => Promise.resolve(changes),
+        deps_upgrade: () => Promise.resolve(['react@18.3.1']),
+      });
+      emit('menu-upgrade-deps');
+      await flush();
+      expect(window.__TAURI__.core.invoke).not.toHaveBeenCalledWith('deps_upgrade', expect.anything());
+      const rows = [...document.querySelectorAll('#deps-upgrades li')];
+      expect(rows.map(li => li.textContent)).toEqual(['react 18.2.0 \u2192 18.3.1used by 2 files']);
+      expect(document.getElementById('deps-status').textContent).toBe('1 upgrade available.');
+      document.getElementById('deps-apply').click();
+      expect(window.__TAURI__.core.invoke).toHaveBeenCalledWith('deps_upgrade', { changes });
+      await flush();
+      expect(document.getElementById('deps-status').textContent).toBe('Upgraded react@18.3.1.');
+      expect(document.getElementById('deps-upgrades').hidden).toBe(true);
+    });
+  });
+
+  describe('storage', () => {
+    async function flush() {
+      for (let i = 0; i < 5; i++) await Promise.resolve();
+    }
+
+    it('imports into this window and lists the conflicts', async () => {
+      const { document, emit, window } = createRendererEnv(command => {
+        if (command === 'storage_import') {
+          return Promise.resolve({
+            mode: 'merge',
+            imported: 2,
+            conflicts: [{ namespace: '/a.tsx', key: 'count', existing: '1', imported: '3' }],
+          });
+        }
+        return Promise.reject('No file loaded');
+      });
+      emit('menu-show-storage');
+      expect(document.getElementById('storage-panel').classList.contains('visible')).toBe(true);
+      document.getElementById('storage-merge').click();
+      expect(window.__TAURI__.core.invoke).toHaveBeenCalledWith('storage_import', { shared: false, mode: 'merge' });
+      await flush();
+      expect(document.getElementById('storage-status').textContent).toBe('Imported 2 values. 1 key kept the stored value.');
+      const rows = [...document.querySelectorAll('#storage-conflicts li')];
+      expect(rows.map(li => li.textContent)).toEqual(['countkept stored value']);
+    });
+
+    it('says nothing when the export dialog is cancelled', async () => {
+      const { document, emit, window } = createRendererEnv(command =>
+        command === 'storage_export' ? Promise.resolve(null) : Promise.reject('No file loaded'));
+      emit('menu-show-storage');
+      document.getElementById('storage-shared-toggle').checked = true;
+      document.getElementById('storage-export').click();
+      expect(window.__TAURI__.core.invoke).toHaveBeenCalledWith('storage_export', { shared: true });
+      await flush();
+      expect(document.getElementById('storage-status').textContent).toBe('');
+      expect(document.getElementById('storage-export').disabled).toBe(false);
+    });
+
+    it('shows why an import was refused', async () => {
+      const { document, emit } = createRendererEnv(command =>
+        command === 'storage_import'
+          ? Promise.reject({ type: 'format', message: 'Not a Terrarium storage export.' })
+          : Promise.reject('No file loaded'));
+      emit('menu-show-storage');
+      document.getElementById('storage-replace').click();
+      await flush();
+      expect(document.getElementById('storage-status').textContent).toBe('Not a Terrarium storage export.');
+    });
+  });
+
   describe('drag and drop', () => {
     it('shows drop overlay on drag-enter', () => {
       const { document, emit } = createRendererEnv();
@@ -0,0 +1,87 @@
+import { describe, it, expect, vi } from 'vitest';
+import { JSDOM } from 'jsdom';
+import * as fs from 'fs';
+import * as path from 'path';
+
+const SHIM_SRC = fs.readFileSync(path.resolve('src-tauri/resources/storage-shim.js'), 'utf-8');
+
+function createShimEnv(invokeImpl, filePath = '/projects/counter.tsx') {
+  const dom = new JSDOM('<!DOCTYPE html><body></body>', {
+    url: 'http://localhost',
+    runScripts: 'dangerously',
+  });
+  const invoke = vi.fn((command, payload) => invokeImpl(command, payload));
+  dom.window.__TAURI__ = { core: { invoke } };
+  dom.window.__TERRARIUM_FILE_PATH__ = filePath;
+  dom.window.eval(SHIM_SRC);
+  return { storage: dom.window.storage, invoke };
+}
+
+describe('storage shim', () => {
+  it('scopes calls to the window file unless shared', async () => {
+    const { storage, invoke } = createShimEnv((command, { key, value, shared }) =>
+      Promise.resolve({ key, value, shared }));
+    expect(await storage.set('count', 3)).toEqual({ key: 'count', value: '3', shared: false });
+    expect(invoke).toHaveBeenCalledWith('storage_set', {
+      key: 'count', value: '3', filePath: '/projects/counter.tsx', shared: false,
+    });
+    await storage.set('scores', '[]', true);
+    expect(invoke).toHaveBeenLastCalledWith('storage_set', {
+      key: 'scores', value: '[]', filePath: '/projects/counter.tsx', shared: true,
+    });
+  });
+
+  it('resolves get to null for a missing key', async () => {
+    const { storage } = createShimEnv(() => Promise.resolve(null));
+    expect(await storage.get('missing')).toBeNull();
+    expect(await storage.getItem('missing')).toBeNull();
+  });
+
+  it('lists keys by prefix and deletes them', async () => {
+    const { storage, invoke } = createShimEnv((command, payload) => {
+      if (command === 'storage_list') return Promise.resolve({ keys: ['todo:1'], prefix: payload.prefix, shared: false });
+      return Promise.resolve({ key: payload.key, deleted: true, shared: false });
+    });
+    expect(await storage.list('todo:')).toEqual({ keys: ['todo:1'], prefix: 'todo:', shared: false });
+    await storage.list();
+    expect(invoke).toHaveBeenLastCalledWith('storage_list', { filePath: '/projects/counter.tsx', shared: false });
+    expect(await storage.delete('todo:1')).toEqual({ key: 'todo:1', deleted: true, shared: false });
+  });
+
+  it('keeps the getItem/setItem/removeItem API', async () => {
+    const { storage, invoke } = createShimEnv((command, { key }) =>
+      Promise.resolve(command === 'storage_get' ? { key, value: '7', shared: false } : null));
+    expect(await storage.getItem('count')).toBe('7');
+    expect(await storage.setItem('count', 8)).toBeUndefined();
+    expect(await storage.removeItem('count')).toBeUndefined();
+    expect(invoke).toHaveBeenLastCalledWith('storage_remove', {
+      key: 'count', filePath: '/projects/counter.tsx', shared: false,
+    });
+  });
+
+  it('rejects with a QuotaExceededError when storage is full', async () => {
+    const { storage } = createShimEnv(() => Promise.reject({ type: 'quota', message: 'Storage is limited to 10 keys, and all of them are in use.' }));
+    const err = await storage.set('count', 1).catch(e => e);
+    expect(err.name).toBe('QuotaExceededError');
+    expect(err.message).toContain('limited to 10 keys');
+    await expect(storage.setItem('count', 1)).rejects.toMatchObject({ name: 'QuotaExceededError' });
+  });
+
+  it('rejects access to another file with a SecurityError', async () => {
+    const { storage } = createShimEnv(() => Promise.reject({ type: 'forbidden', message: "This window can't access the storage of /projects/other.tsx." }));
+    await expect(storage.get('count')).rejects.toMatchObject({ name: 'SecurityError' });
+  });
+
+  it('rejects other failures with a StorageError', async () => {
+    const { storage } = createShimEnv(() => Promise.reject('database is locked'));
+    await expect(storage.get('count')).rejects.toMatchObject({ name: 'StorageError', message: 'database is locked' });
+  });
+
+  it('only reaches the shared namespace without a file', async () => {
+    const { storage, invoke } = createShimEnv(() => Promise.resolve({ keys: [], shared: true }), '');
+    expect(await storage.get('count')).toBeNull();
+    expect(invoke).not.toHaveBeenCalled();
+    await storage.list('', true);
+    expect(invoke).toHaveBeenCalledWith('storage_list', { filePath: '', shared: true });
+  });
+});